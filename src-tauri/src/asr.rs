@@ -1,66 +1,222 @@
-use sherpa_rs::transducer::{TransducerConfig, TransducerRecognizer};
-use std::thread;
-use std::time::Instant;
+use crate::audio_converter::{self, AudioSelection, AudioStream, ChannelMix, SampleSource};
+use crate::diarization::{self, DiarizationOptions, SpeakerLabelingSink};
+use crate::job_queue::{JobId, JobStatus};
+use crate::models::ModelInfo;
+use crate::preprocess::{self, PreprocessOptions};
+use crate::recognizer::{DecodingOptions, Hotword, Recognizer, SharedRecognizer};
+use crate::settings;
+use crate::sink::{ReviewMarkingSink, TauriEventSink, TimeOffsetSink, TranscriptionSink};
+use crate::vad::{self, SpeechSegment};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 use tauri::path::BaseDirectory;
-use tauri::{AppHandle, Emitter, Manager};
+use tauri::{AppHandle, Manager};
 
-const CHUNK_SECONDS: usize = 30;
-const OVERLAP_SECONDS: usize = 8;
-const REQUIRED_SAMPLE_RATE: u32 = 16_000;
-const FEATURE_DIM: i32 = 80;
+// これより短いチャンクでは文脈が足りず、認識精度が大きく落ちる
+const MIN_CHUNK_SECONDS: u32 = 5;
+// チャンネルごとの処理中に、ジョブのキャンセル要求を確認する間隔
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+pub const REQUIRED_SAMPLE_RATE: u32 = 16_000;
 
-#[derive(Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sentence {
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+    /// 話者分離を行った場合の話者 ID（0 始まり）
+    pub speaker: Option<u32>,
+    /// チャンネルごとに文字起こしした場合の元のチャンネル（0 始まり）
+    pub channel: Option<u32>,
+    /// 文を構成する単語（ライブ文字起こしなど単語の時刻がない場合は空）
+    pub words: Vec<Word>,
+    /// 文の信頼度（0〜1、単語の信頼度の平均）。認識エンジンが確率を返さない場合は `None`
+    pub confidence: Option<f32>,
+    /// 信頼度がしきい値を下回り、人による確認が必要な文かどうか
+    pub needs_review: bool,
+}
+
+/// 音声をどう区切って認識器に渡すか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct StartedPayload {
-    total_duration_ms: u64,
+pub enum Segmentation {
+    /// 一定長のチャンクを重複させながら切り出す
+    #[default]
+    FixedWindow,
+    /// VAD で検出した発話区間だけを切り出す（長い無音を飛ばし、重複デコードもしない）
+    Vad,
 }
 
-#[derive(Clone, serde::Serialize)]
+/// 固定長チャンクの長さと、隣のチャンクと重ねる長さ（秒）
+///
+/// 重ねた部分は両側のチャンクで認識し、それぞれ中央寄りの半分だけを使う。
+/// トークンの時刻を返さないモデルでは中央寄りの半分を選べないので、重ねずに区切る。
+/// VAD で区切る場合は `chunk_seconds` が発話区間の最大長になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct ProgressPayload {
-    text: String,
-    start_time_ms: u64,
-    end_time_ms: u64,
+pub struct ChunkOptions {
+    pub chunk_seconds: u32,
+    pub overlap_seconds: u32,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        ChunkOptions {
+            chunk_seconds: 30,
+            overlap_seconds: 8,
+        }
+    }
+}
+
+impl ChunkOptions {
+    /// チャンクが短すぎないか、重なりがチャンクより短いか、モデルの上限を超えないかを確認する
+    pub fn validate(&self, model: &ModelInfo) -> Result<(), String> {
+        if self.chunk_seconds < MIN_CHUNK_SECONDS {
+            return Err(format!(
+                "chunk length must be at least {} seconds: {}",
+                MIN_CHUNK_SECONDS, self.chunk_seconds
+            ));
+        }
+        if self.overlap_seconds >= self.chunk_seconds {
+            return Err(format!(
+                "overlap ({} seconds) must be shorter than the chunk ({} seconds)",
+                self.overlap_seconds, self.chunk_seconds
+            ));
+        }
+        if let Some(max) = model.max_chunk_seconds() {
+            if self.chunk_seconds > max {
+                return Err(format!(
+                    "model {} accepts chunks of at most {} seconds: {}",
+                    model.id, max, self.chunk_seconds
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// モデルで実際に使う設定。推定した時刻で重なりを取り除くと境界の単語が重複したり
+    /// 抜けたりするため、トークンの時刻を返さないモデルでは重なりをなくす
+    pub fn for_model(self, model: &ModelInfo) -> ChunkOptions {
+        if model.has_token_timestamps() || self.overlap_seconds == 0 {
+            return self;
+        }
+        log::info!(
+            "Model {} has no token timestamps; using non-overlapping chunks",
+            model.id
+        );
+        ChunkOptions {
+            overlap_seconds: 0,
+            ..self
+        }
+    }
 }
 
-#[derive(Clone, serde::Serialize)]
+/// ファイルのうち文字起こしする範囲（ミリ秒）。`end_ms` を省略するとファイルの終わりまで
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct FinishedPayload {
-    processing_time_ms: u64,
+pub struct TimeRange {
+    #[serde(default)]
+    pub start_ms: u64,
+    #[serde(default)]
+    pub end_ms: Option<u64>,
+}
+
+impl TimeRange {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.end_ms {
+            Some(end_ms) if end_ms <= self.start_ms => Err(format!(
+                "range end ({} ms) must be after its start ({} ms)",
+                end_ms, self.start_ms
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-struct Sentence {
-    start: f32,
-    end: f32,
-    text: String,
+/// ジョブごとの文字起こし設定
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranscriptionOptions {
+    /// 音声の分割方法。VAD は音声全体をメモリに読み込む（16 kHz の f32 で1時間あたり約 230 MB）
+    pub segmentation: Segmentation,
+    /// 指定されていれば、認識の前に話者分離を行って各文に話者を付ける（VAD と同じく音声全体を
+    /// メモリに読み込む）
+    pub diarization: Option<DiarizationOptions>,
+    /// 有効にすると `asr-progress` イベントに単語ごとの時刻を含める
+    pub word_timestamps: bool,
+    /// 指定すると、信頼度がこの値（0〜1）を下回る文を要確認とする
+    pub review_threshold: Option<f32>,
+    /// 認識で優先する語句（対応するトランスデューサーモデルでのみ使う）
+    pub hotwords: Vec<Hotword>,
+    /// トランスデューサーモデルのデコード設定。指定しなければ保存された設定を使う
+    pub decoding: Option<DecodingOptions>,
+    /// 固定長チャンクの設定。指定しなければモデルごとに保存された設定を使う
+    pub chunking: Option<ChunkOptions>,
+    /// 指定すると、その範囲だけをデコードして文字起こしする。文の時刻はファイル先頭からのまま
+    pub range: Option<TimeRange>,
+    /// 文字起こしするトラックと、チャンネルをモノラルにまとめる方法
+    pub audio: AudioSelection,
+    /// 有効にすると各チャンネルを別々に文字起こしし、文にチャンネルを付けて時刻順にまとめる
+    /// （左右に話者が分かれた通話録音など。話者分離やチャンネルの選択とは同時に使えない）
+    pub split_channels: bool,
+    /// リサンプリング後、認識の前に行う DC 除去・ハイパスフィルター・音量の正規化
+    /// （正規化だけは音声全体をメモリに読み込む。チャンネルごとの場合はチャンネルの数だけ）
+    pub preprocessing: PreprocessOptions,
 }
 
-#[derive(Debug, Clone)]
-struct Word {
-    start_time: f32,
-    end_time: f32,
-    text: String,
+// チャンク処理の結果（キャンセル時はそれまでに確定した文を保持）
+enum ProcessOutcome {
+    Completed(Vec<Sentence>),
+    Cancelled(Vec<Sentence>),
+}
+
+/// 単語と、その最初と最後のトークンの時刻（秒）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub start_time: f32,
+    pub end_time: f32,
+    pub text: String,
+    /// 単語の信頼度（0〜1、トークンの確率の幾何平均）。認識エンジンが確率を返さない場合は `None`
+    pub confidence: Option<f32>,
+}
+
+// トークンの対数確率の平均を確率に戻す（トークンの確率の幾何平均）。1つでも欠けていれば None
+pub(crate) fn confidence_from_log_probs(log_probs: &[Option<f32>]) -> Option<f32> {
+    if log_probs.is_empty() {
+        return None;
+    }
+    let sum: f32 = log_probs.iter().copied().sum::<Option<f32>>()?;
+    Some((sum / log_probs.len() as f32).exp())
+}
+
+// 単語の信頼度の平均。1つでも欠けていれば None
+fn sentence_confidence(words: &[Word]) -> Option<f32> {
+    if words.is_empty() {
+        return None;
+    }
+    let sum: f32 = words.iter().map(|w| w.confidence).sum::<Option<f32>>()?;
+    Some(sum / words.len() as f32)
 }
 
 // チャンクの中央部分の範囲計算
 fn central_range_for_chunk(
-    start: usize,
-    end: usize,
-    samples_len: usize,
+    is_first: bool,
+    is_last: bool,
+    chunk_len: usize,
     sample_rate: u32,
-    chunk_seconds: usize,
-    overlap_seconds: usize,
+    chunking: ChunkOptions,
 ) -> (f32, f32) {
-    let central_start = if start == 0 {
+    let central_start = if is_first {
         0.0
     } else {
-        overlap_seconds as f32 / 2.0
+        chunking.overlap_seconds as f32 / 2.0
     };
-    let central_end = if end == samples_len {
-        (end - start) as f32 / sample_rate as f32
+    let central_end = if is_last {
+        chunk_len as f32 / sample_rate as f32
     } else {
-        chunk_seconds as f32 - overlap_seconds as f32 / 2.0
+        chunking.chunk_seconds as f32 - chunking.overlap_seconds as f32 / 2.0
     };
     (central_start, central_end)
 }
@@ -69,49 +225,48 @@ fn central_range_for_chunk(
 fn filter_central_tokens(
     tokens: &[String],
     timestamps: &[f32],
+    log_probs: &[Option<f32>],
     chunk_start_sec: f32,
     central_start: f32,
     central_end: f32,
-) -> (Vec<String>, Vec<f32>) {
+) -> (Vec<String>, Vec<f32>, Vec<Option<f32>>) {
     let mut filtered_tokens = Vec::new();
     let mut filtered_timestamps = Vec::new();
-    for (token, &timestamp) in tokens.iter().zip(timestamps.iter()) {
+    let mut filtered_log_probs = Vec::new();
+    for ((token, &timestamp), &log_prob) in tokens.iter().zip(timestamps).zip(log_probs) {
         let abs_timestamp = timestamp + chunk_start_sec;
         let rel_timestamp = timestamp;
         if rel_timestamp >= central_start && rel_timestamp < central_end {
             filtered_tokens.push(token.clone());
             filtered_timestamps.push(abs_timestamp);
+            filtered_log_probs.push(log_prob);
         }
     }
-    (filtered_tokens, filtered_timestamps)
+    (filtered_tokens, filtered_timestamps, filtered_log_probs)
 }
 
 // チャンクから中央部分のトークンと絶対時間を抽出
 fn extract_central_tokens(
-    samples: &[f32],
-    start: usize,
-    end: usize,
+    chunk: &Chunk,
     sample_rate: u32,
-    chunk_seconds: usize,
-    overlap_seconds: usize,
-    recognizer: &mut TransducerRecognizer,
-) -> (Vec<String>, Vec<f32>) {
-    let chunk = &samples[start..end];
-    let result = recognizer.transcribe(sample_rate, chunk);
+    chunking: ChunkOptions,
+    recognizer: &mut dyn Recognizer,
+) -> (Vec<String>, Vec<f32>, Vec<Option<f32>>) {
+    let result = recognizer.transcribe(sample_rate, chunk.samples);
 
     let (central_start, central_end) = central_range_for_chunk(
-        start,
-        end,
-        samples.len(),
+        chunk.start == 0,
+        chunk.is_last,
+        chunk.samples.len(),
         sample_rate,
-        chunk_seconds,
-        overlap_seconds,
+        chunking,
     );
-    let chunk_start_sec = start as f32 / sample_rate as f32;
+    let chunk_start_sec = chunk.start as f32 / sample_rate as f32;
 
     filter_central_tokens(
         &result.tokens,
         &result.timestamps,
+        &result.token_log_probs(),
         chunk_start_sec,
         central_start,
         central_end,
@@ -119,7 +274,11 @@ fn extract_central_tokens(
 }
 
 // トークン列から単語列への変換（完全な単語のみ抽出、残りはトークンとして返す）
-fn tokens_to_words(tokens: &[String], timestamps: &[f32]) -> (Vec<Word>, Vec<String>, Vec<f32>) {
+fn tokens_to_words(
+    tokens: &[String],
+    timestamps: &[f32],
+    log_probs: &[Option<f32>],
+) -> (Vec<Word>, Vec<String>, Vec<f32>, Vec<Option<f32>>) {
     let mut words = Vec::new();
     let mut current_word = String::new();
     let mut word_start_time: Option<f32> = None;
@@ -127,13 +286,18 @@ fn tokens_to_words(tokens: &[String], timestamps: &[f32]) -> (Vec<Word>, Vec<Str
     let mut word_start_idx = 0;
 
     for (i, (token, &timestamp)) in tokens.iter().zip(timestamps.iter()).enumerate() {
-        // 空白で始まるトークンは新しい単語の開始
-        if token.starts_with(' ') && !current_word.is_empty() {
+        // 空白で始まるトークンは新しい単語の開始。
+        // 空白で区切らない言語（日本語・中国語）では1文字ずつを単語として扱う
+        let starts_new_word = token.starts_with(' ')
+            || token.chars().next().is_some_and(is_cjk)
+            || current_word.chars().last().is_some_and(is_cjk);
+        if starts_new_word && !current_word.is_empty() {
             // 現在の単語を完成させる
             words.push(Word {
                 start_time: word_start_time.unwrap_or(timestamp),
                 end_time: word_end_time,
                 text: current_word.trim().to_string(),
+                confidence: confidence_from_log_probs(&log_probs[word_start_idx..i]),
             });
             current_word.clear();
             word_start_time = None;
@@ -155,13 +319,19 @@ fn tokens_to_words(tokens: &[String], timestamps: &[f32]) -> (Vec<Word>, Vec<Str
 
     // 残りのトークン（未完成の単語）を抽出
     if current_word.is_empty() {
-        (words, Vec::new(), Vec::new()) // 全て完成
+        (words, Vec::new(), Vec::new(), Vec::new()) // 全て完成
     } else {
         let remaining_start_idx = word_start_idx;
         let remaining_tokens = tokens[remaining_start_idx..].to_vec();
         let remaining_timestamps = timestamps[remaining_start_idx..].to_vec();
+        let remaining_log_probs = log_probs[remaining_start_idx..].to_vec();
 
-        (words, remaining_tokens, remaining_timestamps)
+        (
+            words,
+            remaining_tokens,
+            remaining_timestamps,
+            remaining_log_probs,
+        )
     }
 }
 
@@ -186,18 +356,18 @@ fn extract_sentences_from_words(words: &[Word]) -> (Vec<Sentence>, Vec<Word>) {
         if is_word_sentence_end(&word.text, next_word.map(|w| w.text.as_str())) {
             let start = sentence_start.unwrap();
             let end = word.end_time;
-            let sentence_text = sentence_words
-                .iter()
-                .map(|w| w.text.as_str())
-                .collect::<Vec<_>>()
-                .join(" ");
+            let sentence_text = join_words(sentence_words.iter().map(|w| w.text.as_str()));
 
             sentences.push(Sentence {
                 start,
                 end,
                 text: sentence_text,
+                speaker: None,
+                channel: None,
+                confidence: sentence_confidence(&sentence_words),
+                needs_review: false,
+                words: std::mem::take(&mut sentence_words),
             });
-            sentence_words.clear();
             sentence_start = None;
         }
     }
@@ -206,13 +376,22 @@ fn extract_sentences_from_words(words: &[Word]) -> (Vec<Sentence>, Vec<Word>) {
 }
 
 // トークン列とタイムスタンプ列からセンテンス情報を抽出（単語ベースに変更）
+#[allow(clippy::type_complexity)]
 fn extract_sentences(
     carry_words: &[Word],
     tokens: &[String],
     timestamps: &[f32],
-) -> (Vec<Sentence>, Vec<Word>, Vec<String>, Vec<f32>) {
+    log_probs: &[Option<f32>],
+) -> (
+    Vec<Sentence>,
+    Vec<Word>,
+    Vec<String>,
+    Vec<f32>,
+    Vec<Option<f32>>,
+) {
     // トークンを単語に変換（完全な単語と残りのトークンを分離）
-    let (new_words, remaining_tokens, remaining_timestamps) = tokens_to_words(tokens, timestamps);
+    let (new_words, remaining_tokens, remaining_timestamps, remaining_log_probs) =
+        tokens_to_words(tokens, timestamps, log_probs);
 
     // carry_words と新しい単語を結合
     let mut combined_words = carry_words.to_vec();
@@ -226,6 +405,7 @@ fn extract_sentences(
         remaining_words,
         remaining_tokens,
         remaining_timestamps,
+        remaining_log_probs,
     )
 }
 
@@ -237,6 +417,11 @@ fn is_word_sentence_end(word: &str, next_word: Option<&str>) -> bool {
         return false;
     }
 
+    // 全角の文末記号は略語や小文字の続きと紛れないので、そのまま文末とする
+    if word.ends_with(['。', '？', '！']) {
+        return true;
+    }
+
     // 文末記号で終わる場合
     if word.ends_with('.') || word.ends_with('?') || word.ends_with('!') {
         // 次の単語が小文字で始まる場合は文末でない可能性が高い
@@ -254,229 +439,1307 @@ fn is_word_sentence_end(word: &str, next_word: Option<&str>) -> bool {
     false
 }
 
-fn chunk_indices(
-    total_samples: usize,
+// 単語を空白で区切らない文字（ひらがな・カタカナ・漢字・全角記号）
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF00}'..='\u{FFEF}')
+}
+
+// 単語を連結する。空白で区切らない文字同士の間には空白を入れない
+fn join_words<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let mut text = String::new();
+    for word in words {
+        let needs_space = match (text.chars().last(), word.chars().next()) {
+            (Some(prev), Some(next)) => !is_cjk(prev) && !is_cjk(next),
+            _ => false,
+        };
+        if needs_space {
+            text.push(' ');
+        }
+        text.push_str(word);
+    }
+    text
+}
+
+struct Chunk<'a> {
+    /// 音声全体の中での開始位置（サンプル）
+    start: usize,
+    samples: &'a [f32],
+    is_last: bool,
+}
+
+// 音源から読み込みながら、重複付きの固定長チャンクを順に切り出す。
+// 保持するのは最大で1チャンクと1ブロック分なので、入力の長さによらずメモリ使用量は一定。
+struct ChunkWindow<'s> {
+    source: &'s mut dyn SampleSource,
     chunk_size: usize,
     overlap_size: usize,
-) -> impl Iterator<Item = (usize, usize)> {
-    let mut start = 0;
-    std::iter::from_fn(move || {
-        if start >= total_samples {
-            None
-        } else {
-            let end = std::cmp::min(start + chunk_size, total_samples);
-            let current = (start, end);
-            start = if end == total_samples {
-                end
-            } else {
-                end - overlap_size
-            };
-            Some(current)
-        }
-    })
+    buffer: Vec<f32>,
+    buffer_start: usize,
+    exhausted: bool,
+    advance_before_next: bool,
+    finished: bool,
 }
 
-fn process_chunks(
-    samples: &[f32],
-    sample_rate: u32,
-    chunk_seconds: usize,
-    overlap_seconds: usize,
-    recognizer: &mut TransducerRecognizer,
-    app_handle: &AppHandle,
-) -> Vec<Sentence> {
-    let chunk_size = chunk_seconds * sample_rate as usize;
-    let overlap_size = overlap_seconds * sample_rate as usize;
-    let mut all_sentences: Vec<Sentence> = Vec::new();
-    let mut carry_words: Vec<Word> = Vec::new();
-    let mut carry_tokens: Vec<String> = Vec::new();
-    let mut carry_timestamps: Vec<f32> = Vec::new();
+impl<'s> ChunkWindow<'s> {
+    fn new(source: &'s mut dyn SampleSource, chunk_size: usize, overlap_size: usize) -> Self {
+        ChunkWindow {
+            source,
+            chunk_size,
+            overlap_size,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            exhausted: false,
+            advance_before_next: false,
+            finished: false,
+        }
+    }
 
-    for (start, end) in chunk_indices(samples.len(), chunk_size, overlap_size) {
-        let (local_tokens, local_timestamps) = extract_central_tokens(
-            samples,
-            start,
-            end,
-            sample_rate,
-            chunk_seconds,
-            overlap_seconds,
-            recognizer,
-        );
+    fn next_chunk(&mut self) -> Result<Option<Chunk<'_>>, String> {
+        if self.finished {
+            return Ok(None);
+        }
+        if self.advance_before_next {
+            let advance = self.chunk_size - self.overlap_size;
+            self.buffer.drain(..advance);
+            self.buffer_start += advance;
+            self.advance_before_next = false;
+        }
+
+        // 最終チャンクかどうかを判定するため、1チャンクより多く読めるか確認する
+        while !self.exhausted && self.buffer.len() <= self.chunk_size {
+            match self.source.next_block()? {
+                Some(block) => self.buffer.extend_from_slice(&block),
+                None => self.exhausted = true,
+            }
+        }
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
 
-        let mut tokens = carry_tokens.clone();
-        let mut timestamps = carry_timestamps.clone();
+        let is_last = self.buffer.len() <= self.chunk_size;
+        let len = self.buffer.len().min(self.chunk_size);
+        self.advance_before_next = !is_last;
+        self.finished = is_last;
+        Ok(Some(Chunk {
+            start: self.buffer_start,
+            samples: &self.buffer[..len],
+            is_last,
+        }))
+    }
+}
+
+// チャンクやセグメントをまたいで、未確定の単語とトークンを持ち越しながら文を組み立てる
+#[derive(Default)]
+struct SentenceBuilder {
+    carry_words: Vec<Word>,
+    carry_tokens: Vec<String>,
+    carry_timestamps: Vec<f32>,
+    carry_log_probs: Vec<Option<f32>>,
+}
+
+impl SentenceBuilder {
+    // 絶対時刻のトークン列と各トークンの対数確率を追加し、確定した文を返す
+    fn push(
+        &mut self,
+        local_tokens: Vec<String>,
+        local_timestamps: Vec<f32>,
+        local_log_probs: Vec<Option<f32>>,
+    ) -> Vec<Sentence> {
+        let mut tokens = std::mem::take(&mut self.carry_tokens);
+        let mut timestamps = std::mem::take(&mut self.carry_timestamps);
+        let mut log_probs = std::mem::take(&mut self.carry_log_probs);
         tokens.extend(local_tokens);
         timestamps.extend(local_timestamps);
+        log_probs.extend(local_log_probs);
 
-        let (sentences, remain_words, remain_tokens, remain_timestamps) =
-            extract_sentences(&carry_words, &tokens, &timestamps);
-
-        for sentence in &sentences {
-            app_handle
-                .emit(
-                    "asr-progress",
-                    ProgressPayload {
-                        text: sentence.text.clone(),
-                        start_time_ms: (sentence.start * 1000.0) as u64,
-                        end_time_ms: (sentence.end * 1000.0) as u64,
-                    },
-                )
-                .unwrap();
-        }
-        all_sentences.extend(sentences.clone());
+        let (sentences, remain_words, remain_tokens, remain_timestamps, remain_log_probs) =
+            extract_sentences(&self.carry_words, &tokens, &timestamps, &log_probs);
 
-        carry_words = remain_words;
-        carry_tokens = remain_tokens;
-        carry_timestamps = remain_timestamps;
+        self.carry_words = remain_words;
+        self.carry_tokens = remain_tokens;
+        self.carry_timestamps = remain_timestamps;
+        self.carry_log_probs = remain_log_probs;
+        sentences
     }
 
-    // 最後に未確定分を処理
-    if !carry_words.is_empty() || !carry_tokens.is_empty() {
+    // 最後に未確定分を1つの文として確定させる
+    fn finish(mut self) -> Option<Sentence> {
+        if self.carry_words.is_empty() && self.carry_tokens.is_empty() {
+            return None;
+        }
+
         // carry_tokens があれば単語として carry_words に追加
-        if !carry_tokens.is_empty() {
-            let tokens_text = carry_tokens.join("").trim().to_string();
-            let start_time = carry_timestamps.first().copied().unwrap_or(0.0);
-            let end_time = carry_timestamps.last().copied().unwrap_or(0.0);
+        if !self.carry_tokens.is_empty() {
+            let tokens_text = self.carry_tokens.join("").trim().to_string();
+            let start_time = self.carry_timestamps.first().copied().unwrap_or(0.0);
+            let end_time = self.carry_timestamps.last().copied().unwrap_or(0.0);
 
-            carry_words.push(Word {
+            self.carry_words.push(Word {
                 start_time,
                 end_time,
                 text: tokens_text,
+                confidence: confidence_from_log_probs(&self.carry_log_probs),
             });
         }
 
         // carry_words全体を文として処理
-        let sentence_text = carry_words
-            .iter()
-            .map(|w| w.text.as_str())
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        let start = carry_words.first().map(|w| w.start_time).unwrap_or(0.0);
-        let end = carry_words.last().map(|w| w.end_time).unwrap_or(0.0);
-
-        app_handle
-            .emit(
-                "asr-progress",
-                ProgressPayload {
-                    text: sentence_text.clone(),
-                    start_time_ms: (start * 1000.0) as u64,
-                    end_time_ms: (end * 1000.0) as u64,
-                },
-            )
-            .unwrap();
+        let sentence_text = join_words(self.carry_words.iter().map(|w| w.text.as_str()));
+
+        let start = self
+            .carry_words
+            .first()
+            .map(|w| w.start_time)
+            .unwrap_or(0.0);
+        let end = self.carry_words.last().map(|w| w.end_time).unwrap_or(0.0);
 
-        all_sentences.push(Sentence {
+        Some(Sentence {
             start,
             end,
             text: sentence_text,
-        });
+            speaker: None,
+            channel: None,
+            confidence: sentence_confidence(&self.carry_words),
+            needs_review: false,
+            words: self.carry_words,
+        })
+    }
+}
+
+fn process_chunks(
+    source: &mut dyn SampleSource,
+    sample_rate: u32,
+    chunking: ChunkOptions,
+    recognizer: &mut dyn Recognizer,
+    sink: &mut dyn TranscriptionSink,
+    cancel_requested: &AtomicBool,
+) -> Result<ProcessOutcome, String> {
+    let chunk_size = chunking.chunk_seconds as usize * sample_rate as usize;
+    let overlap_size = chunking.overlap_seconds as usize * sample_rate as usize;
+    let mut all_sentences: Vec<Sentence> = Vec::new();
+    let mut builder = SentenceBuilder::default();
+    let mut window = ChunkWindow::new(source, chunk_size, overlap_size);
+
+    loop {
+        // チャンクの合間でキャンセル要求を確認する
+        if cancel_requested.load(Ordering::SeqCst) {
+            return Ok(ProcessOutcome::Cancelled(all_sentences));
+        }
+
+        let Some(chunk) = window.next_chunk()? else {
+            break;
+        };
+        let (local_tokens, local_timestamps, local_log_probs) =
+            extract_central_tokens(&chunk, sample_rate, chunking, recognizer);
+
+        for sentence in builder.push(local_tokens, local_timestamps, local_log_probs) {
+            sink.sentence(&sentence);
+            all_sentences.push(sentence);
+        }
+    }
+
+    if let Some(sentence) = builder.finish() {
+        sink.sentence(&sentence);
+        all_sentences.push(sentence);
     }
 
-    all_sentences
+    Ok(ProcessOutcome::Completed(all_sentences))
 }
 
-#[tauri::command]
-pub fn start_asr_process(app_handle: AppHandle, file_path: String) {
-    log::info!("Starting ASR process for: {}", file_path);
+// VAD で検出した発話区間だけを認識する（区間は重ならないので重複部分の除去は不要）
+fn process_segments(
+    samples: &[f32],
+    sample_rate: u32,
+    segments: &[SpeechSegment],
+    recognizer: &mut dyn Recognizer,
+    sink: &mut dyn TranscriptionSink,
+    cancel_requested: &AtomicBool,
+) -> ProcessOutcome {
+    let mut all_sentences: Vec<Sentence> = Vec::new();
+    let mut builder = SentenceBuilder::default();
 
-    thread::spawn(move || {
-        let start_t = Instant::now();
+    for segment in segments {
+        if cancel_requested.load(Ordering::SeqCst) {
+            return ProcessOutcome::Cancelled(all_sentences);
+        }
 
-        let samples = match crate::audio_converter::convert_to_mono_f32_16khz(&file_path) {
-            Ok(data) => data,
-            Err(e) => {
-                log::error!("Failed to read or convert audio file: {}", e);
-                app_handle
-                    .emit(
-                        "asr-error",
-                        format!("音声ファイルの読み込みまたは変換に失敗しました: {}", e),
-                    )
-                    .unwrap();
-                return;
+        let result = recognizer.transcribe(sample_rate, &samples[segment.start..segment.end]);
+        let segment_start_sec = segment.start as f32 / sample_rate as f32;
+        let timestamps = result
+            .timestamps
+            .iter()
+            .map(|t| t + segment_start_sec)
+            .collect();
+        let log_probs = result.token_log_probs();
+
+        for sentence in builder.push(result.tokens, timestamps, log_probs) {
+            sink.sentence(&sentence);
+            all_sentences.push(sentence);
+        }
+    }
+
+    if let Some(sentence) = builder.finish() {
+        sink.sentence(&sentence);
+        all_sentences.push(sentence);
+    }
+
+    ProcessOutcome::Completed(all_sentences)
+}
+
+// 範囲を指定した場合はその範囲だけを読むストリームを開く
+fn open_stream(
+    file_path: &str,
+    audio: &AudioSelection,
+    range: Option<TimeRange>,
+    preprocessing: &PreprocessOptions,
+) -> Result<AudioStream, Box<dyn std::error::Error>> {
+    let mut stream = AudioStream::open_with(file_path, audio)?;
+    if let Some(range) = range {
+        stream.set_range(range.start_ms, range.end_ms)?;
+    }
+    stream.set_preprocessing(preprocessing);
+    Ok(stream)
+}
+
+// 音声全体を読み込み、指定されていれば音量を正規化する
+fn read_signal(
+    stream: &mut AudioStream,
+    preprocessing: &PreprocessOptions,
+) -> Result<Vec<f32>, String> {
+    let mut samples = stream.read_all()?;
+    if let Some(normalization) = &preprocessing.normalization {
+        preprocess::normalize(&mut samples, normalization, REQUIRED_SAMPLE_RATE);
+    }
+    Ok(samples)
+}
+
+// 認識する音声の読み方
+enum AudioInput {
+    Stream(Box<AudioStream>),
+    // チャンネルごとに文字起こしする場合は、各チャンネルのスレッドがそれぞれストリームを開く
+    Channels { count: usize, duration_ms: u64 },
+}
+
+// チャンネルごとに文字起こしできるか確認し、選択したトラックのチャンネル数と
+// 文字起こしする長さ（分からなければ0）を持つ入力を返す
+fn split_channels_input(
+    file_path: &str,
+    options: &TranscriptionOptions,
+) -> Result<AudioInput, String> {
+    if options.diarization.is_some() {
+        return Err("cannot be combined with speaker diarization".to_string());
+    }
+    if options.audio.channel_mix != ChannelMix::Average {
+        return Err("cannot be combined with channel selection or mixing".to_string());
+    }
+    let tracks = audio_converter::list_tracks(file_path).map_err(|e| e.to_string())?;
+    let track = match options.audio.track_id {
+        Some(id) => tracks.iter().find(|t| t.id == id),
+        None => tracks.iter().find(|t| t.is_default),
+    }
+    .ok_or("audio track not found")?;
+    let count = track
+        .channels
+        .ok_or_else(|| "the number of channels is unknown".to_string())?;
+    // AudioStream::estimated_duration_ms と同じく、範囲を指定した場合はその長さにする
+    let end_ms = match (track.duration_ms, options.range.and_then(|r| r.end_ms)) {
+        (Some(total), Some(end)) => Some(total.min(end)),
+        (total, end) => total.or(end),
+    };
+    let start_ms = options.range.map_or(0, |r| r.start_ms);
+    Ok(AudioInput::Channels {
+        count,
+        duration_ms: end_ms.map_or(0, |end| end.saturating_sub(start_ms)),
+    })
+}
+
+// 音声の読み込み以降の認識処理に共通の設定（チャンネルごとの処理ではスレッド間で共有する）
+struct RecognitionContext<'a> {
+    segmentation: Segmentation,
+    chunking: ChunkOptions,
+    silero_model_path: Option<PathBuf>,
+    recognizer: &'a SharedRecognizer,
+    cancel_requested: &'a AtomicBool,
+}
+
+// 区切り方に応じて音声を認識する。`samples` は読み込み済みの音声全体（VAD では必須）
+fn recognize(
+    context: &RecognitionContext,
+    stream: &mut AudioStream,
+    samples: Option<&[f32]>,
+    sink: &mut dyn TranscriptionSink,
+) -> Result<ProcessOutcome, String> {
+    let sample_rate = REQUIRED_SAMPLE_RATE;
+    match context.segmentation {
+        Segmentation::FixedWindow => {
+            // 話者分離のために読み込み済みならそれを使い、そうでなければファイルから読み進める
+            let mut buffered;
+            let source: &mut dyn SampleSource = match samples {
+                Some(samples) => {
+                    buffered = samples.chunks(sample_rate as usize);
+                    &mut buffered
+                }
+                None => stream,
+            };
+            process_chunks(
+                source,
+                sample_rate,
+                context.chunking,
+                &mut &*context.recognizer,
+                sink,
+                context.cancel_requested,
+            )
+        }
+        Segmentation::Vad => {
+            let read;
+            let whole_signal = match samples {
+                Some(samples) => samples,
+                None => {
+                    read = stream.read_all()?;
+                    &read
+                }
+            };
+            let segments = vad::detect_speech(
+                whole_signal,
+                sample_rate,
+                context.chunking.chunk_seconds as usize,
+                context.silero_model_path.as_deref(),
+            );
+            log::info!("Detected {} speech segments", segments.len());
+            Ok(process_segments(
+                whole_signal,
+                sample_rate,
+                &segments,
+                &mut &*context.recognizer,
+                sink,
+                context.cancel_requested,
+            ))
+        }
+    }
+}
+
+// 各チャンネルの文を開始時刻順に並べて出力する。
+// チャンネルごとの文は開始時刻順に届くので、他のチャンネルからそれより前に始まる文が
+// もう届かないと分かった文から出力する
+#[derive(Default)]
+struct ChannelMerger {
+    pending: Vec<VecDeque<Sentence>>,
+    // 各チャンネルで最後に届いた文の開始時刻（以降の文はこれより前に始まらない）
+    last_start: Vec<f32>,
+    done: Vec<bool>,
+}
+
+impl ChannelMerger {
+    fn new(channels: usize) -> Self {
+        ChannelMerger {
+            pending: vec![VecDeque::new(); channels],
+            last_start: vec![0.0; channels],
+            done: vec![false; channels],
+        }
+    }
+
+    fn push(&mut self, channel: usize, sentence: Sentence) {
+        self.last_start[channel] = sentence.start;
+        self.pending[channel].push_back(sentence);
+    }
+
+    fn finish(&mut self, channel: usize) {
+        self.done[channel] = true;
+    }
+
+    // 出力してよい文を開始時刻順に取り出す
+    fn pop_ready(&mut self) -> Vec<Sentence> {
+        let mut ready = Vec::new();
+        while let Some((channel, start)) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter_map(|(channel, queue)| queue.front().map(|s| (channel, s.start)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+        {
+            let others_caught_up = (0..self.pending.len()).all(|other| {
+                other == channel
+                    || self.done[other]
+                    || !self.pending[other].is_empty()
+                    || self.last_start[other] >= start
+            });
+            if !others_caught_up {
+                break;
             }
+            ready.extend(self.pending[channel].pop_front());
+        }
+        ready
+    }
+
+    // キャンセル時などに、残っている文をすべて開始時刻順に取り出す
+    fn drain(&mut self) -> Vec<Sentence> {
+        self.done.iter_mut().for_each(|done| *done = true);
+        self.pop_ready()
+    }
+}
+
+enum ChannelEvent {
+    Sentence(usize, Sentence),
+    Done(usize, Result<ProcessOutcome, String>),
+}
+
+// 認識した文にチャンネルを付けて、まとめ役のスレッドへ送る
+struct ChannelSink {
+    channel: usize,
+    sender: mpsc::Sender<ChannelEvent>,
+}
+
+impl TranscriptionSink for ChannelSink {
+    fn started(&mut self, _total_duration_ms: u64) {}
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        let labeled = Sentence {
+            channel: Some(self.channel as u32),
+            // チャンネルごとに話者が分かれている前提で、チャンネルを話者としても扱う
+            speaker: Some(self.channel as u32),
+            ..sentence.clone()
         };
-        let sample_rate = REQUIRED_SAMPLE_RATE;
+        // 受け取り側はすべてのスレッドが終わるまで待つので、送信は失敗しない
+        let _ = self
+            .sender
+            .send(ChannelEvent::Sentence(self.channel, labeled));
+    }
+
+    fn finished(&mut self, _sentences: &[Sentence], _processing_time_ms: u64) {}
+
+    fn cancelled(&mut self, _sentences: &[Sentence], _processing_time_ms: u64) {}
+
+    fn error(&mut self, _message: &str) {}
+}
+
+// 各チャンネルを別々のスレッドで文字起こしし、文を開始時刻順にまとめて `sink` へ渡す。
+// 認識器はチャンクごとにロックされるので、チャンネルはチャンク単位で交互に認識される
+fn process_channels(
+    context: &RecognitionContext,
+    file_path: &str,
+    options: &TranscriptionOptions,
+    channels: usize,
+    sink: &mut dyn TranscriptionSink,
+) -> Result<ProcessOutcome, String> {
+    log::info!("Transcribing {} channels separately", channels);
+    // どれかのチャンネルが失敗したら、結果を捨てることになる残りのチャンネルも止める。
+    // 呼び出し元のキャンセル要求のフラグは複数のファイルで共有されうる（CLI）ので、
+    // チャンネル用のフラグを別に用意し、キャンセル要求はそちらへ転送する
+    let stop_requested = AtomicBool::new(false);
+    let channel_context = RecognitionContext {
+        segmentation: context.segmentation,
+        chunking: context.chunking,
+        silero_model_path: context.silero_model_path.clone(),
+        recognizer: context.recognizer,
+        cancel_requested: &stop_requested,
+    };
+    let cancel_requested = context.cancel_requested;
+    let context = &channel_context;
+    let (sender, receiver) = mpsc::channel();
+    let mut merger = ChannelMerger::new(channels);
+    let mut sentences = Vec::new();
+    let mut failure = None;
+    let mut cancelled = false;
+
+    std::thread::scope(|scope| {
+        for channel in 0..channels {
+            let sender = sender.clone();
+            scope.spawn(move || {
+                let audio = AudioSelection {
+                    track_id: options.audio.track_id,
+                    channel_mix: ChannelMix::Single { channel },
+                };
+                let mut channel_sink = ChannelSink {
+                    channel,
+                    sender: sender.clone(),
+                };
+                let result = open_stream(file_path, &audio, options.range, &options.preprocessing)
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut stream| match options.preprocessing.normalization {
+                        // 正規化には音声全体が必要なので、先に読み込む
+                        Some(_) => {
+                            let samples = read_signal(&mut stream, &options.preprocessing)?;
+                            recognize(context, &mut stream, Some(&samples), &mut channel_sink)
+                        }
+                        None => recognize(context, &mut stream, None, &mut channel_sink),
+                    });
+                let _ = sender.send(ChannelEvent::Done(channel, result));
+            });
+        }
+        drop(sender);
+
+        loop {
+            if cancel_requested.load(Ordering::SeqCst) {
+                stop_requested.store(true, Ordering::SeqCst);
+            }
+            let event = match receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            match event {
+                ChannelEvent::Sentence(channel, sentence) => merger.push(channel, sentence),
+                ChannelEvent::Done(channel, result) => {
+                    merger.finish(channel);
+                    match result {
+                        Ok(ProcessOutcome::Completed(_)) => {}
+                        Ok(ProcessOutcome::Cancelled(_)) => cancelled = true,
+                        Err(e) => {
+                            log::error!("Channel {} failed: {}", channel, e);
+                            stop_requested.store(true, Ordering::SeqCst);
+                            failure.get_or_insert(format!("channel {}: {}", channel, e));
+                        }
+                    }
+                }
+            }
+            for sentence in merger.pop_ready() {
+                sink.sentence(&sentence);
+                sentences.push(sentence);
+            }
+        }
+    });
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    // すべてのチャンネルが終わっているので、ここで残りが出ることはない
+    for sentence in merger.drain() {
+        sink.sentence(&sentence);
+        sentences.push(sentence);
+    }
+    Ok(if cancelled {
+        ProcessOutcome::Cancelled(sentences)
+    } else {
+        ProcessOutcome::Completed(sentences)
+    })
+}
+
+/// 音声ファイル1つを最後まで文字起こしし、終了時のステータスを返す
+///
+/// 進捗はすべて `sink` に通知されるため、Tauri を介さずに利用できる。
+pub fn transcribe_file(
+    file_path: &str,
+    model: &'static ModelInfo,
+    model_dir_path: &Path,
+    options: &TranscriptionOptions,
+    recognizer: &SharedRecognizer,
+    cancel_requested: &AtomicBool,
+    sink: &mut dyn TranscriptionSink,
+) -> JobStatus {
+    let start_t = Instant::now();
 
-        let model_dir_path = match app_handle.path().resolve(
-            "models/sherpa-onnx-nemo-parakeet-tdt-0.6b-v2-int8",
-            BaseDirectory::AppLocalData,
+    let chunking = options.chunking.unwrap_or_default();
+    if let Err(e) = chunking.validate(model) {
+        sink.error(&format!("チャンクの設定が正しくありません: {}", e));
+        return JobStatus::Failed;
+    }
+    let chunking = chunking.for_model(model);
+
+    if let Err(e) = options.preprocessing.validate(REQUIRED_SAMPLE_RATE) {
+        sink.error(&format!("前処理の設定が正しくありません: {}", e));
+        return JobStatus::Failed;
+    }
+
+    let mut input = if options.split_channels {
+        match split_channels_input(file_path, options) {
+            Ok(input) => input,
+            Err(e) => {
+                sink.error(&format!("チャンネルごとの文字起こしができません: {}", e));
+                return JobStatus::Failed;
+            }
+        }
+    } else {
+        match open_stream(
+            file_path,
+            &options.audio,
+            options.range,
+            &options.preprocessing,
         ) {
-            Ok(path) => path,
+            Ok(stream) => AudioInput::Stream(Box::new(stream)),
             Err(e) => {
-                let err_msg = format!("モデルディレクトリの解決に失敗しました: {}", e);
-                log::error!("{}", err_msg);
-                app_handle.emit("asr-error", err_msg).unwrap();
-                return;
+                sink.error(&format!(
+                    "音声ファイルの読み込みまたは変換に失敗しました: {}",
+                    e
+                ));
+                return JobStatus::Failed;
             }
-        };
+        }
+    };
+    // VAD・話者分離・音量の正規化は音声全体を見る必要があるので、その場合だけ全体をメモリに
+    // 読み込む。それ以外は認識しながら少しずつデコードする。チャンネルごとの場合は各チャンネルを別に読む
+    let needs_whole_signal = options.segmentation == Segmentation::Vad
+        || options.diarization.is_some()
+        || options.preprocessing.normalization.is_some();
+    let samples = match &mut input {
+        AudioInput::Stream(stream) if needs_whole_signal => {
+            match read_signal(stream, &options.preprocessing) {
+                Ok(samples) => Some(samples),
+                Err(e) => {
+                    sink.error(&format!(
+                        "音声ファイルの読み込みまたは変換に失敗しました: {}",
+                        e
+                    ));
+                    return JobStatus::Failed;
+                }
+            }
+        }
+        _ => None,
+    };
+    let sample_rate = REQUIRED_SAMPLE_RATE;
 
-        let cpus = num_cpus::get();
-        // NOTE: CPU数が多いからといってスレッド数を増やしすぎると逆にパフォーマンスが落ちる。
-        let num_threads = std::cmp::max(1, std::cmp::min(6, cpus / 2));
-        let config = TransducerConfig {
-            decoder: model_dir_path
-                .join("decoder.int8.onnx")
-                .to_str()
-                .unwrap()
-                .to_string(),
-            encoder: model_dir_path
-                .join("encoder.int8.onnx")
-                .to_str()
-                .unwrap()
-                .to_string(),
-            joiner: model_dir_path
-                .join("joiner.int8.onnx")
-                .to_str()
-                .unwrap()
-                .to_string(),
-            tokens: model_dir_path
-                .join("tokens.txt")
-                .to_str()
-                .unwrap()
-                .to_string(),
-            num_threads: num_threads as i32,
-            sample_rate: REQUIRED_SAMPLE_RATE as i32,
-            feature_dim: FEATURE_DIM,
-            debug: true,
-            model_type: "nemo_transducer".to_string(),
-            ..Default::default()
-        };
+    // 変換に時間がかかるため、認識器を読み込む前にもキャンセル要求を確認する
+    if cancel_requested.load(Ordering::SeqCst) {
+        log::info!("ASR cancelled before recognition: {}", file_path);
+        sink.cancelled(&[], start_t.elapsed().as_millis() as u64);
+        return JobStatus::Cancelled;
+    }
 
-        let mut recognizer = match TransducerRecognizer::new(config) {
-            Ok(r) => r,
-            Err(e) => {
-                log::error!("Failed to create recognizer: {}", e);
-                app_handle
-                    .emit("asr-error", format!("認識器の初期化に失敗しました: {}", e))
-                    .unwrap();
-                return;
+    if let Err(e) = recognizer.ensure_loaded(
+        model,
+        model_dir_path,
+        &options.decoding.unwrap_or_default(),
+        &options.hotwords,
+    ) {
+        sink.error(&format!("認識器の初期化に失敗しました: {}", e));
+        return JobStatus::Failed;
+    }
+
+    let total_duration_ms = match (&samples, &input) {
+        (Some(samples), _) => (samples.len() as f64 / sample_rate as f64 * 1000.0) as u64,
+        (None, AudioInput::Stream(stream)) => stream.estimated_duration_ms().unwrap_or(0),
+        (None, AudioInput::Channels { duration_ms, .. }) => *duration_ms,
+    };
+    // 範囲を指定した場合、以降の処理は範囲の先頭を0秒として進むので、報告する時刻だけ戻す。
+    // 話者分離や要確認の印は範囲内の時刻で行うため、このシンクを最も内側に置く
+    let mut offset_sink;
+    let sink: &mut dyn TranscriptionSink = match options.range {
+        Some(range) if range.start_ms > 0 => {
+            offset_sink = TimeOffsetSink::new(sink, range.start_ms as f32 / 1000.0);
+            &mut offset_sink
+        }
+        _ => sink,
+    };
+
+    sink.started(total_duration_ms);
+    let whole_signal = samples.as_deref().unwrap_or_default();
+
+    let mut labeling_sink;
+    let sink: &mut dyn TranscriptionSink = match &options.diarization {
+        Some(diarization_options) => {
+            let diarization_model_dir = model_dir_path
+                .parent()
+                .unwrap_or(model_dir_path)
+                .join(diarization::DIARIZATION_MODEL_DIR);
+            let turns = match diarization::diarize(
+                whole_signal,
+                &diarization_model_dir,
+                diarization_options,
+            ) {
+                Ok(turns) => turns,
+                Err(e) => {
+                    sink.error(&format!("話者分離に失敗しました: {}", e));
+                    return JobStatus::Failed;
+                }
+            };
+            log::info!("Diarization found {} speaker turns", turns.len());
+
+            // 話者分離にも時間がかかるため、終わった時点でキャンセル要求を確認する
+            if cancel_requested.load(Ordering::SeqCst) {
+                log::info!("ASR cancelled after diarization: {}", file_path);
+                sink.cancelled(&[], start_t.elapsed().as_millis() as u64);
+                return JobStatus::Cancelled;
+            }
+
+            labeling_sink = SpeakerLabelingSink::new(sink, turns);
+            &mut labeling_sink
+        }
+        None => sink,
+    };
+
+    let mut review_sink;
+    let sink: &mut dyn TranscriptionSink = match options.review_threshold {
+        Some(threshold) => {
+            review_sink = ReviewMarkingSink::new(sink, threshold);
+            &mut review_sink
+        }
+        None => sink,
+    };
+
+    let context = RecognitionContext {
+        segmentation: options.segmentation,
+        chunking,
+        silero_model_path: model_dir_path
+            .parent()
+            .map(|dir| dir.join(vad::SILERO_VAD_MODEL_FILE)),
+        recognizer,
+        cancel_requested,
+    };
+    let outcome = match &mut input {
+        AudioInput::Channels { count, .. } => {
+            process_channels(&context, file_path, options, *count, sink)
+        }
+        AudioInput::Stream(stream) => recognize(&context, stream, samples.as_deref(), sink),
+    };
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            sink.error(&format!("音声ファイルのデコードに失敗しました: {}", e));
+            return JobStatus::Failed;
+        }
+    };
+
+    let elapsed = start_t.elapsed();
+    match outcome {
+        ProcessOutcome::Cancelled(sentences) => {
+            log::info!(
+                "ASR cancelled for: {} ({} sentences finished)",
+                file_path,
+                sentences.len()
+            );
+            sink.cancelled(&sentences, elapsed.as_millis() as u64);
+            JobStatus::Cancelled
+        }
+        ProcessOutcome::Completed(sentences) => {
+            log::info!("Time taken for decode: {:?}", elapsed);
+            sink.finished(&sentences, elapsed.as_millis() as u64);
+            JobStatus::Finished
+        }
+    }
+}
+
+/// キューの1ジョブを処理し、イベントをフロントエンドへ送る
+pub fn run_job(
+    app_handle: &AppHandle,
+    recognizer: &SharedRecognizer,
+    job_id: JobId,
+    file_path: &str,
+    model: &'static ModelInfo,
+    options: &TranscriptionOptions,
+    cancel_requested: &AtomicBool,
+) -> JobStatus {
+    log::info!(
+        "Starting ASR job {} with {} for: {}",
+        job_id,
+        model.id,
+        file_path
+    );
+    let mut sink = TauriEventSink::new(app_handle.clone(), job_id, options.word_timestamps);
+
+    let model_dir_path = match app_handle.path().resolve(
+        format!("models/{}", model.directory),
+        BaseDirectory::AppLocalData,
+    ) {
+        Ok(path) => path,
+        Err(e) => {
+            sink.error(&format!("モデルディレクトリの解決に失敗しました: {}", e));
+            return JobStatus::Failed;
+        }
+    };
+
+    let options = TranscriptionOptions {
+        decoding: Some(
+            options
+                .decoding
+                .unwrap_or_else(|| settings::load_decoding_options(app_handle)),
+        ),
+        chunking: Some(
+            options
+                .chunking
+                .unwrap_or_else(|| settings::load_chunk_options(app_handle, model)),
+        ),
+        ..options.clone()
+    };
+    let status = transcribe_file(
+        file_path,
+        model,
+        &model_dir_path,
+        &options,
+        recognizer,
+        cancel_requested,
+        &mut sink,
+    );
+    log::info!("ASR job {} ended with {:?}: {}", job_id, status, file_path);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::RecognitionResult;
+    use crate::sink::MemorySink;
+    use std::sync::Arc;
+
+    // テストではサンプル数を抑えるため低いサンプリングレートを使う
+    const SAMPLE_RATE: u32 = 100;
+
+    /// 呼び出し順にあらかじめ用意した結果を返す偽の認識器
+    struct ScriptedRecognizer {
+        sample_rate: u32,
+        responses: Vec<RecognitionResult>,
+        chunk_lengths: Vec<usize>,
+        cancel_after_first_call: Option<Arc<AtomicBool>>,
+    }
+
+    impl ScriptedRecognizer {
+        fn new(responses: Vec<Vec<(&str, f32)>>) -> Self {
+            ScriptedRecognizer {
+                sample_rate: SAMPLE_RATE,
+                responses: responses
+                    .into_iter()
+                    .map(|r| RecognitionResult {
+                        tokens: r.iter().map(|(t, _)| t.to_string()).collect(),
+                        timestamps: r.iter().map(|(_, ts)| *ts).collect(),
+                        log_probs: Vec::new(),
+                    })
+                    .collect(),
+                chunk_lengths: Vec::new(),
+                cancel_after_first_call: None,
+            }
+        }
+    }
+
+    impl Recognizer for ScriptedRecognizer {
+        fn transcribe(&mut self, sample_rate: u32, samples: &[f32]) -> RecognitionResult {
+            assert_eq!(sample_rate, self.sample_rate);
+            self.chunk_lengths.push(samples.len());
+            if let Some(flag) = &self.cancel_after_first_call {
+                flag.store(true, Ordering::SeqCst);
+            }
+            assert!(
+                !self.responses.is_empty(),
+                "recognizer called more times than scripted"
+            );
+            self.responses.remove(0)
+        }
+    }
+
+    fn sentence(text: &str, words: &[(&str, f32, f32)]) -> Sentence {
+        let words: Vec<Word> = words
+            .iter()
+            .map(|&(text, start_time, end_time)| Word {
+                start_time,
+                end_time,
+                text: text.to_string(),
+                confidence: None,
+            })
+            .collect();
+        Sentence {
+            start: words[0].start_time,
+            end: words[words.len() - 1].end_time,
+            text: text.to_string(),
+            speaker: None,
+            channel: None,
+            words,
+            confidence: None,
+            needs_review: false,
+        }
+    }
+
+    fn hello_world() -> Sentence {
+        sentence("Hello world.", &[("Hello", 1.0, 1.0), ("world.", 2.0, 2.0)])
+    }
+
+    fn run(
+        seconds: usize,
+        recognizer: &mut ScriptedRecognizer,
+        cancel_requested: &AtomicBool,
+    ) -> (ProcessOutcome, MemorySink) {
+        let samples = vec![0.0; seconds * SAMPLE_RATE as usize];
+        let mut sink = MemorySink::default();
+        let outcome = process_chunks(
+            &mut samples.chunks(SAMPLE_RATE as usize),
+            SAMPLE_RATE,
+            ChunkOptions::default(),
+            recognizer,
+            &mut sink,
+            cancel_requested,
+        )
+        .unwrap();
+        (outcome, sink)
+    }
+
+    // 2チャンク（0-30秒、22-50秒）にまたがる音声の台本
+    fn two_chunk_script() -> Vec<Vec<(&'static str, f32)>> {
+        vec![
+            vec![
+                (" Hello", 1.0),
+                (" world.", 2.0),
+                (" This", 24.0),
+                (" is", 25.5),
+                (" spl", 25.75),
+                // 中央部分（26秒まで）の外なので次のチャンクで拾われる
+                ("it", 26.25),
+            ],
+            vec![
+                // 重複部分（チャンク先頭から4秒まで）は前のチャンクで拾い済み
+                (" This", 2.0),
+                (" is", 3.5),
+                (" spl", 3.75),
+                ("it", 4.25),
+                (" across", 5.0),
+                (" chunks.", 6.0),
+                (" And", 20.0),
+                (" more", 21.0),
+            ],
+        ]
+    }
+
+    fn chunk_bounds(samples: &[f32], block_len: usize) -> Vec<(usize, usize)> {
+        let mut source = samples.chunks(block_len);
+        let mut window = ChunkWindow::new(&mut source, 30, 8);
+        let mut bounds = Vec::new();
+        while let Some(chunk) = window.next_chunk().unwrap() {
+            bounds.push((chunk.start, chunk.start + chunk.samples.len()));
+        }
+        bounds
+    }
+
+    #[test]
+    fn chunk_window_overlaps_and_clamps_last_chunk() {
+        assert_eq!(
+            chunk_bounds(&[0.0; 100], 7),
+            vec![(0, 30), (22, 52), (44, 74), (66, 96), (88, 100)]
+        );
+    }
+
+    #[test]
+    fn chunk_window_single_chunk_when_input_fits() {
+        assert_eq!(chunk_bounds(&[0.0; 30], 7), vec![(0, 30)]);
+        assert_eq!(chunk_bounds(&[], 7), vec![]);
+    }
+
+    #[test]
+    fn central_range_keeps_outer_edges_of_first_and_last_chunks() {
+        let sr = SAMPLE_RATE as usize;
+        assert_eq!(
+            central_range_for_chunk(true, false, 30 * sr, SAMPLE_RATE, ChunkOptions::default()),
+            (0.0, 26.0)
+        );
+        assert_eq!(
+            central_range_for_chunk(false, true, 28 * sr, SAMPLE_RATE, ChunkOptions::default()),
+            (4.0, 28.0)
+        );
+    }
+
+    #[test]
+    fn central_ranges_tile_the_signal_for_every_valid_chunking() {
+        let model = crate::models::find_offline_model(crate::models::DEFAULT_MODEL_ID).unwrap();
+        let sr = SAMPLE_RATE as usize;
+        for chunk_seconds in MIN_CHUNK_SECONDS..=40 {
+            for overlap_seconds in 0..chunk_seconds {
+                let chunking = ChunkOptions {
+                    chunk_seconds,
+                    overlap_seconds,
+                };
+                assert!(chunking.validate(model).is_ok());
+                for total_seconds in [1, chunk_seconds, chunk_seconds + 1, 95] {
+                    let samples = vec![0.0; total_seconds as usize * sr];
+                    let mut source = samples.chunks(sr);
+                    let mut window = ChunkWindow::new(
+                        &mut source,
+                        chunk_seconds as usize * sr,
+                        overlap_seconds as usize * sr,
+                    );
+                    // 各チャンクで使う範囲が、隙間も重なりもなく音声全体を覆うこと
+                    let mut covered_until = 0.0;
+                    while let Some(chunk) = window.next_chunk().unwrap() {
+                        let (start, end) = central_range_for_chunk(
+                            chunk.start == 0,
+                            chunk.is_last,
+                            chunk.samples.len(),
+                            SAMPLE_RATE,
+                            chunking,
+                        );
+                        let chunk_start = (chunk.start / sr) as f32;
+                        assert_eq!(chunk_start + start, covered_until, "{:?}", chunking);
+                        assert!(start < end, "{:?}", chunking);
+                        covered_until = chunk_start + end;
+                    }
+                    assert_eq!(covered_until, total_seconds as f32, "{:?}", chunking);
+                }
             }
+        }
+    }
+
+    #[test]
+    fn chunk_options_reject_short_chunks_and_long_overlaps() {
+        let model = crate::models::find_offline_model(crate::models::DEFAULT_MODEL_ID).unwrap();
+        let invalid = [(MIN_CHUNK_SECONDS - 1, 0), (20, 20), (20, 25)];
+        for (chunk_seconds, overlap_seconds) in invalid {
+            let chunking = ChunkOptions {
+                chunk_seconds,
+                overlap_seconds,
+            };
+            assert!(chunking.validate(model).is_err(), "{:?}", chunking);
+        }
+
+        // Whisper は30秒より長い音声を一度に認識できない
+        let whisper = crate::models::models()
+            .iter()
+            .find(|m| m.max_chunk_seconds().is_some())
+            .unwrap();
+        let long = ChunkOptions {
+            chunk_seconds: 40,
+            overlap_seconds: 8,
         };
+        assert!(long.validate(model).is_ok());
+        assert!(long.validate(whisper).is_err());
+    }
 
-        let total_duration_ms = (samples.len() as f64 / sample_rate as f64 * 1000.0) as u64;
-        app_handle
-            .emit("asr-started", StartedPayload { total_duration_ms })
+    #[test]
+    fn chunks_do_not_overlap_for_models_without_token_timestamps() {
+        let transducer =
+            crate::models::find_offline_model(crate::models::DEFAULT_MODEL_ID).unwrap();
+        let whisper = crate::models::models()
+            .iter()
+            .find(|m| matches!(m.kind, crate::models::ModelKind::Whisper { .. }))
             .unwrap();
+        let chunking = ChunkOptions::default();
+        assert_eq!(chunking.for_model(transducer), chunking);
+        assert_eq!(
+            chunking.for_model(whisper),
+            ChunkOptions {
+                chunk_seconds: chunking.chunk_seconds,
+                overlap_seconds: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn channel_merger_interleaves_channels_by_start_time() {
+        let at = |start: f32| sentence("x", &[("x", start, start + 1.0)]);
+        let starts =
+            |sentences: Vec<Sentence>| -> Vec<f32> { sentences.iter().map(|s| s.start).collect() };
+        let mut merger = ChannelMerger::new(2);
+
+        // 右チャンネルからまだ何も届いていないので、左の文は出力できない
+        merger.push(0, at(1.0));
+        merger.push(0, at(5.0));
+        assert!(merger.pop_ready().is_empty());
+
+        // 右が 3 秒まで進めば、それより前に始まる左の文は確定する
+        merger.push(1, at(3.0));
+        assert_eq!(starts(merger.pop_ready()), vec![1.0, 3.0]);
+
+        // 右が終われば左の残りも出力できる
+        merger.finish(1);
+        merger.push(0, at(7.0));
+        assert_eq!(starts(merger.pop_ready()), vec![5.0, 7.0]);
+
+        merger.push(0, at(9.0));
+        merger.push(1, at(8.0));
+        assert_eq!(starts(merger.drain()), vec![8.0, 9.0]);
+    }
+
+    // 左右のチャンネルを `seconds` 秒ずつ別々に認識する。台本はどのチャンクでも同じなので、
+    // チャンネルが交互に認識器を使っても結果は変わらない
+    fn transcribe_stereo(
+        name: &str,
+        seconds: u32,
+        channels: usize,
+    ) -> (Result<ProcessOutcome, String>, MemorySink, bool) {
+        let path = std::env::temp_dir().join(format!("{}-{}.wav", name, std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: REQUIRED_SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..REQUIRED_SAMPLE_RATE * seconds {
+            writer.write_sample(0.25f32).unwrap();
+            writer.write_sample(-0.25f32).unwrap();
+        }
+        writer.finalize().unwrap();
 
-        process_chunks(
-            &samples,
-            sample_rate,
-            CHUNK_SECONDS,
-            OVERLAP_SECONDS,
-            &mut recognizer,
-            &app_handle,
+        let chunks = (seconds / 10) as usize * 2;
+        let mut scripted = ScriptedRecognizer::new(vec![vec![(" Hello.", 1.0)]; chunks]);
+        scripted.sample_rate = REQUIRED_SAMPLE_RATE;
+        let model = crate::models::find_offline_model(crate::models::DEFAULT_MODEL_ID).unwrap();
+        let recognizer = SharedRecognizer::with_engine(model, Box::new(scripted));
+        let cancel_requested = AtomicBool::new(false);
+        let context = RecognitionContext {
+            segmentation: Segmentation::FixedWindow,
+            chunking: ChunkOptions {
+                chunk_seconds: 10,
+                overlap_seconds: 0,
+            },
+            silero_model_path: None,
+            recognizer: &recognizer,
+            cancel_requested: &cancel_requested,
+        };
+        let mut sink = MemorySink::default();
+        let result = process_channels(
+            &context,
+            path.to_str().unwrap(),
+            &TranscriptionOptions::default(),
+            channels,
+            &mut sink,
         );
+        std::fs::remove_file(&path).unwrap();
+        (result, sink, cancel_requested.load(Ordering::SeqCst))
+    }
 
-        let elapsed = start_t.elapsed();
-        log::info!("Time taken for decode: {:?}", elapsed);
-        app_handle
-            .emit(
-                "asr-finished",
-                FinishedPayload {
-                    processing_time_ms: elapsed.as_millis() as u64,
-                },
-            )
-            .unwrap();
-        log::info!("ASR process finished for: {}", file_path);
-    });
+    #[test]
+    fn process_channels_interleaves_channels_by_start_time() {
+        let (result, sink, _) = transcribe_stereo("split-channels", 20, 2);
+        let sentences = match result.unwrap() {
+            ProcessOutcome::Completed(sentences) => sentences,
+            ProcessOutcome::Cancelled(_) => panic!("unexpected cancellation"),
+        };
+        assert_eq!(sink.sentences(), sentences);
+
+        let starts: Vec<f32> = sentences.iter().map(|s| s.start).collect();
+        assert_eq!(starts, vec![1.0, 1.0, 11.0, 11.0]);
+        for pair in sentences.chunks(2) {
+            let mut channels: Vec<Option<u32>> = pair.iter().map(|s| s.channel).collect();
+            channels.sort();
+            assert_eq!(channels, vec![Some(0), Some(1)]);
+            assert!(pair.iter().all(|s| s.speaker == s.channel));
+        }
+    }
+
+    #[test]
+    fn process_channels_fails_when_a_channel_fails() {
+        // 3チャンネル目はないので開けずに失敗し、残りのチャンネルも止まる
+        let (result, _, cancelled) = transcribe_stereo("split-channels-error", 20, 3);
+        let error = result.err().expect("a missing channel must fail the file");
+        assert!(error.starts_with("channel 2:"), "{}", error);
+        // 呼び出し元のキャンセル要求のフラグには触れない
+        assert!(!cancelled);
+    }
+
+    #[test]
+    fn process_chunks_merges_overlap_and_carries_words_across_chunks() {
+        let mut recognizer = ScriptedRecognizer::new(two_chunk_script());
+        let (outcome, sink) = run(50, &mut recognizer, &AtomicBool::new(false));
+
+        let expected = vec![
+            hello_world(),
+            sentence(
+                "This is split across chunks.",
+                &[
+                    ("This", 24.0, 24.0),
+                    ("is", 25.5, 25.5),
+                    // チャンクをまたいだ単語は両方のチャンクのトークンの時刻を持つ
+                    ("split", 25.75, 26.25),
+                    ("across", 27.0, 27.0),
+                    ("chunks.", 28.0, 28.0),
+                ],
+            ),
+            sentence("And more", &[("And", 42.0, 42.0), ("more", 43.0, 43.0)]),
+        ];
+        match outcome {
+            ProcessOutcome::Completed(sentences) => assert_eq!(sentences, expected),
+            ProcessOutcome::Cancelled(_) => panic!("unexpected cancellation"),
+        }
+        assert_eq!(sink.sentences(), expected);
+        assert_eq!(
+            recognizer.chunk_lengths,
+            vec![30 * SAMPLE_RATE as usize, 28 * SAMPLE_RATE as usize]
+        );
+    }
+
+    #[test]
+    fn process_chunks_flushes_unfinished_sentence_at_end() {
+        let mut recognizer = ScriptedRecognizer::new(vec![vec![
+            (" no", 1.0),
+            (" period", 2.0),
+            (" her", 3.0),
+            ("e", 3.5),
+        ]]);
+        let (outcome, _) = run(10, &mut recognizer, &AtomicBool::new(false));
+
+        match outcome {
+            ProcessOutcome::Completed(sentences) => {
+                assert_eq!(
+                    sentences,
+                    vec![sentence(
+                        "no period here",
+                        &[("no", 1.0, 1.0), ("period", 2.0, 2.0), ("here", 3.0, 3.5)]
+                    )]
+                )
+            }
+            ProcessOutcome::Cancelled(_) => panic!("unexpected cancellation"),
+        }
+    }
+
+    #[test]
+    fn process_chunks_stops_between_chunks_when_cancelled() {
+        let cancel_requested = Arc::new(AtomicBool::new(false));
+        let mut recognizer = ScriptedRecognizer::new(two_chunk_script());
+        recognizer.cancel_after_first_call = Some(cancel_requested.clone());
+        let (outcome, _) = run(50, &mut recognizer, &cancel_requested);
+
+        match outcome {
+            ProcessOutcome::Cancelled(sentences) => {
+                assert_eq!(sentences, vec![hello_world()])
+            }
+            ProcessOutcome::Completed(_) => panic!("expected cancellation"),
+        }
+        assert_eq!(recognizer.chunk_lengths.len(), 1);
+    }
+
+    #[test]
+    fn sentence_end_ignores_abbreviations_and_lowercase_continuations() {
+        assert!(is_word_sentence_end("done.", Some("Next")));
+        assert!(is_word_sentence_end("really?", None));
+        assert!(!is_word_sentence_end("Mr.", Some("Smith")));
+        assert!(!is_word_sentence_end("approx.", Some("three")));
+        assert!(!is_word_sentence_end("word", Some("Next")));
+    }
+
+    #[test]
+    fn japanese_tokens_split_into_sentences_without_spaces() {
+        let tokens: Vec<String> = ["今日", "は", "晴れ", "。", "明日", "も", "GPU", "を"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        let timestamps: Vec<f32> = (0..tokens.len()).map(|i| i as f32 * 0.2).collect();
+
+        let log_probs = vec![None; tokens.len()];
+
+        let (sentences, carry_words, _, _, _) =
+            extract_sentences(&[], &tokens, &timestamps, &log_probs);
+
+        assert_eq!(sentences.len(), 1);
+        assert_eq!(sentences[0].text, "今日は晴れ。");
+        assert!((sentences[0].end - 0.6).abs() < 1e-6);
+        assert_eq!(
+            join_words(carry_words.iter().map(|w| w.text.as_str())),
+            "明日もGPU"
+        );
+    }
+
+    #[test]
+    fn token_log_probs_aggregate_into_word_and_sentence_confidence() {
+        let mut recognizer = ScriptedRecognizer::new(vec![]);
+        recognizer.responses.push(RecognitionResult {
+            tokens: [" Hel", "lo", " there."]
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            timestamps: vec![1.0, 1.5, 2.0],
+            log_probs: vec![0.5f32.ln(), 0.8f32.ln(), 0.9f32.ln()],
+        });
+        let (outcome, _) = run(10, &mut recognizer, &AtomicBool::new(false));
+
+        let ProcessOutcome::Completed(sentences) = outcome else {
+            panic!("expected completion");
+        };
+        let word_confidences: Vec<f32> = sentences[0]
+            .words
+            .iter()
+            .map(|w| w.confidence.unwrap())
+            .collect();
+        // "Hello" は 2 トークンの確率の幾何平均
+        assert!((word_confidences[0] - 0.4f32.sqrt()).abs() < 1e-5);
+        assert!((word_confidences[1] - 0.9).abs() < 1e-5);
+        let expected = (0.4f32.sqrt() + 0.9) / 2.0;
+        assert!((sentences[0].confidence.unwrap() - expected).abs() < 1e-5);
+    }
 }
@@ -1,21 +1,91 @@
 // cSpell:words Sinc
+use crate::preprocess::{Filters, PreprocessOptions};
 use log::info;
 use rubato::{
     Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
 };
 use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
-use symphonia::core::formats::FormatOptions;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
-use symphonia::core::units::Duration;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::{Hint, ProbeResult};
+use symphonia::core::units::{Duration, Time, TimeBase};
 use symphonia::default::{get_codecs, get_probe};
 
-pub fn convert_to_mono_f32_16khz(input_path: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-    info!("Converting audio file: {}", input_path);
+const OUTPUT_SAMPLE_RATE: u32 = 16000;
+// Number of input frames the resampler consumes per call.
+const RESAMPLER_BLOCK_FRAMES: usize = 4096;
+// Blocks are handed out once this many output samples (1 second) are ready.
+const OUTPUT_BLOCK_SAMPLES: usize = OUTPUT_SAMPLE_RATE as usize;
 
+/// How the channels of the selected track are combined into mono.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum ChannelMix {
+    /// Average all channels.
+    #[default]
+    Average,
+    /// Use one channel (0-based) and ignore the others, e.g. one microphone per channel.
+    Single { channel: usize },
+    /// Weighted sum with one gain per channel, used as given (not normalised).
+    Weights { weights: Vec<f32> },
+}
+
+impl ChannelMix {
+    /// The gain of each channel for a track with `channels` channels.
+    fn weights(&self, channels: usize) -> Result<Vec<f32>, String> {
+        match self {
+            ChannelMix::Average => Ok(vec![1.0 / channels as f32; channels]),
+            ChannelMix::Single { channel } if *channel < channels => {
+                let mut weights = vec![0.0; channels];
+                weights[*channel] = 1.0;
+                Ok(weights)
+            }
+            ChannelMix::Single { channel } => Err(format!(
+                "channel {} does not exist; the track has {} channels",
+                channel, channels
+            )),
+            ChannelMix::Weights { weights } if weights.len() == channels => Ok(weights.clone()),
+            ChannelMix::Weights { weights } => Err(format!(
+                "got {} downmix weights for a track with {} channels",
+                weights.len(),
+                channels
+            )),
+        }
+    }
+}
+
+/// Which track of the file to decode and how to mix its channels.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AudioSelection {
+    /// Track ID as reported by `list_tracks`; the first decodable track if `None`.
+    pub track_id: Option<u32>,
+    pub channel_mix: ChannelMix,
+}
+
+/// A decodable track of a media file, as listed by `list_tracks`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioTrackInfo {
+    pub id: u32,
+    /// Short codec name, e.g. `aac` or `pcm_s16le`.
+    pub codec: String,
+    /// Language tag from the container, if any.
+    pub language: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    /// Speaker position of each channel (e.g. `FRONT_LEFT`), when the container reports them.
+    pub channel_labels: Vec<String>,
+    pub duration_ms: Option<u64>,
+    /// Whether this track is decoded when no track is selected.
+    pub is_default: bool,
+}
+
+// Opens and probes a media file.
+fn probe(input_path: &str) -> Result<ProbeResult, Box<dyn std::error::Error>> {
     // Create a media source.
     let src = std::fs::File::open(input_path)?;
     let mss = MediaSourceStream::new(Box::new(src), Default::default());
@@ -35,124 +105,786 @@ pub fn convert_to_mono_f32_16khz(input_path: &str) -> Result<Vec<f32>, Box<dyn s
     let fmt_opts: FormatOptions = Default::default();
 
     // Probe the media source.
-    let probed = get_probe().format(&hint, mss, &fmt_opts, &meta_opts)?;
-    let mut format = probed.format;
+    Ok(get_probe().format(&hint, mss, &fmt_opts, &meta_opts)?)
+}
+
+/// Lists the tracks of a media file that can be decoded, without decoding any audio.
+pub fn list_tracks(input_path: &str) -> Result<Vec<AudioTrackInfo>, Box<dyn std::error::Error>> {
+    Ok(track_infos(probe(input_path)?.format.as_ref()))
+}
+
+fn track_infos(format: &dyn FormatReader) -> Vec<AudioTrackInfo> {
+    let mut tracks = Vec::new();
+    for track in format.tracks() {
+        let params = &track.codec_params;
+        if params.codec == CODEC_TYPE_NULL {
+            continue;
+        }
+        let codec = get_codecs()
+            .get_codec(params.codec)
+            .map_or_else(|| params.codec.to_string(), |d| d.short_name.to_string());
+        let duration_ms = match (params.n_frames, params.sample_rate) {
+            (Some(frames), Some(rate)) => Some(frames * 1000 / rate as u64),
+            _ => None,
+        };
+        tracks.push(AudioTrackInfo {
+            id: track.id,
+            codec,
+            language: track.language.clone(),
+            sample_rate: params.sample_rate,
+            channels: params.channels.map(|ch| ch.count()),
+            channel_labels: params
+                .channels
+                .map(|ch| ch.iter().map(|c| format!("{:?}", c)).collect())
+                .unwrap_or_default(),
+            duration_ms,
+            is_default: tracks.is_empty(),
+        });
+    }
+    tracks
+}
+
+/// A chapter (cue) embedded in the container.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chapter {
+    pub start_ms: u64,
+    /// Start of the next chapter, or the end of the file for the last one.
+    pub end_ms: Option<u64>,
+    pub title: Option<String>,
+}
+
+/// What `probe_media` learns about a file without decoding its audio. The stream fields
+/// describe the default track.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaInfo {
+    /// Container format, e.g. `wav` or `mp4`, when it can be recognised from the file header.
+    pub container: Option<String>,
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    pub duration_ms: Option<u64>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub recording_date: Option<String>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// Reads the stream parameters, tags and chapters of a media file. Only the headers are
+/// read, so this is fast even for long recordings.
+pub fn probe_media(input_path: &str) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+    let container = read_header(input_path)?;
+    let ProbeResult {
+        mut format,
+        metadata: mut probed,
+    } = probe(input_path)?;
+
+    let default_track = track_infos(format.as_ref()).into_iter().next();
+    let (codec, sample_rate, channels, duration_ms) = match default_track {
+        Some(track) => (
+            Some(track.codec),
+            track.sample_rate,
+            track.channels,
+            track.duration_ms,
+        ),
+        None => (None, None, None, None),
+    };
+
+    // Tags inside the container take precedence over tags found in front of it (e.g. ID3v2).
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(revision) = format.metadata().skip_to_latest() {
+        tags.extend(revision.tags().iter().cloned());
+    }
+    if let Some(mut metadata) = probed.get() {
+        if let Some(revision) = metadata.skip_to_latest() {
+            tags.extend(revision.tags().iter().cloned());
+        }
+    }
+    let find = |keys: &[StandardTagKey]| {
+        keys.iter().find_map(|key| {
+            tags.iter()
+                .find(|tag| tag.std_key == Some(*key))
+                .map(|tag| tag.value.to_string())
+        })
+    };
 
-    // Find the first audio track with a known codec format.
-    let track = format
-        .tracks()
+    // Cue timestamps are in the time base of the default track.
+    let time_base = format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        params
+            .time_base
+            .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))
+    });
+    let mut chapters: Vec<Chapter> = match time_base {
+        Some(time_base) => format
+            .cues()
+            .iter()
+            .map(|cue| Chapter {
+                start_ms: time_to_ms(time_base.calc_time(cue.start_ts)),
+                end_ms: None,
+                title: cue
+                    .tags
+                    .iter()
+                    .find(|tag| tag.std_key == Some(StandardTagKey::TrackTitle))
+                    .or_else(|| cue.tags.first())
+                    .map(|tag| tag.value.to_string()),
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let ends: Vec<Option<u64>> = chapters
         .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or("No audio track found")?;
-
-    let sample_rate = track
-        .codec_params
-        .sample_rate
-        .ok_or("Sample rate unknown")?;
-
-    // Don't fail immediately if channels is missing; try to detect from decoded frames as a
-    // fallback. Keep a mutable option we update after decoding the first frame.
-    let mut channels_opt = track.codec_params.channels;
-    if let Some(ch) = channels_opt {
-        info!(
-            "Found audio track: {} with sample rate: {} and channels: {}",
-            track.codec_params.codec,
-            sample_rate,
-            ch.count()
-        );
-    } else {
-        info!(
-            "Found audio track: {} with sample rate: {} but channel info is missing in codec params; will try to detect from decoded frames",
-            track.codec_params.codec,
+        .skip(1)
+        .map(|next| Some(next.start_ms))
+        .chain(std::iter::once(duration_ms))
+        .collect();
+    for (chapter, end_ms) in chapters.iter_mut().zip(ends) {
+        chapter.end_ms = end_ms;
+    }
+
+    Ok(MediaInfo {
+        container: container.map(str::to_string),
+        codec,
+        sample_rate,
+        channels,
+        duration_ms,
+        title: find(&[StandardTagKey::TrackTitle]),
+        artist: find(&[StandardTagKey::Artist, StandardTagKey::AlbumArtist]),
+        recording_date: find(&[
+            StandardTagKey::Date,
+            StandardTagKey::OriginalDate,
+            StandardTagKey::ReleaseDate,
+        ]),
+        chapters,
+    })
+}
+
+fn time_to_ms(time: Time) -> u64 {
+    time.seconds * 1000 + (time.frac * 1000.0) as u64
+}
+
+// Symphonia does not report which format reader it picked, so the container is recognised
+// from the same signatures its probe looks for.
+fn read_header(input_path: &str) -> std::io::Result<Option<&'static str>> {
+    use std::io::Read;
+    let mut header = Vec::with_capacity(12);
+    std::fs::File::open(input_path)?
+        .take(12)
+        .read_to_end(&mut header)?;
+    Ok(container_name(&header))
+}
+
+fn container_name(header: &[u8]) -> Option<&'static str> {
+    match header {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', ..] => Some("aiff"),
+        [b'f', b'L', b'a', b'C', ..] => Some("flac"),
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [b'c', b'a', b'f', b'f', ..] => Some("caf"),
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => Some("mkv"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("mp4"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        // ADTS frames have layer 0; MPEG audio frames use layers 1 to 3.
+        [0xff, second, ..] if second & 0xf6 == 0xf0 => Some("aac"),
+        [0xff, second, ..] if second & 0xe0 == 0xe0 => Some("mp3"),
+        _ => None,
+    }
+}
+
+/// A source of 16 kHz mono samples delivered in blocks.
+pub trait SampleSource {
+    /// Returns the next block of samples, or `None` at the end of the stream.
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>, String>;
+}
+
+/// Already decoded samples, e.g. `samples.chunks(n)`.
+impl SampleSource for std::slice::Chunks<'_, f32> {
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>, String> {
+        Ok(self.next().map(|block| block.to_vec()))
+    }
+}
+
+/// Decodes and resamples a media file to 16 kHz mono a block at a time, so memory use does not
+/// grow with the length of the input.
+pub struct AudioStream {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    channels: Option<usize>,
+    channel_mix: ChannelMix,
+    // Gain of each channel, known once the channel count is.
+    channel_weights: Option<Vec<f32>>,
+    sample_rate: u32,
+    n_frames: Option<u64>,
+    time_base: Option<TimeBase>,
+    // Requested range in milliseconds and the same bounds as track timestamps.
+    range_start_ms: u64,
+    range_end_ms: Option<u64>,
+    start_ts: u64,
+    end_ts: Option<u64>,
+    resampler: Option<SincFixedIn<f32>>,
+    // Mono samples at the input rate that have not been resampled yet.
+    pending_input: Vec<f32>,
+    // 16 kHz samples ready to be handed out.
+    output: Vec<f32>,
+    input_frames_total: u64,
+    output_frames_total: u64,
+    end_of_input: bool,
+    // DC removal and high-pass filter applied to the 16 kHz output.
+    filters: Option<Filters>,
+}
+
+impl AudioStream {
+    pub fn open(input_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open_with(input_path, &AudioSelection::default())
+    }
+
+    /// Opens `input_path`, decoding the selected track and mixing its channels as requested.
+    pub fn open_with(
+        input_path: &str,
+        selection: &AudioSelection,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        info!("Opening audio file: {}", input_path);
+
+        let format = probe(input_path)?.format;
+
+        // Find the requested track, or the first audio track with a known codec format.
+        let track = match selection.track_id {
+            Some(id) => format
+                .tracks()
+                .iter()
+                .find(|t| t.id == id && t.codec_params.codec != CODEC_TYPE_NULL)
+                .ok_or_else(|| format!("No decodable track with ID {}", id))?,
+            None => format
+                .tracks()
+                .iter()
+                .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+                .ok_or("No audio track found")?,
+        };
+
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or("Sample rate unknown")?;
+
+        // Don't fail immediately if channels is missing; try to detect from decoded frames as a
+        // fallback.
+        let channels = track.codec_params.channels.map(|ch| ch.count());
+        if let Some(ch) = channels {
+            info!(
+                "Found audio track: {} with sample rate: {} and channels: {}",
+                track.codec_params.codec, sample_rate, ch
+            );
+        } else {
+            info!(
+                "Found audio track: {} with sample rate: {} but channel info is missing in codec params; will try to detect from decoded frames",
+                track.codec_params.codec,
+                sample_rate,
+            );
+        }
+
+        // Create a decoder for the track.
+        let decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+        let track_id = track.id;
+        let n_frames = track.codec_params.n_frames;
+        let time_base = track.codec_params.time_base;
+
+        let resampler = if sample_rate != OUTPUT_SAMPLE_RATE {
+            info!(
+                "Resampling audio from {} Hz to {} Hz",
+                sample_rate, OUTPUT_SAMPLE_RATE
+            );
+            let params = SincInterpolationParameters {
+                sinc_len: 256,
+                f_cutoff: 0.95,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 256,
+                window: WindowFunction::BlackmanHarris2,
+            };
+            Some(SincFixedIn::<f32>::new(
+                OUTPUT_SAMPLE_RATE as f64 / sample_rate as f64,
+                2.0,
+                params,
+                RESAMPLER_BLOCK_FRAMES,
+                1,
+            )?)
+        } else {
+            None
+        };
+        // Check the channel selection now when the channel count is already known.
+        let channel_weights = channels
+            .map(|count| selection.channel_mix.weights(count))
+            .transpose()?;
+
+        Ok(AudioStream {
+            format,
+            decoder,
+            track_id,
+            channels,
+            channel_mix: selection.channel_mix.clone(),
+            channel_weights,
             sample_rate,
-        );
+            n_frames,
+            time_base,
+            range_start_ms: 0,
+            range_end_ms: None,
+            start_ts: 0,
+            end_ts: None,
+            resampler,
+            pending_input: Vec::new(),
+            output: Vec::new(),
+            input_frames_total: 0,
+            output_frames_total: 0,
+            end_of_input: false,
+            filters: None,
+        })
     }
 
-    // Create a decoder for the track.
-    let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+    /// Runs the streaming preprocessing steps (DC removal and high-pass filter) on every block
+    /// after resampling. Normalisation needs the whole signal and is applied by the caller.
+    pub fn set_preprocessing(&mut self, options: &PreprocessOptions) {
+        self.filters = options.filters(OUTPUT_SAMPLE_RATE);
+    }
 
-    // Store the decoded audio samples as f32.
-    let mut samples_f32 = Vec::new();
+    /// Restricts the stream to `start_ms..end_ms` of the file (to the end if `end_ms` is
+    /// `None`). Must be called before the first block is read.
+    ///
+    /// Seeks to the start when the format supports it and otherwise decodes and discards the
+    /// audio before it. Samples are still counted from the start of the range.
+    pub fn set_range(
+        &mut self,
+        start_ms: u64,
+        end_ms: Option<u64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if end_ms.is_some_and(|end| end <= start_ms) {
+            return Err(format!(
+                "range end ({} ms) must be after its start ({} ms)",
+                end_ms.unwrap_or_default(),
+                start_ms
+            )
+            .into());
+        }
+        self.range_start_ms = start_ms;
+        self.range_end_ms = end_ms;
+        self.start_ts = self.ms_to_ts(start_ms);
+        self.end_ts = end_ms.map(|end| self.ms_to_ts(end));
 
-    // The decode loop.
-    loop {
-        // Get the next packet from the media format.
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(Error::IoError(err)) => {
-                if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                    break;
-                } else {
-                    return Err(Box::new(err));
+        if start_ms > 0 {
+            let seek_to = SeekTo::Time {
+                time: Time::from(start_ms as f64 / 1000.0),
+                track_id: Some(self.track_id),
+            };
+            match self.format.seek(SeekMode::Accurate, seek_to) {
+                Ok(seeked) => {
+                    info!(
+                        "Seeked to timestamp {} for requested {}",
+                        seeked.actual_ts, seeked.required_ts
+                    );
+                    self.decoder.reset();
                 }
+                // Packets before the start are dropped in `decode_next_packet` instead.
+                Err(err) => info!(
+                    "Seeking is not supported ({}); decoding from the start",
+                    err
+                ),
             }
-            Err(err) => {
-                return Err(Box::new(err));
+        }
+        Ok(())
+    }
+
+    /// Duration reported by the container, if known, before decoding. Limited to the range set
+    /// with `set_range`.
+    pub fn estimated_duration_ms(&self) -> Option<u64> {
+        let total_ms = self
+            .n_frames
+            .map(|frames| frames * 1000 / self.sample_rate as u64);
+        let end_ms = match (total_ms, self.range_end_ms) {
+            (Some(total), Some(end)) => total.min(end),
+            (total, end) => total.or(end)?,
+        };
+        Some(end_ms.saturating_sub(self.range_start_ms))
+    }
+
+    fn ms_to_ts(&self, ms: u64) -> u64 {
+        match self.time_base {
+            Some(time_base) => time_base.calc_timestamp(Time::from(ms as f64 / 1000.0)),
+            None => ms * self.sample_rate as u64 / 1000,
+        }
+    }
+
+    fn ts_to_frames(&self, ts: u64) -> usize {
+        match self.time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * self.sample_rate as f64).round() as usize
             }
+            None => ts as usize,
+        }
+    }
+
+    /// Reads the rest of the stream into memory.
+    pub fn read_all(&mut self) -> Result<Vec<f32>, String> {
+        let mut samples = Vec::new();
+        while let Some(block) = self.next_block()? {
+            samples.extend_from_slice(&block);
+        }
+        info!("Decoded {} samples at 16 kHz", samples.len());
+        Ok(samples)
+    }
+
+    // Decodes the next packet of our track into `pending_input`. Returns false at end of input.
+    fn decode_next_packet(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        loop {
+            // Get the next packet from the media format.
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(false);
+                }
+                Err(err) => return Err(Box::new(err)),
+            };
+            // Skip packets from other tracks, e.g. video in a container.
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            if self.end_ts.is_some_and(|end| packet.ts() >= end) {
+                return Ok(false);
+            }
+
+            // Decode the packet into audio samples.
+            let audio_buf = self.decoder.decode(&packet)?;
+
+            // If channel info was missing in track codec params, obtain it from the decoded buffer.
+            if self.channels.is_none() {
+                let count = audio_buf.spec().channels.count();
+                info!("Detected channels from decoded frame: {}", count);
+                self.channels = Some(count);
+            }
+
+            // Copy the samples to a local buffer.
+            let mut sample_buf =
+                SampleBuffer::<f32>::new(audio_buf.capacity() as Duration, *audio_buf.spec());
+            sample_buf.copy_interleaved_ref(audio_buf);
+
+            // Get the interleaved samples.
+            let buffer = sample_buf.samples();
+
+            // Trim the parts of the packet that fall outside the requested range.
+            let channels_count = self.channels.ok_or("Channel info unknown after decoding")?;
+            let frames_in_packet = buffer.len() / channels_count;
+            let skip = self
+                .ts_to_frames(self.start_ts.saturating_sub(packet.ts()))
+                .min(frames_in_packet);
+            let keep = match self.end_ts {
+                Some(end) => self.ts_to_frames(end - packet.ts()).min(frames_in_packet),
+                None => frames_in_packet,
+            };
+            if skip >= keep {
+                continue;
+            }
+            let buffer = &buffer[skip * channels_count..keep * channels_count];
+
+            // Convert to mono and append to the pending buffer.
+            if self.channel_weights.is_none() {
+                self.channel_weights = Some(self.channel_mix.weights(channels_count)?);
+            }
+            let weights = self.channel_weights.as_deref().unwrap_or_default();
+            let before = self.pending_input.len();
+            if weights == [1.0] {
+                self.pending_input.extend_from_slice(buffer);
+            } else {
+                for frame in buffer.chunks(channels_count) {
+                    let sum: f32 = frame.iter().zip(weights).map(|(s, w)| s * w).sum();
+                    self.pending_input.push(sum);
+                }
+            }
+            self.input_frames_total += (self.pending_input.len() - before) as u64;
+            return Ok(true);
+        }
+    }
+
+    // Total output length that corresponds to all input decoded so far.
+    fn expected_output_frames(&self) -> u64 {
+        (self.input_frames_total as f64 * OUTPUT_SAMPLE_RATE as f64 / self.sample_rate as f64)
+            .ceil() as u64
+    }
+
+    // Moves whatever can be resampled from `pending_input` into `output`.
+    fn resample_pending(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let expected = self.expected_output_frames();
+        let Some(resampler) = self.resampler.as_mut() else {
+            let pending = std::mem::take(&mut self.pending_input);
+            self.output.extend_from_slice(&pending);
+            self.output_frames_total += pending.len() as u64;
+            return Ok(());
         };
 
-        // Decode the packet into audio samples.
-        let audio_buf = decoder.decode(&packet)?;
+        let mut produced = Vec::new();
+        while self.pending_input.len() >= resampler.input_frames_next() {
+            let frames = resampler.input_frames_next();
+            let out = resampler.process(&[&self.pending_input[..frames]], None)?;
+            self.pending_input.drain(..frames);
+            produced.extend_from_slice(&out[0]);
+        }
 
-        // If channel info was missing in track codec params, obtain it from the decoded buffer.
-        if channels_opt.is_none() {
-            channels_opt = Some(audio_buf.spec().channels);
-            if let Some(ch) = channels_opt {
-                info!("Detected channels from decoded frame: {}", ch.count());
+        // At the end, feed the remainder and then silence until the tail comes out. The sinc
+        // filter is centred on each output position, so the output needs no delay correction.
+        if self.end_of_input {
+            let rest = std::mem::take(&mut self.pending_input);
+            let out = resampler.process_partial(Some(&[&rest]), None)?;
+            produced.extend_from_slice(&out[0]);
+            while self.output_frames_total + (produced.len() as u64) < expected {
+                let out = resampler.process_partial::<&[f32]>(None, None)?;
+                produced.extend_from_slice(&out[0]);
             }
         }
 
-        // Copy the samples to a local buffer.
-        let mut sample_buf =
-            SampleBuffer::<f32>::new(audio_buf.capacity() as Duration, *audio_buf.spec());
-        sample_buf.copy_interleaved_ref(audio_buf);
+        self.output.extend_from_slice(&produced);
+        self.output_frames_total += produced.len() as u64;
+        if self.end_of_input {
+            let excess = self.output_frames_total.saturating_sub(expected) as usize;
+            self.output
+                .truncate(self.output.len().saturating_sub(excess));
+            self.output_frames_total -= excess as u64;
+        }
+        Ok(())
+    }
 
-        // Get the interleaved samples.
-        let buffer = sample_buf.samples();
+    fn fill_output(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        while self.output.len() < OUTPUT_BLOCK_SAMPLES && !self.end_of_input {
+            if !self.decode_next_packet()? {
+                self.end_of_input = true;
+            }
+            self.resample_pending()?;
+        }
+        Ok(())
+    }
+}
 
-        // Convert to mono and append to the main buffer.
-        let channels_count = channels_opt
-            .ok_or("Channel info unknown after decoding")?
-            .count();
+impl SampleSource for AudioStream {
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>, String> {
+        self.fill_output().map_err(|e| e.to_string())?;
+        if self.output.is_empty() {
+            return Ok(None);
+        }
+        let len = self.output.len().min(OUTPUT_BLOCK_SAMPLES);
+        let mut block: Vec<f32> = self.output.drain(..len).collect();
+        if let Some(filters) = &mut self.filters {
+            filters.process(&mut block);
+        }
+        Ok(Some(block))
+    }
+}
 
-        if channels_count == 1 {
-            samples_f32.extend_from_slice(buffer);
-        } else {
-            for frame in buffer.chunks(channels_count) {
-                let sum: f32 = frame.iter().sum();
-                samples_f32.push(sum / channels_count as f32);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `seconds` seconds of 16 kHz float WAV where every frame is `frame`.
+    fn write_constant(name: &str, frame: &[f32], seconds: u32) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.wav", name, std::process::id()));
+        let spec = hound::WavSpec {
+            channels: frame.len() as u16,
+            sample_rate: OUTPUT_SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..OUTPUT_SAMPLE_RATE * seconds {
+            for &sample in frame {
+                writer.write_sample(sample).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    // Writes one second per value, each second filled with that value, as mono WAV.
+    fn write_steps(name: &str, sample_rate: u32, values: &[f32]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.wav", name, std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for &value in values {
+            for _ in 0..sample_rate {
+                writer.write_sample(value).unwrap();
             }
         }
+        writer.finalize().unwrap();
+        path
     }
-    info!("Decoded {} samples", samples_f32.len());
 
-    // Resample if necessary.
-    let samples_resampled = if sample_rate != 16000 {
-        info!("Resampling audio from {} Hz to 16000 Hz", sample_rate);
-        let params = SincInterpolationParameters {
-            sinc_len: 256,
-            f_cutoff: 0.95,
-            interpolation: SincInterpolationType::Linear,
-            oversampling_factor: 256,
-            window: WindowFunction::BlackmanHarris2,
+    fn first_sample(path: &std::path::Path, channel_mix: ChannelMix) -> f32 {
+        let selection = AudioSelection {
+            track_id: None,
+            channel_mix,
+        };
+        let mut stream = AudioStream::open_with(path.to_str().unwrap(), &selection).unwrap();
+        stream.next_block().unwrap().unwrap()[0]
+    }
+
+    #[test]
+    fn channel_mix_selects_or_weights_channels() {
+        let path = write_constant("kotonoha-asr-stereo", &[0.2, 0.6], 1);
+
+        let tracks = list_tracks(path.to_str().unwrap()).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].channels, Some(2));
+        assert!(tracks[0].is_default);
+
+        assert!((first_sample(&path, ChannelMix::Average) - 0.4).abs() < 1e-6);
+        assert_eq!(first_sample(&path, ChannelMix::Single { channel: 1 }), 0.6);
+        let weights = ChannelMix::Weights {
+            weights: vec![1.0, 0.5],
         };
-        let mut resampler = SincFixedIn::<f32>::new(
-            16000.0 / sample_rate as f64,
-            2.0,
-            params,
-            samples_f32.len(),
-            1,
-        )?;
-        let chunks = vec![samples_f32];
-        let resampled_chunks = resampler.process(&chunks, None)?;
-        info!(
-            "Resampling complete, new sample count: {}",
-            resampled_chunks[0].len()
+        assert!((first_sample(&path, weights) - 0.5).abs() < 1e-6);
+
+        let missing_channel = AudioSelection {
+            track_id: None,
+            channel_mix: ChannelMix::Single { channel: 2 },
+        };
+        assert!(AudioStream::open_with(path.to_str().unwrap(), &missing_channel).is_err());
+        let missing_track = AudioSelection {
+            track_id: Some(99),
+            ..AudioSelection::default()
+        };
+        assert!(AudioStream::open_with(path.to_str().unwrap(), &missing_track).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn range_reads_only_the_requested_seconds() {
+        let path = write_steps(
+            "kotonoha-asr-range",
+            OUTPUT_SAMPLE_RATE,
+            &[0.1, 0.2, 0.3, 0.4, 0.5],
         );
-        resampled_chunks[0].clone()
-    } else {
-        samples_f32
-    };
+        let mut stream = AudioStream::open(path.to_str().unwrap()).unwrap();
+        stream.set_range(1500, Some(3500)).unwrap();
+        assert_eq!(stream.estimated_duration_ms(), Some(2000));
+
+        let samples = stream.read_all().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let second = OUTPUT_SAMPLE_RATE as usize;
+        assert_eq!(samples.len(), 2 * second);
+        assert_eq!(samples[0], 0.2);
+        assert_eq!(samples[second / 2], 0.3);
+        assert_eq!(samples[samples.len() - 1], 0.4);
+    }
+
+    #[test]
+    fn range_without_end_reads_to_the_end_of_the_file() {
+        let path = write_steps(
+            "kotonoha-asr-range-open",
+            OUTPUT_SAMPLE_RATE,
+            &[0.1, 0.2, 0.3],
+        );
+        let mut stream = AudioStream::open(path.to_str().unwrap()).unwrap();
+        stream.set_range(2000, None).unwrap();
+        assert_eq!(stream.estimated_duration_ms(), Some(1000));
 
-    Ok(samples_resampled)
+        let samples = stream.read_all().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples, vec![0.3; OUTPUT_SAMPLE_RATE as usize]);
+        assert!(stream.set_range(2000, Some(1000)).is_err());
+    }
+
+    // Index of the first sample at or above `level`, i.e. where a rising step crosses it.
+    fn crossing(samples: &[f32], from: usize, level: f32) -> usize {
+        from + samples[from..].iter().position(|&s| s >= level).unwrap()
+    }
+
+    // Reads the stream block by block, checking that no block is longer than promised.
+    fn read_blocks(stream: &mut AudioStream) -> Vec<f32> {
+        let mut samples = Vec::new();
+        while let Some(block) = stream.next_block().unwrap() {
+            assert!(!block.is_empty() && block.len() <= OUTPUT_BLOCK_SAMPLES);
+            samples.extend_from_slice(&block);
+        }
+        samples
+    }
+
+    #[test]
+    fn resampled_output_keeps_length_and_timing() {
+        let second = OUTPUT_SAMPLE_RATE as usize;
+        for sample_rate in [44100, 48000] {
+            let name = format!("kotonoha-asr-resample-{}", sample_rate);
+            let path = write_steps(&name, sample_rate, &[0.1, 0.2, 0.3]);
+            let mut stream = AudioStream::open(path.to_str().unwrap()).unwrap();
+            let samples = read_blocks(&mut stream);
+            std::fs::remove_file(&path).unwrap();
+
+            // The padding of the tail flush is cut off.
+            assert_eq!(samples.len(), 3 * second, "{} Hz", sample_rate);
+            // Each step lands on its second even after many resampler and output blocks.
+            for (step, level) in [(1, 0.15), (2, 0.25)] {
+                let at = crossing(&samples, step * second - second / 2, level);
+                assert!(
+                    at.abs_diff(step * second) <= 2,
+                    "{} Hz: {}",
+                    sample_rate,
+                    at
+                );
+            }
+            assert!((samples[second / 2] - 0.1).abs() < 1e-2);
+            assert!((samples[second + second / 2] - 0.2).abs() < 1e-2);
+            // The tail is flushed instead of being lost or replaced by silence.
+            assert!((samples[3 * second - 100] - 0.3).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn resampled_range_starts_mid_block() {
+        let second = OUTPUT_SAMPLE_RATE as usize;
+        for sample_rate in [44100, 48000] {
+            let name = format!("kotonoha-asr-resample-range-{}", sample_rate);
+            let path = write_steps(&name, sample_rate, &[0.1, 0.2, 0.3]);
+            let mut stream = AudioStream::open(path.to_str().unwrap()).unwrap();
+            // 1.01 s is neither a whole resampler block nor a whole packet into the file.
+            stream.set_range(1010, Some(2500)).unwrap();
+            let samples = read_blocks(&mut stream);
+            std::fs::remove_file(&path).unwrap();
+
+            let expected = 1490 * second / 1000;
+            assert!(
+                samples.len().abs_diff(expected) <= 1,
+                "{} Hz: {} samples",
+                sample_rate,
+                samples.len()
+            );
+            // The step at 2 s of the file is 990 ms into the range.
+            let step = 990 * second / 1000;
+            let at = crossing(&samples, step / 2, 0.25);
+            assert!(at.abs_diff(step) <= 2, "{} Hz: {}", sample_rate, at);
+            assert!((samples[step / 2] - 0.2).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn probe_media_reads_stream_parameters_without_decoding() {
+        let path = write_constant("kotonoha-asr-probe", &[0.0, 0.0], 3);
+        let info = probe_media(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(info.container.as_deref(), Some("wav"));
+        assert_eq!(info.sample_rate, Some(OUTPUT_SAMPLE_RATE));
+        assert_eq!(info.channels, Some(2));
+        assert_eq!(info.duration_ms, Some(3000));
+        assert_eq!(info.title, None);
+        assert!(info.chapters.is_empty());
+    }
+
+    #[test]
+    fn container_is_recognised_from_the_header() {
+        assert_eq!(container_name(b"fLaC\0\0\0\x22"), Some("flac"));
+        assert_eq!(container_name(b"\0\0\0\x20ftypM4A "), Some("mp4"));
+        assert_eq!(container_name(&[0xff, 0xf1, 0x50, 0x80]), Some("aac"));
+        assert_eq!(container_name(&[0xff, 0xfb, 0x90, 0x64]), Some("mp3"));
+        assert_eq!(container_name(b"RIFF\0\0\0\0AVI "), None);
+        assert_eq!(container_name(b""), None);
+    }
 }
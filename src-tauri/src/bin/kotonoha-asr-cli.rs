@@ -0,0 +1,612 @@
+//! Headless transcription for scripts and build servers.
+//!
+//! Runs the same decode → recognize → sentence pipeline as the desktop app, but without
+//! starting Tauri.
+//!
+//! Exit codes:
+//! - 0: every input was transcribed and written
+//! - 1: at least one input could not be read or transcribed
+//! - 2: invalid command-line arguments
+//! - 3: the model could not be loaded
+//! - 4: a transcript could not be written
+use clap::builder::{PossibleValuesParser, RangedU64ValueParser};
+use clap::{Parser, ValueEnum};
+use kotonoha_asr_lib::asr::{self, ChunkOptions, Segmentation, TimeRange, TranscriptionOptions};
+use kotonoha_asr_lib::audio_converter::{self, AudioSelection, ChannelMix};
+use kotonoha_asr_lib::diarization::DiarizationOptions;
+use kotonoha_asr_lib::export::{self, ExportFormat, SubtitleOptions, TranscriptMetadata};
+use kotonoha_asr_lib::job_queue::JobStatus;
+use kotonoha_asr_lib::models::{self, DEFAULT_MODEL_ID};
+use kotonoha_asr_lib::preprocess::{
+    Normalization, PreprocessOptions, DEFAULT_TARGET_DBFS, DEFAULT_TARGET_LUFS,
+};
+use kotonoha_asr_lib::recognizer::{DecodingMethod, DecodingOptions, Hotword, SharedRecognizer};
+use kotonoha_asr_lib::sink::{self, FileSink};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+
+const EXIT_TRANSCRIPTION_FAILED: u8 = 1;
+const EXIT_INVALID_ARGUMENTS: u8 = 2;
+const EXIT_MODEL_LOAD_FAILED: u8 = 3;
+const EXIT_OUTPUT_FAILED: u8 = 4;
+
+#[derive(Parser)]
+#[command(about = "Transcribe audio files into timestamped sentences")]
+struct Args {
+    /// Directory containing the files of the selected model
+    #[arg(long, required_unless_present = "list_tracks")]
+    model_dir: Option<PathBuf>,
+
+    /// Model ID from the built-in registry (streaming models are for live transcription only)
+    #[arg(
+        long,
+        default_value = DEFAULT_MODEL_ID,
+        value_parser = PossibleValuesParser::new(
+            models::models().iter().filter(|m| !m.is_streaming()).map(|m| m.id)
+        ),
+    )]
+    model: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Sswt)]
+    format: Format,
+
+    /// Maximum characters per subtitle line (srt and vtt only)
+    #[arg(
+        long,
+        default_value_t = SubtitleOptions::default().max_line_length,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+    )]
+    max_line_length: usize,
+
+    /// Maximum lines per subtitle cue; longer sentences are split into several cues
+    /// (srt and vtt only)
+    #[arg(
+        long,
+        default_value_t = SubtitleOptions::default().max_lines,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+    )]
+    max_lines: usize,
+
+    /// Write one transcript per input into this directory instead of stdout, named after the
+    /// input (inputs with the same file stem are rejected)
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// How to split audio before recognition. `vad` uses `silero_vad.onnx` next to the
+    /// model directory when present, or an energy-based detector otherwise. The CLI does not
+    /// download it; fetch it from the asr-models release of sherpa-onnx. `vad` holds the
+    /// whole decoded signal in memory, about 230 MB per hour of audio
+    #[arg(long, value_enum, default_value_t = SegmentationArg::Fixed)]
+    segmentation: SegmentationArg,
+
+    /// List the tracks of each input with their channels, along with the duration, tags and
+    /// chapters of the file, and exit without transcribing
+    #[arg(long)]
+    list_tracks: bool,
+
+    /// ID of the track to transcribe (see --list-tracks); the first audio track by default
+    #[arg(long)]
+    track: Option<u32>,
+
+    /// Transcribe only this channel (0-based) instead of averaging all channels
+    #[arg(long, conflicts_with = "downmix")]
+    channel: Option<usize>,
+
+    /// Comma-separated gain per channel for a custom downmix, e.g. `1,0.5`
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    downmix: Option<Vec<f32>>,
+
+    /// Transcribe each channel of the track separately and interleave the sentences by time,
+    /// labelling each with its channel (e.g. agent and customer in a call recording)
+    #[arg(long, conflicts_with_all = ["diarize", "num_speakers", "channel", "downmix"])]
+    split_channels: bool,
+
+    /// Start transcribing at this position (`SS`, `MM:SS` or `HH:MM:SS`, with optional
+    /// fractions of a second). Timestamps stay relative to the start of the file
+    #[arg(long, value_parser = parse_position)]
+    start: Option<u64>,
+
+    /// Stop transcribing at this position (same format as --start)
+    #[arg(long, value_parser = parse_position)]
+    end: Option<u64>,
+
+    /// Length of each fixed window in seconds (at least 5). With `--segmentation vad` this
+    /// is the longest speech segment passed to the recognizer
+    #[arg(long, default_value_t = ChunkOptions::default().chunk_seconds)]
+    chunk_seconds: u32,
+
+    /// Seconds shared by neighbouring windows; must be shorter than the window. Ignored for
+    /// Whisper and SenseVoice, which do not report token timestamps to trim the overlap by
+    #[arg(long, default_value_t = ChunkOptions::default().overlap_seconds)]
+    overlap_seconds: u32,
+
+    /// Remove a constant DC offset before recognition
+    #[arg(long)]
+    remove_dc: bool,
+
+    /// Cut-off in Hz of a high-pass filter applied before recognition, e.g. 80 to remove
+    /// rumble and handling noise
+    #[arg(long)]
+    high_pass: Option<f32>,
+
+    /// Normalise the level before recognition, to a peak level or to an integrated loudness.
+    /// Holds the whole decoded signal in memory, about 230 MB per hour of audio
+    #[arg(long, value_enum)]
+    normalize: Option<NormalizeArg>,
+
+    /// Target of --normalize, in dBFS for `peak` (default -1) or LUFS for `loudness`
+    /// (default -23)
+    #[arg(long, requires = "normalize", allow_negative_numbers = true)]
+    normalize_target: Option<f32>,
+
+    /// Label each sentence with a speaker. Needs the segmentation and embedding models in
+    /// `diarization/` next to the model directory. Holds the whole decoded signal in memory,
+    /// about 230 MB per hour of audio
+    #[arg(long)]
+    diarize: bool,
+
+    /// Expected number of speakers (implies --diarize); estimated automatically if omitted
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    num_speakers: Option<u32>,
+
+    /// Mark sentences whose confidence is below this value (0 to 1) as needing review
+    /// (json only). Only transducer models report confidence; with Whisper and SenseVoice it is
+    /// `null` and no sentence is marked
+    #[arg(long, value_parser = parse_review_threshold)]
+    review_threshold: Option<f32>,
+
+    /// File listing words and phrases to favour, one per line with an optional boost
+    /// (`phrase :2.0`). Only used by models that support hotwords
+    #[arg(long)]
+    hotwords: Option<PathBuf>,
+
+    /// Decoding method for transducer models. `beam` is slower but usually more accurate;
+    /// models that only support greedy search ignore it. Hotwords always use `beam`
+    #[arg(long, value_enum, default_value_t = DecodingMethodArg::Greedy)]
+    decoding_method: DecodingMethodArg,
+
+    /// Number of hypotheses kept by beam search
+    #[arg(
+        long,
+        default_value_t = DecodingOptions::default().max_active_paths,
+        value_parser = clap::value_parser!(u32).range(1..),
+    )]
+    max_active_paths: u32,
+
+    /// Penalty subtracted from the blank token's score; raise it if words are dropped
+    #[arg(long, default_value_t = DecodingOptions::default().blank_penalty, value_parser = parse_blank_penalty)]
+    blank_penalty: f32,
+
+    /// Audio or video files to transcribe
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_review_threshold(value: &str) -> Result<f32, String> {
+    let threshold: f32 = value.parse().map_err(|e| format!("{}", e))?;
+    sink::validate_review_threshold(threshold)?;
+    Ok(threshold)
+}
+
+// Parses `SS`, `MM:SS` or `HH:MM:SS` (seconds may have a fraction) into milliseconds. Minutes
+// and seconds must be below 60 once a larger unit is given.
+fn parse_position(value: &str) -> Result<u64, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() > 3 {
+        return Err(format!("{} is not SS, MM:SS or HH:MM:SS", value));
+    }
+    let (seconds, hours_and_minutes) = parts.split_last().expect("split yields one part");
+    let seconds: f64 = seconds.parse().map_err(|e| format!("{}", e))?;
+    if !seconds.is_finite() || seconds < 0.0 || (!hours_and_minutes.is_empty() && seconds >= 60.0) {
+        return Err(format!("{} is not a valid position", value));
+    }
+    let too_large = || format!("{} is too large", value);
+    let mut minutes: u64 = 0;
+    for (i, part) in hours_and_minutes.iter().enumerate() {
+        let unit: u64 = part.parse().map_err(|e| format!("{}", e))?;
+        // Only the leading field may exceed its unit, e.g. `90:00` but not `1:90:00`.
+        if i > 0 && unit >= 60 {
+            return Err(format!("{} is not a valid position", value));
+        }
+        minutes = minutes
+            .checked_mul(60)
+            .and_then(|m| m.checked_add(unit))
+            .ok_or_else(too_large)?;
+    }
+    minutes
+        .checked_mul(60_000)
+        .and_then(|ms| ms.checked_add((seconds * 1000.0).round() as u64))
+        .ok_or_else(too_large)
+}
+
+fn parse_blank_penalty(value: &str) -> Result<f32, String> {
+    let penalty: f32 = value.parse().map_err(|e| format!("{}", e))?;
+    if penalty.is_finite() && penalty >= 0.0 {
+        Ok(penalty)
+    } else {
+        Err(format!("{} is not a non-negative number", penalty))
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Sswt,
+    Text,
+    Srt,
+    Vtt,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SegmentationArg {
+    Fixed,
+    Vad,
+}
+
+impl From<SegmentationArg> for Segmentation {
+    fn from(segmentation: SegmentationArg) -> Self {
+        match segmentation {
+            SegmentationArg::Fixed => Segmentation::FixedWindow,
+            SegmentationArg::Vad => Segmentation::Vad,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DecodingMethodArg {
+    Greedy,
+    Beam,
+}
+
+impl From<DecodingMethodArg> for DecodingMethod {
+    fn from(method: DecodingMethodArg) -> Self {
+        match method {
+            DecodingMethodArg::Greedy => DecodingMethod::GreedySearch,
+            DecodingMethodArg::Beam => DecodingMethod::ModifiedBeamSearch,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NormalizeArg {
+    Peak,
+    Loudness,
+}
+
+impl From<Format> for ExportFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Sswt => ExportFormat::Sswt,
+            Format::Text => ExportFormat::Text,
+            Format::Srt => ExportFormat::Srt,
+            Format::Vtt => ExportFormat::WebVtt,
+            Format::Json => ExportFormat::Json,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.list_tracks {
+        return list_tracks(&args.inputs);
+    }
+    let model_dir = args
+        .model_dir
+        .as_deref()
+        .expect("--model-dir is required by clap unless --list-tracks is given");
+    let model = models::find_offline_model(&args.model).expect("model ID is validated by clap");
+    let format = ExportFormat::from(args.format);
+    let subtitle_options = SubtitleOptions {
+        max_line_length: args.max_line_length,
+        max_lines: args.max_lines,
+    };
+    let chunking = ChunkOptions {
+        chunk_seconds: args.chunk_seconds,
+        overlap_seconds: args.overlap_seconds,
+    };
+    if let Err(e) = chunking.validate(model) {
+        eprintln!("Invalid chunk settings: {}", e);
+        return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+    }
+    let audio = AudioSelection {
+        track_id: args.track,
+        channel_mix: match (args.channel, &args.downmix) {
+            (Some(channel), _) => ChannelMix::Single { channel },
+            (None, Some(weights)) => ChannelMix::Weights {
+                weights: weights.clone(),
+            },
+            (None, None) => ChannelMix::Average,
+        },
+    };
+    let range = (args.start.is_some() || args.end.is_some()).then_some(TimeRange {
+        start_ms: args.start.unwrap_or(0),
+        end_ms: args.end,
+    });
+    if let Some(Err(e)) = range.map(|range| range.validate()) {
+        eprintln!("Invalid range: {}", e);
+        return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+    }
+    let preprocessing = PreprocessOptions {
+        remove_dc: args.remove_dc,
+        high_pass_hz: args.high_pass,
+        normalization: args.normalize.map(|mode| match mode {
+            NormalizeArg::Peak => Normalization::Peak {
+                target_dbfs: args.normalize_target.unwrap_or(DEFAULT_TARGET_DBFS),
+            },
+            NormalizeArg::Loudness => Normalization::Loudness {
+                target_lufs: args.normalize_target.unwrap_or(DEFAULT_TARGET_LUFS),
+            },
+        }),
+    };
+    if let Err(e) = preprocessing.validate(asr::REQUIRED_SAMPLE_RATE) {
+        eprintln!("Invalid preprocessing settings: {}", e);
+        return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+    }
+    let hotwords = match &args.hotwords {
+        Some(path) => match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Hotword::parse_list(&text))
+        {
+            Ok(hotwords) => hotwords,
+            Err(e) => {
+                eprintln!("Failed to read hotwords from {}: {}", path.display(), e);
+                return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+            }
+        },
+        None => Vec::new(),
+    };
+    if !hotwords.is_empty() && !model.supports_hotwords() {
+        eprintln!(
+            "Model {} does not support hotwords; ignoring them",
+            model.id
+        );
+    }
+    if args.review_threshold.is_some() && !model.reports_confidence() {
+        eprintln!(
+            "Model {} reports no confidence; --review-threshold will not mark any sentence",
+            model.id
+        );
+    }
+    let options = TranscriptionOptions {
+        segmentation: args.segmentation.into(),
+        diarization: (args.diarize || args.num_speakers.is_some()).then_some(DiarizationOptions {
+            num_speakers: args.num_speakers,
+        }),
+        // Only affects the desktop app's `asr-progress` events, not the written transcript.
+        word_timestamps: false,
+        review_threshold: args.review_threshold,
+        hotwords,
+        decoding: Some(DecodingOptions {
+            method: args.decoding_method.into(),
+            max_active_paths: args.max_active_paths,
+            blank_penalty: args.blank_penalty,
+        }),
+        chunking: Some(chunking),
+        range,
+        audio,
+        split_channels: args.split_channels,
+        preprocessing,
+    };
+
+    let recognizer = SharedRecognizer::default();
+    if let Err(e) = recognizer.ensure_loaded(
+        model,
+        model_dir,
+        &options.decoding.unwrap_or_default(),
+        &options.hotwords,
+    ) {
+        eprintln!("Failed to load model from {}: {}", model_dir.display(), e);
+        return ExitCode::from(EXIT_MODEL_LOAD_FAILED);
+    }
+
+    let output_paths = match &args.output_dir {
+        Some(dir) => match output_paths(&args.inputs, dir, format.extension()) {
+            Ok(paths) => paths.into_iter().map(Some).collect(),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+            }
+        },
+        None => vec![None; args.inputs.len()],
+    };
+
+    // The CLI cannot cancel a file, so `transcribe_file` never reports `Cancelled` here.
+    let never_cancelled = AtomicBool::new(false);
+    let mut transcription_failed = false;
+    let mut output_failed = false;
+
+    for (input, output_path) in args.inputs.iter().zip(output_paths) {
+        eprintln!("Transcribing {}", input.display());
+        let metadata = TranscriptMetadata {
+            source_file: Some(input.to_string_lossy().into_owned()),
+            model_id: Some(model.id.to_string()),
+            confidence_available: Some(model.reports_confidence()),
+            ..TranscriptMetadata::default()
+        };
+        let mut sink = FileSink::new(output_path.clone(), format, subtitle_options, metadata);
+
+        let status = asr::transcribe_file(
+            &input.to_string_lossy(),
+            model,
+            model_dir,
+            &options,
+            &recognizer,
+            &never_cancelled,
+            &mut sink,
+        );
+
+        match (status, sink.failure()) {
+            (JobStatus::Finished, None) => {
+                if let Some(path) = output_path {
+                    eprintln!("Wrote {}", path.display());
+                }
+            }
+            (JobStatus::Finished, Some(e)) => {
+                eprintln!("{}: {}", input.display(), e);
+                output_failed = true;
+            }
+            (JobStatus::Failed, failure) => {
+                eprintln!(
+                    "Failed to transcribe {}: {}",
+                    input.display(),
+                    failure.unwrap_or("unknown error")
+                );
+                transcription_failed = true;
+            }
+            (status, _) => unreachable!("transcribe_file returned {:?}", status),
+        }
+    }
+
+    if output_failed {
+        ExitCode::from(EXIT_OUTPUT_FAILED)
+    } else if transcription_failed {
+        ExitCode::from(EXIT_TRANSCRIPTION_FAILED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// One output file per input, named after the input's stem. Inputs that would write the same
+// file (e.g. `a/talk.wav` and `b/talk.mp3`) are rejected rather than overwriting each other.
+fn output_paths(inputs: &[PathBuf], dir: &Path, extension: &str) -> Result<Vec<PathBuf>, String> {
+    let mut seen: HashMap<PathBuf, &PathBuf> = HashMap::new();
+    let mut paths = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        // Append the extension rather than use `with_extension`, which would cut a dotted stem
+        // such as `meeting.2024-05-01` at its last dot.
+        let mut name = input
+            .file_stem()
+            .unwrap_or(input.as_os_str())
+            .to_os_string();
+        name.push(".");
+        name.push(extension);
+        let path = dir.join(name);
+        if let Some(other) = seen.insert(path.clone(), input) {
+            return Err(format!(
+                "{} and {} would both be written to {}; transcribe them into separate \
+                 --output-dir directories",
+                other.display(),
+                input.display(),
+                path.display()
+            ));
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+// Prints the container, tags and chapters reported by `probe_media` above the track list.
+fn print_media_info(info: &audio_converter::MediaInfo) {
+    let mut details = Vec::new();
+    if let Some(container) = &info.container {
+        details.push(container.clone());
+    }
+    if let Some(duration_ms) = info.duration_ms {
+        details.push(export::format_timestamp(duration_ms as f32 / 1000.0));
+    }
+    for (label, value) in [
+        ("title", &info.title),
+        ("artist", &info.artist),
+        ("date", &info.recording_date),
+    ] {
+        if let Some(value) = value {
+            details.push(format!("{} {}", label, value));
+        }
+    }
+    if !details.is_empty() {
+        println!("  {}", details.join(", "));
+    }
+    for chapter in &info.chapters {
+        println!(
+            "  chapter {}: {}",
+            export::format_timestamp(chapter.start_ms as f32 / 1000.0),
+            chapter.title.as_deref().unwrap_or("")
+        );
+    }
+}
+
+fn list_tracks(inputs: &[PathBuf]) -> ExitCode {
+    let mut failed = false;
+    for input in inputs {
+        match audio_converter::list_tracks(&input.to_string_lossy()) {
+            Ok(tracks) => {
+                println!("{}", input.display());
+                if let Ok(info) = audio_converter::probe_media(&input.to_string_lossy()) {
+                    print_media_info(&info);
+                }
+                for track in tracks {
+                    println!(
+                        "  track {}{}: {}, {} Hz, {} channels{}{}",
+                        track.id,
+                        if track.is_default { " (default)" } else { "" },
+                        track.codec,
+                        track
+                            .sample_rate
+                            .map_or_else(|| "?".to_string(), |r| r.to_string()),
+                        track
+                            .channels
+                            .map_or_else(|| "?".to_string(), |c| c.to_string()),
+                        if track.channel_labels.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" ({})", track.channel_labels.join(", "))
+                        },
+                        track
+                            .language
+                            .map(|language| format!(", language {}", language))
+                            .unwrap_or_default(),
+                    );
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read tracks of {}: {}", input.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::from(EXIT_TRANSCRIPTION_FAILED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_paths_keep_dotted_stems() {
+        let inputs = [
+            PathBuf::from("/in/meeting.2024-05-01.wav"),
+            PathBuf::from("/in/meeting.2024-05-02.wav"),
+        ];
+        assert_eq!(
+            output_paths(&inputs, Path::new("/out"), "srt").unwrap(),
+            vec![
+                PathBuf::from("/out/meeting.2024-05-01.srt"),
+                PathBuf::from("/out/meeting.2024-05-02.srt"),
+            ]
+        );
+
+        let colliding = [PathBuf::from("/a/talk.wav"), PathBuf::from("/b/talk.mp3")];
+        assert!(output_paths(&colliding, Path::new("/out"), "srt").is_err());
+    }
+
+    #[test]
+    fn parse_position_bounds_fields_and_rejects_overflow() {
+        assert_eq!(parse_position("75.5"), Ok(75_500));
+        assert_eq!(parse_position("90:00"), Ok(5_400_000));
+        assert_eq!(parse_position("1:02:03.25"), Ok(3_723_250));
+
+        assert!(parse_position("1:75:99").is_err());
+        assert!(parse_position("1:00:60").is_err());
+        assert!(parse_position("10:60").is_err());
+        assert!(parse_position("1:2:3:4").is_err());
+        assert!(parse_position(&format!("{}:00:00", u64::MAX)).is_err());
+        assert!(parse_position(&format!("{}:00", u64::MAX / 60)).is_err());
+    }
+}
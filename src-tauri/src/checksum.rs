@@ -0,0 +1,328 @@
+use crate::diarization;
+use crate::models::{self, ModelInfo};
+use crate::vad;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+// ダウンロード時に確認したハッシュは `<ファイル名>.sha256` に記録する
+const CHECKSUM_SUFFIX: &str = ".sha256";
+// 照合できるハッシュがなかったファイルの記録に付ける印
+const UNVERIFIED_MARK: &str = "unverified";
+
+pub fn checksum_path_for(path: &Path) -> PathBuf {
+    let mut checksum_path = path.as_os_str().to_owned();
+    checksum_path.push(CHECKSUM_SUFFIX);
+    PathBuf::from(checksum_path)
+}
+
+/// 16進小文字の SHA-256
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    update_from_file(&mut hasher, path)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// ファイルの内容を 1MB ずつ `hasher` に渡す（大きなモデルでも全体をメモリに読み込まない）
+pub fn update_from_file(hasher: &mut Sha256, path: &Path) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..n]);
+    }
+}
+
+/// サーバーが通知するファイル全体の SHA-256
+///
+/// Hugging Face は LFS のファイルについて `X-Linked-ETag`（リダイレクト先では `ETag`）に
+/// SHA-256 を返す。Git 管理の小さなファイルの ETag は SHA-1 なので、64 桁でなければ無視する。
+pub fn expected_sha256(headers: &HeaderMap) -> Option<String> {
+    ["x-linked-etag", "etag"]
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok())
+        .map(|value| {
+            value
+                .trim_start_matches("W/")
+                .trim_matches('"')
+                .to_ascii_lowercase()
+        })
+        .find(|value| value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// ダウンロードしたファイルと照合するハッシュ
+///
+/// モデルの定義に記録したハッシュを優先し、サーバーが通知するハッシュは食い違いを警告する
+/// ための確認にだけ使う。定義に記録がないファイルはサーバーのハッシュで確認する。
+pub fn choose_expected(recorded: Option<&str>, served: Option<String>) -> Option<String> {
+    match (recorded, served) {
+        (Some(recorded), Some(served)) => {
+            if served != recorded {
+                log::warn!(
+                    "Server reports SHA-256 {}, but the model registry records {}",
+                    served,
+                    recorded
+                );
+            }
+            Some(recorded.to_string())
+        }
+        (Some(recorded), None) => Some(recorded.to_string()),
+        (None, served) => served,
+    }
+}
+
+/// ダウンロードしたファイルのハッシュを記録する
+///
+/// `verified` が偽のハッシュは、配布元の値と照合できずダウンロードしたもの自体から計算した値で、
+/// 以後の破損は検出できるが、最初から壊れていたかどうかは分からない。
+pub fn record(path: &Path, sha256: &str, verified: bool) -> std::io::Result<()> {
+    let line = if verified {
+        format!("{}\n", sha256)
+    } else {
+        format!("{} {}\n", sha256, UNVERIFIED_MARK)
+    };
+    std::fs::write(checksum_path_for(path), line)
+}
+
+// 記録したハッシュと、それが配布元の値と照合済みかどうか
+fn recorded(path: &Path) -> Option<(String, bool)> {
+    let content = std::fs::read_to_string(checksum_path_for(path)).ok()?;
+    let mut fields = content.split_whitespace();
+    let sha256 = fields.next()?.to_string();
+    Some((sha256, fields.next() != Some(UNVERIFIED_MARK)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum FileStatus {
+    Ok,
+    Missing,
+    /// 記録されたハッシュと一致しない（ファイルは削除済み）
+    Corrupted {
+        expected: String,
+        actual: String,
+    },
+    /// 配布元のハッシュと照合できないため、正しくダウンロードされたか分からない
+    /// （ダウンロード後に変わっていないことだけは確認済みの場合もある）
+    Unverified,
+}
+
+/// ハッシュと照合し、壊れていればファイルと記録を削除する
+///
+/// `registered` はモデルの定義に記録したハッシュで、なければダウンロード時の記録と照合する。
+/// 照合したのが配布元の値でなければ、一致しても `Unverified` を返す。
+pub fn verify_file(path: &Path, registered: Option<&str>) -> Result<FileStatus, String> {
+    if !path.exists() {
+        return Ok(FileStatus::Missing);
+    }
+    let Some((expected, verified)) = registered
+        .map(|sha256| (sha256.to_string(), true))
+        .or_else(|| recorded(path))
+    else {
+        return Ok(FileStatus::Unverified);
+    };
+    let actual =
+        sha256_file(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if actual == expected {
+        return Ok(if verified {
+            FileStatus::Ok
+        } else {
+            FileStatus::Unverified
+        });
+    }
+
+    log::warn!(
+        "Checksum mismatch for {}: expected {}, got {}",
+        path.display(),
+        expected,
+        actual
+    );
+    std::fs::remove_file(path)
+        .map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+    let _ = std::fs::remove_file(checksum_path_for(path));
+    Ok(FileStatus::Corrupted { expected, actual })
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    /// アプリのローカルデータディレクトリからの相対パス
+    file_path: String,
+    #[serde(flatten)]
+    status: FileStatus,
+}
+
+fn model_files(model: &ModelInfo) -> Vec<String> {
+    model
+        .files()
+        .iter()
+        .map(|file| format!("models/{}/{}", model.directory, file))
+        .collect()
+}
+
+// 話者分離モデルと Silero VAD のファイル
+fn auxiliary_files() -> Vec<String> {
+    [
+        diarization::SEGMENTATION_MODEL_FILE,
+        diarization::EMBEDDING_MODEL_FILE,
+    ]
+    .iter()
+    .map(|file| format!("models/{}/{}", diarization::DIARIZATION_MODEL_DIR, file))
+    .chain([format!("models/{}", vad::SILERO_VAD_MODEL_FILE)])
+    .collect()
+}
+
+/// ダウンロード済みのモデルファイルを検証する
+///
+/// `model_id` を指定した場合はそのモデルのファイルすべて（未ダウンロードのものは `missing`）、
+/// 省略した場合はダウンロード済みのすべてのモデルと話者分離モデル・Silero VAD を対象にする。
+#[tauri::command]
+pub fn verify_models(
+    app_handle: AppHandle,
+    model_id: Option<String>,
+) -> Result<Vec<FileReport>, String> {
+    let models_root = app_handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+
+    let file_paths = match model_id {
+        Some(model_id) => model_files(models::find_model(&model_id)?),
+        None => models::models()
+            .iter()
+            .flat_map(model_files)
+            .chain(auxiliary_files())
+            .filter(|file_path| models_root.join(file_path).exists())
+            .collect(),
+    };
+
+    file_paths
+        .into_iter()
+        .map(|file_path| {
+            let registered = models::recorded_sha256(&file_path);
+            let status = verify_file(&models_root.join(&file_path), registered)?;
+            Ok(FileReport { file_path, status })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kotonoha-checksum-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn expected_sha256_reads_linked_etag_and_ignores_sha1_etags() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "etag",
+            HeaderValue::from_static("\"0a4d55a8d778e5022fab701977c5d840bbc486d0\""),
+        );
+        assert_eq!(expected_sha256(&headers), None);
+
+        let linked = format!("\"{}\"", HELLO_SHA256.to_ascii_uppercase());
+        headers.insert("x-linked-etag", HeaderValue::from_str(&linked).unwrap());
+        assert_eq!(expected_sha256(&headers).as_deref(), Some(HELLO_SHA256));
+    }
+
+    #[test]
+    fn verify_file_deletes_corrupted_files() {
+        let good = temp_file("good.onnx", b"hello");
+        record(&good, HELLO_SHA256, true).unwrap();
+        assert_eq!(verify_file(&good, None), Ok(FileStatus::Ok));
+
+        let bad = temp_file("bad.onnx", b"hell");
+        record(&bad, HELLO_SHA256, true).unwrap();
+        assert!(matches!(
+            verify_file(&bad, None),
+            Ok(FileStatus::Corrupted { .. })
+        ));
+        assert!(!bad.exists());
+        assert!(!checksum_path_for(&bad).exists());
+        assert_eq!(verify_file(&bad, None), Ok(FileStatus::Missing));
+
+        let unrecorded = temp_file("unrecorded.onnx", b"hello");
+        assert_eq!(verify_file(&unrecorded, None), Ok(FileStatus::Unverified));
+        assert_eq!(
+            verify_file(&unrecorded, Some(HELLO_SHA256)),
+            Ok(FileStatus::Ok)
+        );
+
+        // モデルの定義に記録したハッシュは、ダウンロード時の記録より優先する
+        let tampered = temp_file("tampered.onnx", b"hell");
+        record(&tampered, "0".repeat(64).as_str(), true).unwrap();
+        assert!(matches!(
+            verify_file(&tampered, Some(HELLO_SHA256)),
+            Ok(FileStatus::Corrupted { expected, .. }) if expected == HELLO_SHA256
+        ));
+
+        for path in [good, unrecorded] {
+            std::fs::remove_file(checksum_path_for(&path)).ok();
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn hashes_not_checked_against_the_source_stay_unverified() {
+        // ダウンロードしたもの自体のハッシュは、一致しても正しいとは言えない
+        let unchecked = temp_file("unchecked.onnx", b"hello");
+        record(&unchecked, HELLO_SHA256, false).unwrap();
+        assert_eq!(verify_file(&unchecked, None), Ok(FileStatus::Unverified));
+        assert_eq!(
+            verify_file(&unchecked, Some(HELLO_SHA256)),
+            Ok(FileStatus::Ok)
+        );
+
+        // 後から変わった場合は検出できる
+        std::fs::write(&unchecked, b"hell").unwrap();
+        assert!(matches!(
+            verify_file(&unchecked, None),
+            Ok(FileStatus::Corrupted { .. })
+        ));
+        assert!(!unchecked.exists());
+    }
+
+    #[test]
+    fn recorded_hash_takes_precedence_over_the_served_one() {
+        let other = "0".repeat(64);
+        assert_eq!(
+            choose_expected(Some(HELLO_SHA256), Some(other.clone())).as_deref(),
+            Some(HELLO_SHA256)
+        );
+        assert_eq!(choose_expected(None, Some(other.clone())), Some(other));
+        assert_eq!(choose_expected(None, None), None);
+    }
+
+    #[test]
+    fn update_from_file_streams_the_whole_file() {
+        let content: Vec<u8> = (0..3 * 1024 * 1024 + 7).map(|i| i as u8).collect();
+        let path = temp_file("large.onnx", &content);
+        let mut hasher = Sha256::new();
+        update_from_file(&mut hasher, &path).unwrap();
+        assert_eq!(
+            to_hex(&hasher.finalize()),
+            to_hex(&Sha256::digest(&content))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
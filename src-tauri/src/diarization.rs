@@ -0,0 +1,217 @@
+use crate::asr::Sentence;
+use crate::sink::TranscriptionSink;
+use sherpa_rs::diarize::{Diarize, DiarizeConfig};
+use std::path::Path;
+
+/// `models` ディレクトリ直下の話者分離モデルの置き場所
+pub const DIARIZATION_MODEL_DIR: &str = "diarization";
+pub const SEGMENTATION_MODEL_FILE: &str = "pyannote-segmentation-3-0.onnx";
+pub const EMBEDDING_MODEL_FILE: &str = "nemo_en_titanet_small.onnx";
+
+// 話者数が指定されていないときのクラスタリングの閾値（小さいほど話者が増える）
+const CLUSTERING_THRESHOLD: f32 = 0.5;
+const MIN_DURATION_ON: f32 = 0.3;
+const MIN_DURATION_OFF: f32 = 0.5;
+
+/// ジョブごとの話者分離の設定
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiarizationOptions {
+    /// 想定される話者数（`None` なら自動推定）
+    pub num_speakers: Option<u32>,
+}
+
+/// ある話者が話している区間（秒）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeakerTurn {
+    pub start: f32,
+    pub end: f32,
+    pub speaker: u32,
+}
+
+/// 音声全体を話者ごとの区間に分ける
+pub fn diarize(
+    samples: &[f32],
+    diarization_model_dir: &Path,
+    options: &DiarizationOptions,
+) -> Result<Vec<SpeakerTurn>, String> {
+    let segmentation_model = diarization_model_dir.join(SEGMENTATION_MODEL_FILE);
+    let embedding_model = diarization_model_dir.join(EMBEDDING_MODEL_FILE);
+    for model in [&segmentation_model, &embedding_model] {
+        if !model.exists() {
+            return Err(format!(
+                "話者分離モデルが見つかりません: {}",
+                model.display()
+            ));
+        }
+    }
+
+    let config = DiarizeConfig {
+        num_clusters: options.num_speakers.map(|n| n as i32),
+        threshold: Some(CLUSTERING_THRESHOLD),
+        min_duration_on: Some(MIN_DURATION_ON),
+        min_duration_off: Some(MIN_DURATION_OFF),
+        ..Default::default()
+    };
+    let mut diarizer =
+        Diarize::new(&segmentation_model, &embedding_model, config).map_err(|e| e.to_string())?;
+    let segments = diarizer
+        .compute(samples.to_vec(), None)
+        .map_err(|e| e.to_string())?;
+
+    Ok(segments
+        .into_iter()
+        .map(|segment| SpeakerTurn {
+            start: segment.start,
+            end: segment.end,
+            speaker: segment.speaker.max(0) as u32,
+        })
+        .collect())
+}
+
+/// 文と最も長く重なる話者を割り当てる
+///
+/// どの区間とも重ならない文（話者分離が短い発話を落とした場合など）は、最も近い区間の話者にする。
+pub fn assign_speaker(sentence: &Sentence, turns: &[SpeakerTurn]) -> Option<u32> {
+    let overlap = |turn: &SpeakerTurn| turn.end.min(sentence.end) - turn.start.max(sentence.start);
+    let distance = |turn: &SpeakerTurn| {
+        (turn.start - sentence.end)
+            .max(sentence.start - turn.end)
+            .max(0.0)
+    };
+
+    let mut overlaps: Vec<(u32, f32)> = Vec::new();
+    for turn in turns.iter().filter(|turn| overlap(turn) > 0.0) {
+        match overlaps
+            .iter_mut()
+            .find(|(speaker, _)| *speaker == turn.speaker)
+        {
+            Some((_, total)) => *total += overlap(turn),
+            None => overlaps.push((turn.speaker, overlap(turn))),
+        }
+    }
+
+    match overlaps.iter().max_by(|a, b| a.1.total_cmp(&b.1)) {
+        Some((speaker, _)) => Some(*speaker),
+        None => turns
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .map(|turn| turn.speaker),
+    }
+}
+
+/// 文に話者を付けてから下流のシンクへ渡す
+pub struct SpeakerLabelingSink<'a> {
+    inner: &'a mut dyn TranscriptionSink,
+    turns: Vec<SpeakerTurn>,
+}
+
+impl<'a> SpeakerLabelingSink<'a> {
+    pub fn new(inner: &'a mut dyn TranscriptionSink, turns: Vec<SpeakerTurn>) -> Self {
+        SpeakerLabelingSink { inner, turns }
+    }
+
+    fn label(&self, sentence: &Sentence) -> Sentence {
+        Sentence {
+            speaker: assign_speaker(sentence, &self.turns),
+            ..sentence.clone()
+        }
+    }
+
+    fn label_all(&self, sentences: &[Sentence]) -> Vec<Sentence> {
+        sentences.iter().map(|s| self.label(s)).collect()
+    }
+}
+
+impl TranscriptionSink for SpeakerLabelingSink<'_> {
+    fn started(&mut self, total_duration_ms: u64) {
+        self.inner.started(total_duration_ms);
+    }
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        let labeled = self.label(sentence);
+        self.inner.sentence(&labeled);
+    }
+
+    fn finished(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        let labeled = self.label_all(sentences);
+        self.inner.finished(&labeled, processing_time_ms);
+    }
+
+    fn cancelled(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        let labeled = self.label_all(sentences);
+        self.inner.cancelled(&labeled, processing_time_ms);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.inner.error(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemorySink;
+
+    fn sentence(start: f32, end: f32) -> Sentence {
+        Sentence {
+            start,
+            end,
+            text: String::new(),
+            speaker: None,
+            channel: None,
+            words: Vec::new(),
+            confidence: None,
+            needs_review: false,
+        }
+    }
+
+    fn turn(start: f32, end: f32, speaker: u32) -> SpeakerTurn {
+        SpeakerTurn {
+            start,
+            end,
+            speaker,
+        }
+    }
+
+    #[test]
+    fn assign_speaker_picks_longest_total_overlap() {
+        let turns = [
+            turn(0.0, 2.0, 0),
+            turn(2.0, 3.5, 1),
+            turn(3.5, 4.0, 0),
+            turn(4.0, 6.0, 1),
+        ];
+
+        assert_eq!(assign_speaker(&sentence(0.5, 2.5), &turns), Some(0));
+        assert_eq!(assign_speaker(&sentence(1.5, 5.0), &turns), Some(1));
+    }
+
+    #[test]
+    fn assign_speaker_falls_back_to_nearest_turn() {
+        let turns = [turn(0.0, 1.0, 0), turn(5.0, 6.0, 1)];
+
+        assert_eq!(assign_speaker(&sentence(1.2, 2.0), &turns), Some(0));
+        assert_eq!(assign_speaker(&sentence(4.0, 4.8), &turns), Some(1));
+        assert_eq!(assign_speaker(&sentence(4.0, 4.8), &[]), None);
+    }
+
+    #[test]
+    fn labeling_sink_labels_streamed_and_final_sentences() {
+        let mut memory = MemorySink::default();
+        let mut sink =
+            SpeakerLabelingSink::new(&mut memory, vec![turn(0.0, 2.0, 0), turn(2.0, 4.0, 1)]);
+
+        sink.sentence(&sentence(0.0, 1.8));
+        sink.cancelled(&[sentence(0.0, 1.8), sentence(2.1, 3.9)], 0);
+
+        assert_eq!(memory.sentences()[0].speaker, Some(0));
+        match memory.events.last() {
+            Some(crate::sink::TranscriptionEvent::Cancelled { sentences }) => {
+                let speakers: Vec<_> = sentences.iter().map(|s| s.speaker).collect();
+                assert_eq!(speakers, vec![Some(0), Some(1)]);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use crate::checksum;
+use crate::models;
 use futures_util::StreamExt;
 use reqwest;
-use std::fs::File;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Emitter, Manager};
 
 #[derive(Clone, serde::Serialize)]
@@ -13,12 +18,14 @@ struct DownloadProgressPayload {
     total: u64,
 }
 
+/// `url` を `file_path` にダウンロードし、配布元のハッシュと照合できたかどうかを返す
+/// （照合できなかった場合は `unverified`）
 #[tauri::command]
 pub async fn download_model_file_stream(
     app_handle: AppHandle,
     url: String,
     file_path: String,
-) -> Result<(), String> {
+) -> Result<checksum::FileStatus, String> {
     let app_data_dir = app_handle
         .path()
         .app_local_data_dir()
@@ -32,79 +39,205 @@ pub async fn download_model_file_stream(
             .map_err(|e| format!("Failed to create directory: {}", e))?;
     }
 
+    // 途中までのデータは .part に書き、完了してから本来の名前に移動する
+    let part_path = part_path_for(&full_path);
+    let resume_from = std::fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+
     // HTTPクライアントを作成
     let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
+    let mut request = client.get(&url);
+    if resume_from > 0 {
+        log::info!("Resuming download of {} from byte {}", url, resume_from);
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let mut response = request
         .send()
         .await
         .map_err(|e| format!("Failed to send request: {}", e))?;
 
-    if !response.status().is_success() {
+    // .part が既に完全なサイズなら範囲外になる。サーバーが示す全体のサイズと一致すれば
+    // ダウンロードし直さずに検証へ進み、一致しなければ最初からやり直す
+    let mut part_is_complete = false;
+    if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        if complete_length(response.headers()) == Some(resume_from) {
+            log::info!("{} is already fully downloaded", file_path);
+            part_is_complete = true;
+        } else {
+            log::warn!("Range not satisfiable for {}, restarting download", url);
+            response = client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to send request: {}", e))?;
+        }
+    }
+
+    if !part_is_complete && !response.status().is_success() {
         return Err(format!("HTTP error: {}", response.status()));
     }
 
-    let total_size = response.content_length().unwrap_or(0);
-
-    // ファイルを作成
-    let mut file = File::create(&full_path).map_err(|e| format!("Failed to create file: {}", e))?;
-
-    let mut downloaded = 0u64;
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
-
-        file.write_all(&chunk)
-            .map_err(|e| format!("Failed to write chunk: {}", e))?;
-
-        downloaded += chunk.len() as u64;
-
-        // 進捗を報告（1MBごとまたは完了時）
-        if downloaded % (1024 * 1024) == 0 || downloaded == total_size {
-            let progress = if total_size > 0 {
-                (downloaded as f64 / total_size as f64 * 100.0) as u32
-            } else {
-                0
-            };
-
-            app_handle
-                .emit(
-                    "download_progress",
-                    DownloadProgressPayload {
-                        file_name: file_path
-                            .split('/')
-                            .last()
-                            .unwrap_or(&file_path)
-                            .to_string(),
-                        progress,
-                        downloaded,
-                        total: total_size,
-                    },
-                )
-                .unwrap();
+    // Range に対応していないサーバーは 200 で全体を返すので、その場合は追記せず書き直す
+    let resumed = part_is_complete || response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let already_downloaded = if resumed { resume_from } else { 0 };
+    let total_size = if part_is_complete {
+        resume_from
+    } else {
+        response
+            .content_length()
+            .map(|len| len + already_downloaded)
+            .unwrap_or(0)
+    };
+
+    let served_sha256 = checksum::expected_sha256(response.headers());
+
+    // ハッシュは再開前に書き込んだ分も含めて計算する
+    let mut hasher = Sha256::new();
+    if resumed {
+        checksum::update_from_file(&mut hasher, &part_path)
+            .map_err(|e| format!("Failed to read partial file: {}", e))?;
+    }
+
+    let mut downloaded = already_downloaded;
+    if !part_is_complete {
+        // ファイルを作成
+        let mut file = if resumed {
+            OpenOptions::new().append(true).open(&part_path)
+        } else {
+            File::create(&part_path)
         }
+        .map_err(|e| format!("Failed to create file: {}", e))?;
+
+        let mut stream = response.bytes_stream();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+
+            file.write_all(&chunk)
+                .map_err(|e| format!("Failed to write chunk: {}", e))?;
+            hasher.update(&chunk);
+
+            downloaded += chunk.len() as u64;
+
+            // 進捗を報告（1MBごとまたは完了時）
+            if downloaded % (1024 * 1024) == 0 || downloaded == total_size {
+                let progress = if total_size > 0 {
+                    (downloaded as f64 / total_size as f64 * 100.0) as u32
+                } else {
+                    0
+                };
+
+                emit_progress(&app_handle, &file_path, progress, downloaded, total_size);
+            }
+        }
+
+        file.flush()
+            .map_err(|e| format!("Failed to flush file: {}", e))?;
+    }
+
+    // 接続が途中で切れた場合は .part を残し、次回の再開に使う
+    if total_size > 0 && downloaded != total_size {
+        return Err(format!(
+            "Incomplete download of {}: got {} of {} bytes",
+            file_path, downloaded, total_size
+        ));
     }
 
-    file.flush()
-        .map_err(|e| format!("Failed to flush file: {}", e))?;
+    let actual_sha256 = checksum::to_hex(&hasher.finalize());
+    let expected_sha256 =
+        checksum::choose_expected(models::recorded_sha256(&file_path), served_sha256);
+    let verified = match expected_sha256 {
+        Some(expected) if expected != actual_sha256 => {
+            let _ = std::fs::remove_file(&part_path);
+            return Err(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                file_path, expected, actual_sha256
+            ));
+        }
+        Some(_) => true,
+        None => {
+            log::warn!(
+                "No expected SHA-256 for {}; the download cannot be verified",
+                file_path
+            );
+            false
+        }
+    };
+
+    std::fs::rename(&part_path, &full_path)
+        .map_err(|e| format!("Failed to move downloaded file into place: {}", e))?;
+    // 照合できなかったファイルも、以後の破損を検出できるよう未検証の印を付けて記録しておく
+    checksum::record(&full_path, &actual_sha256, verified)
+        .map_err(|e| format!("Failed to record checksum: {}", e))?;
 
     // 完了を報告
-    app_handle
-        .emit(
-            "download_progress",
-            DownloadProgressPayload {
-                file_name: file_path
-                    .split('/')
-                    .last()
-                    .unwrap_or(&file_path)
-                    .to_string(),
-                progress: 100,
-                downloaded,
-                total: total_size,
-            },
-        )
-        .unwrap();
-
-    Ok(())
+    emit_progress(&app_handle, &file_path, 100, downloaded, total_size);
+
+    Ok(if verified {
+        checksum::FileStatus::Ok
+    } else {
+        checksum::FileStatus::Unverified
+    })
+}
+
+// 進捗の通知に失敗してもダウンロードは続ける（通知できないだけで、ファイルは正しく書ける）
+fn emit_progress(
+    app_handle: &AppHandle,
+    file_path: &str,
+    progress: u32,
+    downloaded: u64,
+    total: u64,
+) {
+    let payload = DownloadProgressPayload {
+        file_name: file_path
+            .rsplit('/')
+            .next()
+            .unwrap_or(file_path)
+            .to_string(),
+        progress,
+        downloaded,
+        total,
+    };
+    if let Err(e) = app_handle.emit("download_progress", payload) {
+        log::warn!("Failed to emit download progress for {}: {}", file_path, e);
+    }
+}
+
+fn part_path_for(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+// 416 の `Content-Range: bytes */<全体のサイズ>` から全体のサイズを読む
+fn complete_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes */")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn complete_length_reads_unsatisfied_content_range() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(complete_length(&headers), None);
+        headers.insert(
+            reqwest::header::CONTENT_RANGE,
+            HeaderValue::from_static("bytes */1234"),
+        );
+        assert_eq!(complete_length(&headers), Some(1234));
+        headers.insert(
+            reqwest::header::CONTENT_RANGE,
+            HeaderValue::from_static("bytes 0-99/1234"),
+        );
+        assert_eq!(complete_length(&headers), None);
+    }
 }
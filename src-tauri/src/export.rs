@@ -0,0 +1,493 @@
+use crate::asr::{Sentence, Word};
+
+/// Output formats for a finished transcription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// "Simple Subtitle With Timestamp": `[HH:MM:SS.mmm -> HH:MM:SS.mmm] text` per line,
+    /// the same format the app saves.
+    Sswt,
+    /// Sentence text only, one sentence per line.
+    Text,
+    /// SubRip subtitles, with long sentences split into several cues.
+    Srt,
+    /// WebVTT subtitles, with long sentences split into several cues.
+    WebVtt,
+    /// Structured JSON with word-level timings and metadata, described in
+    /// `doc/transcript_json_schema.md`.
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Sswt => "sswt",
+            ExportFormat::Text => "txt",
+            ExportFormat::Srt => "srt",
+            ExportFormat::WebVtt => "vtt",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Layout limits for subtitle cues. Ignored by the non-subtitle formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SubtitleOptions {
+    /// Maximum number of characters on one line.
+    pub max_line_length: usize,
+    /// Maximum number of lines in one cue. Sentences that need more lines are split into
+    /// several cues.
+    pub max_lines: usize,
+}
+
+impl Default for SubtitleOptions {
+    // The usual broadcast limits: two lines of at most 42 characters.
+    fn default() -> Self {
+        SubtitleOptions {
+            max_line_length: 42,
+            max_lines: 2,
+        }
+    }
+}
+
+/// Version of the JSON export schema. Bump it whenever a field is removed, renamed or changes
+/// meaning; adding a field does not need a new version.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Information about the transcription run, written into the JSON export. Every field is
+/// optional because a transcript saved from the frontend may no longer know all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranscriptMetadata {
+    /// Path of the transcribed audio file.
+    pub source_file: Option<String>,
+    /// Length of the audio in milliseconds.
+    pub duration_ms: Option<u64>,
+    /// ID of the model that produced the transcript.
+    pub model_id: Option<String>,
+    /// Wall-clock time the transcription took, in milliseconds.
+    pub processing_time_ms: Option<u64>,
+    /// Whether the model reports token probabilities. When it does not, every `confidence`
+    /// is `null` and no sentence is marked for review.
+    pub confidence_available: Option<bool>,
+}
+
+/// A word with its timings in milliseconds, as exchanged with the frontend and written into
+/// the JSON export.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordTiming {
+    pub text: String,
+    pub start_time_ms: u64,
+    pub end_time_ms: u64,
+    /// Recognizer confidence between 0 and 1, or `None` if the model reports none.
+    #[serde(default)]
+    pub confidence: Option<f32>,
+}
+
+impl From<&Word> for WordTiming {
+    fn from(word: &Word) -> Self {
+        WordTiming {
+            text: word.text.clone(),
+            start_time_ms: seconds_to_ms(word.start_time),
+            end_time_ms: seconds_to_ms(word.end_time),
+            confidence: word.confidence,
+        }
+    }
+}
+
+impl From<WordTiming> for Word {
+    fn from(word: WordTiming) -> Self {
+        Word {
+            start_time: word.start_time_ms as f32 / 1000.0,
+            end_time: word.end_time_ms as f32 / 1000.0,
+            text: word.text,
+            confidence: word.confidence,
+        }
+    }
+}
+
+/// Converts seconds to whole milliseconds the same way the `asr-progress` payload does.
+pub fn seconds_to_ms(seconds: f32) -> u64 {
+    (seconds * 1000.0) as u64
+}
+
+/// Formats seconds as `HH:MM:SS.mmm`, matching `formatTime` in the frontend.
+pub fn format_timestamp(seconds: f32) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0) as u64;
+    let ms = total_ms % 1000;
+    let total_secs = total_ms / 1000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        total_secs / 3600,
+        (total_secs / 60) % 60,
+        total_secs % 60,
+        ms
+    )
+}
+
+/// Formats a speaker ID as a 1-based label, matching `formatSpeaker` in the frontend.
+pub fn format_speaker(speaker: u32) -> String {
+    format!("Speaker {}", speaker + 1)
+}
+
+// Sentence text, prefixed with `Speaker N: ` when diarization assigned a speaker.
+fn labeled_text(sentence: &Sentence) -> String {
+    match sentence.speaker {
+        Some(speaker) => format!("{}: {}", format_speaker(speaker), sentence.text),
+        None => sentence.text.clone(),
+    }
+}
+
+/// Formats milliseconds as a subtitle timecode, `HH:MM:SS,mmm` for SRT and `HH:MM:SS.mmm` for
+/// WebVTT.
+fn format_timecode(ms: u64, fraction_separator: char) -> String {
+    let total_secs = ms / 1000;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        total_secs / 3600,
+        (total_secs / 60) % 60,
+        total_secs % 60,
+        fraction_separator,
+        ms % 1000
+    )
+}
+
+// Wraps text into lines of at most `max_len` characters, breaking at whitespace. Words longer
+// than a line, such as Japanese text without spaces, are broken between characters.
+fn wrap_lines(text: &str, max_len: usize) -> Vec<String> {
+    let max_len = max_len.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_len = 0;
+    for word in text.split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+        for piece in chars.chunks(max_len) {
+            if line_len > 0 && line_len + 1 + piece.len() > max_len {
+                lines.push(std::mem::take(&mut line));
+                line_len = 0;
+            }
+            if line_len > 0 {
+                line.push(' ');
+                line_len += 1;
+            }
+            line.extend(piece);
+            line_len += piece.len();
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+struct Cue {
+    start_ms: u64,
+    end_ms: u64,
+    lines: Vec<String>,
+}
+
+// Splits a sentence into cues that fit the layout limits. The sentence's time span is shared
+// between the cues in proportion to the number of characters in each.
+fn split_into_cues(text: &str, start: f32, end: f32, options: &SubtitleOptions) -> Vec<Cue> {
+    let lines = wrap_lines(text, options.max_line_length);
+    let groups: Vec<&[String]> = lines.chunks(options.max_lines.max(1)).collect();
+    let char_count = |group: &[String]| -> usize { group.iter().map(|l| l.chars().count()).sum() };
+    let total_chars: usize = groups.iter().map(|g| char_count(g)).sum();
+
+    let start_ms = (start.max(0.0) * 1000.0).round() as u64;
+    let end_ms = ((end.max(0.0) * 1000.0).round() as u64).max(start_ms);
+    let duration_ms = end_ms - start_ms;
+
+    let mut cues = Vec::new();
+    let mut chars_before = 0;
+    for group in groups {
+        let cue_start = start_ms + duration_ms * chars_before as u64 / total_chars as u64;
+        chars_before += char_count(group);
+        let cue_end = start_ms + duration_ms * chars_before as u64 / total_chars as u64;
+        cues.push(Cue {
+            start_ms: cue_start,
+            end_ms: cue_end,
+            lines: group.to_vec(),
+        });
+    }
+    cues
+}
+
+// WebVTT cue text must not contain raw `&`, `<` or `>`.
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn render_srt(sentences: &[Sentence], options: &SubtitleOptions) -> String {
+    let mut out = String::new();
+    let cues = sentences
+        .iter()
+        .flat_map(|s| split_into_cues(&labeled_text(s), s.start, s.end, options));
+    for (i, cue) in cues.enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n",
+            i + 1,
+            format_timecode(cue.start_ms, ','),
+            format_timecode(cue.end_ms, ',')
+        ));
+        for line in &cue.lines {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn render_vtt(sentences: &[Sentence], options: &SubtitleOptions) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for sentence in sentences {
+        // The speaker goes into a voice tag on every cue instead of into the wrapped text.
+        let voice = sentence
+            .speaker
+            .map(|speaker| format!("<v {}>", format_speaker(speaker)))
+            .unwrap_or_default();
+        for cue in split_into_cues(&sentence.text, sentence.start, sentence.end, options) {
+            out.push_str(&format!(
+                "{} --> {}\n{}",
+                format_timecode(cue.start_ms, '.'),
+                format_timecode(cue.end_ms, '.'),
+                voice
+            ));
+            for line in &cue.lines {
+                out.push_str(&escape_vtt(line));
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonTranscript<'a> {
+    schema_version: u32,
+    metadata: &'a TranscriptMetadata,
+    sentences: Vec<JsonSentence<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSentence<'a> {
+    text: &'a str,
+    start_time_ms: u64,
+    end_time_ms: u64,
+    speaker: Option<u32>,
+    channel: Option<u32>,
+    confidence: Option<f32>,
+    needs_review: bool,
+    words: Vec<WordTiming>,
+}
+
+fn render_json(sentences: &[Sentence], metadata: &TranscriptMetadata) -> String {
+    let transcript = JsonTranscript {
+        schema_version: JSON_SCHEMA_VERSION,
+        metadata,
+        sentences: sentences
+            .iter()
+            .map(|s| JsonSentence {
+                text: &s.text,
+                start_time_ms: seconds_to_ms(s.start),
+                end_time_ms: seconds_to_ms(s.end),
+                speaker: s.speaker,
+                channel: s.channel,
+                confidence: s.confidence,
+                needs_review: s.needs_review,
+                words: s.words.iter().map(WordTiming::from).collect(),
+            })
+            .collect(),
+    };
+    // Serializing plain structs of strings and numbers cannot fail.
+    let mut out = serde_json::to_string_pretty(&transcript).expect("transcript is serializable");
+    out.push('\n');
+    out
+}
+
+pub fn render(format: ExportFormat, sentences: &[Sentence]) -> String {
+    render_with_options(
+        format,
+        sentences,
+        &TranscriptMetadata::default(),
+        &SubtitleOptions::default(),
+    )
+}
+
+/// Renders the sentences in `format`. `metadata` is only used by the JSON format and
+/// `options` only by the subtitle formats.
+pub fn render_with_options(
+    format: ExportFormat,
+    sentences: &[Sentence],
+    metadata: &TranscriptMetadata,
+    options: &SubtitleOptions,
+) -> String {
+    match format {
+        ExportFormat::Srt => return render_srt(sentences, options),
+        ExportFormat::WebVtt => return render_vtt(sentences, options),
+        ExportFormat::Json => return render_json(sentences, metadata),
+        ExportFormat::Sswt | ExportFormat::Text => {}
+    }
+
+    let mut out = String::new();
+    for sentence in sentences {
+        match format {
+            ExportFormat::Sswt => out.push_str(&format!(
+                "[{} -> {}] {}",
+                format_timestamp(sentence.start),
+                format_timestamp(sentence.end),
+                labeled_text(sentence)
+            )),
+            _ => out.push_str(&labeled_text(sentence)),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentence(text: &str, start: f32, end: f32, speaker: Option<u32>) -> Sentence {
+        Sentence {
+            start,
+            end,
+            text: text.to_string(),
+            speaker,
+            channel: None,
+            words: Vec::new(),
+            confidence: None,
+            needs_review: false,
+        }
+    }
+
+    #[test]
+    fn timecodes_use_format_specific_separator() {
+        assert_eq!(format_timecode(3_723_045, ','), "01:02:03,045");
+        assert_eq!(format_timecode(3_723_045, '.'), "01:02:03.045");
+    }
+
+    #[test]
+    fn wrap_lines_breaks_at_spaces_and_inside_long_words() {
+        assert_eq!(
+            wrap_lines("the quick brown fox jumps", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(
+            wrap_lines("これは改行のない長い日本語の文です", 8),
+            vec!["これは改行のない", "長い日本語の文で", "す"]
+        );
+    }
+
+    #[test]
+    fn srt_splits_long_sentences_into_timed_cues() {
+        let options = SubtitleOptions {
+            max_line_length: 10,
+            max_lines: 1,
+        };
+        let srt = render_with_options(
+            ExportFormat::Srt,
+            &[sentence("aaaa bbbb cccc dddd", 1.0, 3.0, None)],
+            &TranscriptMetadata::default(),
+            &options,
+        );
+        assert_eq!(
+            srt,
+            "1\n00:00:01,000 --> 00:00:02,000\naaaa bbbb\n\n\
+             2\n00:00:02,000 --> 00:00:03,000\ncccc dddd\n\n"
+        );
+    }
+
+    #[test]
+    fn vtt_has_header_voice_tags_and_escaped_text() {
+        let vtt = render(
+            ExportFormat::WebVtt,
+            &[sentence("Q&A <live>", 0.5, 1.25, Some(0))],
+        );
+        assert_eq!(
+            vtt,
+            "WEBVTT\n\n00:00:00.500 --> 00:00:01.250\n<v Speaker 1>Q&amp;A &lt;live&gt;\n\n"
+        );
+    }
+
+    #[test]
+    fn json_has_schema_version_metadata_and_word_timings() {
+        let mut hello = sentence("Hello world.", 1.0, 2.5, Some(1));
+        hello.words = vec![
+            Word {
+                start_time: 1.0,
+                end_time: 1.5,
+                text: "Hello".to_string(),
+                confidence: Some(0.5),
+            },
+            Word {
+                start_time: 2.0,
+                end_time: 2.5,
+                text: "world.".to_string(),
+                confidence: Some(0.25),
+            },
+        ];
+        hello.confidence = Some(0.375);
+        hello.needs_review = true;
+        let metadata = TranscriptMetadata {
+            source_file: Some("/tmp/talk.wav".to_string()),
+            duration_ms: Some(3000),
+            model_id: Some("parakeet-tdt-0.6b-v2".to_string()),
+            processing_time_ms: Some(420),
+            confidence_available: Some(true),
+        };
+        let json = render_with_options(
+            ExportFormat::Json,
+            &[hello],
+            &metadata,
+            &SubtitleOptions::default(),
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "schemaVersion": 1,
+                "metadata": {
+                    "sourceFile": "/tmp/talk.wav",
+                    "durationMs": 3000,
+                    "modelId": "parakeet-tdt-0.6b-v2",
+                    "processingTimeMs": 420,
+                    "confidenceAvailable": true
+                },
+                "sentences": [{
+                    "text": "Hello world.",
+                    "startTimeMs": 1000,
+                    "endTimeMs": 2500,
+                    "speaker": 1,
+                    "channel": null,
+                    "confidence": 0.375,
+                    "needsReview": true,
+                    "words": [
+                        {
+                            "text": "Hello",
+                            "startTimeMs": 1000,
+                            "endTimeMs": 1500,
+                            "confidence": 0.5
+                        },
+                        {
+                            "text": "world.",
+                            "startTimeMs": 2000,
+                            "endTimeMs": 2500,
+                            "confidence": 0.25
+                        }
+                    ]
+                }]
+            })
+        );
+    }
+}
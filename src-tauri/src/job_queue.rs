@@ -0,0 +1,595 @@
+use crate::asr::{self, TranscriptionOptions};
+use crate::models::{self, ModelInfo};
+use crate::recognizer::{EngineSettings, SharedRecognizer};
+use crate::settings;
+use crate::sink::{self, TauriEventSink, TranscriptionSink};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Manager, State};
+
+const DEFAULT_CONCURRENCY: usize = 1;
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    id: JobId,
+    file_path: String,
+    model_id: &'static str,
+    status: JobStatus,
+}
+
+struct Job {
+    id: JobId,
+    file_path: String,
+    model: &'static ModelInfo,
+    options: TranscriptionOptions,
+    // 同じ認識器で同時に実行できるかの判定に使う
+    engine_settings: EngineSettings,
+    status: JobStatus,
+    cancel_requested: Arc<AtomicBool>,
+}
+
+// 一覧に残す終了済み（完了・失敗・キャンセル）ジョブの最大数。超えたら古いものから取り除く
+const MAX_FINISHED_JOBS: usize = 100;
+
+// ワーカースレッドで開始するジョブ
+struct JobLaunch {
+    id: JobId,
+    file_path: String,
+    model: &'static ModelInfo,
+    options: TranscriptionOptions,
+    cancel_requested: Arc<AtomicBool>,
+}
+
+// `cancel` の結果
+#[derive(Debug, PartialEq)]
+enum CancelAction {
+    /// 実行中のジョブに停止を要求した（チャンクの合間でフラグを確認して停止する）
+    Requested,
+    /// 待機中のジョブをキャンセル済みにした（呼び出し側がキャンセルを通知する）
+    Dequeued,
+}
+
+// キューの状態。ジョブの開始やイベントの送信は呼び出し側がロックを外してから行う
+struct QueueInner {
+    jobs: Vec<Job>,
+    next_id: JobId,
+    concurrency: usize,
+}
+
+impl Default for QueueInner {
+    fn default() -> Self {
+        QueueInner {
+            jobs: Vec::new(),
+            next_id: 1,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+}
+
+impl QueueInner {
+    fn count(&self, status: JobStatus) -> usize {
+        self.jobs.iter().filter(|j| j.status == status).count()
+    }
+
+    fn find_mut(&mut self, job_id: JobId) -> Result<&mut Job, String> {
+        self.jobs
+            .iter_mut()
+            .find(|j| j.id == job_id)
+            .ok_or_else(|| format!("job not found: {}", job_id))
+    }
+
+    fn enqueue(
+        &mut self,
+        file_paths: Vec<String>,
+        model: &'static ModelInfo,
+        options: TranscriptionOptions,
+    ) -> Vec<JobId> {
+        let engine_settings = EngineSettings::new(
+            model,
+            &options.decoding.unwrap_or_default(),
+            &options.hotwords,
+        );
+        file_paths
+            .into_iter()
+            .map(|file_path| {
+                let id = self.next_id;
+                self.next_id += 1;
+                log::info!("Queued ASR job {} for: {}", id, file_path);
+                self.jobs.push(Job {
+                    id,
+                    file_path,
+                    model,
+                    options: options.clone(),
+                    engine_settings: engine_settings.clone(),
+                    status: JobStatus::Queued,
+                    cancel_requested: Arc::new(AtomicBool::new(false)),
+                });
+                id
+            })
+            .collect()
+    }
+
+    // 空きがある限り待機中のジョブを実行中にし、開始すべきジョブを返す
+    //
+    // 認識器は1つのモデル・デコード設定・ホットワードの組しか保持できないため、実行中の
+    // ジョブとこれらが異なるジョブは、実行中のジョブがすべて終わるまで待たせる（順番は追い越さない）。
+    fn start_ready(&mut self) -> Vec<JobLaunch> {
+        let mut launches = Vec::new();
+        while self.count(JobStatus::Running) < self.concurrency {
+            let running_settings = self
+                .jobs
+                .iter()
+                .find(|j| j.status == JobStatus::Running)
+                .map(|j| j.engine_settings.clone());
+            let Some(job) = self.jobs.iter_mut().find(|j| j.status == JobStatus::Queued) else {
+                break;
+            };
+            if running_settings.is_some_and(|settings| settings != job.engine_settings) {
+                break;
+            }
+            job.status = JobStatus::Running;
+            launches.push(JobLaunch {
+                id: job.id,
+                file_path: job.file_path.clone(),
+                model: job.model,
+                options: job.options.clone(),
+                cancel_requested: job.cancel_requested.clone(),
+            });
+        }
+        launches
+    }
+
+    // ジョブの終了を記録し、処理するジョブが残っていなければ true を返す
+    fn finish(&mut self, job_id: JobId, status: JobStatus) -> bool {
+        if let Ok(job) = self.find_mut(job_id) {
+            job.status = status;
+        }
+        self.prune_finished(MAX_FINISHED_JOBS);
+        self.count(JobStatus::Running) == 0 && self.count(JobStatus::Queued) == 0
+    }
+
+    fn is_finished(job: &Job) -> bool {
+        matches!(
+            job.status,
+            JobStatus::Finished | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+
+    // 終了済みのジョブを、新しいものから `keep` 件だけ残して取り除く
+    fn prune_finished(&mut self, keep: usize) {
+        let mut excess = self
+            .jobs
+            .iter()
+            .filter(|j| Self::is_finished(j))
+            .count()
+            .saturating_sub(keep);
+        self.jobs.retain(|j| {
+            if excess > 0 && Self::is_finished(j) {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // 待機中のジョブを、待機中ジョブの中での `new_index` 番目に移動する
+    fn move_job(&mut self, job_id: JobId, new_index: usize) -> Result<(), String> {
+        let from = self
+            .jobs
+            .iter()
+            .position(|j| j.id == job_id)
+            .ok_or_else(|| format!("job not found: {}", job_id))?;
+        if self.jobs[from].status != JobStatus::Queued {
+            return Err(format!("only queued jobs can be moved: {}", job_id));
+        }
+        let job = self.jobs.remove(from);
+
+        // 待機中ジョブの並びにおける位置を、ジョブ全体の中での位置に変換する
+        let queued_positions: Vec<usize> = self
+            .jobs
+            .iter()
+            .enumerate()
+            .filter(|(_, j)| j.status == JobStatus::Queued)
+            .map(|(i, _)| i)
+            .collect();
+        let to = match queued_positions.get(new_index) {
+            Some(&i) => i,
+            None => self.jobs.len(),
+        };
+        self.jobs.insert(to, job);
+        Ok(())
+    }
+
+    fn remove(&mut self, job_id: JobId) -> Result<(), String> {
+        if self.find_mut(job_id)?.status == JobStatus::Running {
+            return Err(format!(
+                "running job cannot be removed, cancel it first: {}",
+                job_id
+            ));
+        }
+        self.jobs.retain(|j| j.id != job_id);
+        Ok(())
+    }
+
+    fn cancel(&mut self, job_id: JobId) -> Result<CancelAction, String> {
+        let job = self.find_mut(job_id)?;
+        match job.status {
+            JobStatus::Running => {
+                job.cancel_requested.store(true, Ordering::SeqCst);
+                Ok(CancelAction::Requested)
+            }
+            JobStatus::Queued => {
+                job.status = JobStatus::Cancelled;
+                Ok(CancelAction::Dequeued)
+            }
+            _ => Err(format!("job is not queued or running: {}", job_id)),
+        }
+    }
+
+    fn infos(&self) -> Vec<JobInfo> {
+        self.jobs
+            .iter()
+            .map(|j| JobInfo {
+                id: j.id,
+                file_path: j.file_path.clone(),
+                model_id: j.model.id,
+                status: j.status,
+            })
+            .collect()
+    }
+}
+
+/// 文字起こしジョブのキュー
+///
+/// ジョブは登録順（`move_asr_job` で並べ替え可能）に取り出され、
+/// 最大 `concurrency` 件まで並行して実行される。認識器は全ジョブで共有する。
+#[derive(Default)]
+pub struct JobQueue {
+    inner: Mutex<QueueInner>,
+    recognizer: SharedRecognizer,
+}
+
+impl JobQueue {
+    fn enqueue(
+        &self,
+        file_paths: Vec<String>,
+        model: &'static ModelInfo,
+        options: TranscriptionOptions,
+    ) -> Vec<JobId> {
+        self.inner
+            .lock()
+            .unwrap()
+            .enqueue(file_paths, model, options)
+    }
+}
+
+// 空きがある限り、待機中のジョブをワーカースレッドで開始する
+fn schedule(app_handle: &AppHandle) {
+    let queue = app_handle.state::<JobQueue>();
+    let launches = queue.inner.lock().unwrap().start_ready();
+
+    for launch in launches {
+        let app_handle = app_handle.clone();
+        thread::spawn(move || {
+            let queue = app_handle.state::<JobQueue>();
+            let status = asr::run_job(
+                &app_handle,
+                &queue.recognizer,
+                launch.id,
+                &launch.file_path,
+                launch.model,
+                &launch.options,
+                &launch.cancel_requested,
+            );
+            finish_job(&app_handle, launch.id, status);
+        });
+    }
+}
+
+fn finish_job(app_handle: &AppHandle, job_id: JobId, status: JobStatus) {
+    let queue = app_handle.state::<JobQueue>();
+    {
+        let mut inner = queue.inner.lock().unwrap();
+        // 認識器はメモリを大量に使うので、処理するジョブがなくなったら解放する
+        if inner.finish(job_id, status) {
+            queue.recognizer.release();
+        }
+    }
+    schedule(app_handle);
+}
+
+// 保存されたデコード設定は登録時に決める。実行時まで待つと、同じ認識器で同時に
+// 実行できるジョブかどうかをキューが判定できない
+fn resolve_options(
+    app_handle: &AppHandle,
+    mut options: TranscriptionOptions,
+) -> TranscriptionOptions {
+    options
+        .decoding
+        .get_or_insert_with(|| settings::load_decoding_options(app_handle));
+    options
+}
+
+// 不正な設定はジョブの実行を待たずにエラーにする
+fn validate_options(model: &ModelInfo, options: &TranscriptionOptions) -> Result<(), String> {
+    if let Some(chunking) = &options.chunking {
+        chunking.validate(model)?;
+    }
+    if let Some(decoding) = &options.decoding {
+        decoding.validate()?;
+    }
+    if let Some(range) = &options.range {
+        range.validate()?;
+    }
+    if let Some(threshold) = options.review_threshold {
+        sink::validate_review_threshold(threshold)?;
+    }
+    if options.split_channels && options.diarization.is_some() {
+        return Err("split_channels cannot be combined with diarization".to_string());
+    }
+    options.preprocessing.validate(asr::REQUIRED_SAMPLE_RATE)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn start_asr_process(
+    app_handle: AppHandle,
+    queue: State<'_, JobQueue>,
+    file_path: String,
+    model_id: Option<String>,
+    options: Option<TranscriptionOptions>,
+) -> Result<JobId, String> {
+    let model =
+        models::find_offline_model(model_id.as_deref().unwrap_or(models::DEFAULT_MODEL_ID))?;
+    let options = resolve_options(&app_handle, options.unwrap_or_default());
+    validate_options(model, &options)?;
+    let job_id = queue.enqueue(vec![file_path], model, options)[0];
+    schedule(&app_handle);
+    Ok(job_id)
+}
+
+#[tauri::command]
+pub fn enqueue_asr_jobs(
+    app_handle: AppHandle,
+    queue: State<'_, JobQueue>,
+    file_paths: Vec<String>,
+    model_id: Option<String>,
+    options: Option<TranscriptionOptions>,
+) -> Result<Vec<JobId>, String> {
+    let model =
+        models::find_offline_model(model_id.as_deref().unwrap_or(models::DEFAULT_MODEL_ID))?;
+    let options = resolve_options(&app_handle, options.unwrap_or_default());
+    validate_options(model, &options)?;
+    let job_ids = queue.enqueue(file_paths, model, options);
+    schedule(&app_handle);
+    Ok(job_ids)
+}
+
+#[tauri::command]
+pub fn list_asr_jobs(queue: State<'_, JobQueue>) -> Vec<JobInfo> {
+    queue.inner.lock().unwrap().infos()
+}
+
+/// 待機中のジョブを、待機中ジョブの中での `new_index` 番目に移動する
+#[tauri::command]
+pub fn move_asr_job(
+    queue: State<'_, JobQueue>,
+    job_id: JobId,
+    new_index: usize,
+) -> Result<(), String> {
+    queue.inner.lock().unwrap().move_job(job_id, new_index)
+}
+
+/// 実行中以外のジョブを一覧から取り除く
+#[tauri::command]
+pub fn remove_asr_job(queue: State<'_, JobQueue>, job_id: JobId) -> Result<(), String> {
+    queue.inner.lock().unwrap().remove(job_id)
+}
+
+/// 終了済み（完了・失敗・キャンセル）のジョブをすべて一覧から取り除く
+#[tauri::command]
+pub fn clear_finished_asr_jobs(queue: State<'_, JobQueue>) {
+    queue.inner.lock().unwrap().prune_finished(0);
+}
+
+#[tauri::command]
+pub fn set_asr_concurrency(
+    app_handle: AppHandle,
+    queue: State<'_, JobQueue>,
+    concurrency: usize,
+) -> Result<(), String> {
+    if concurrency == 0 {
+        return Err("concurrency must be at least 1".into());
+    }
+    queue.inner.lock().unwrap().concurrency = concurrency;
+    schedule(&app_handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cancel_asr_process(
+    app_handle: AppHandle,
+    queue: State<'_, JobQueue>,
+    job_id: JobId,
+) -> Result<(), String> {
+    log::info!("Cancellation of ASR job {} requested", job_id);
+    // イベントの送信中にキューをロックしたままにしないよう、先に状態だけ変える
+    let action = queue.inner.lock().unwrap().cancel(job_id)?;
+    if action == CancelAction::Dequeued {
+        TauriEventSink::new(app_handle, job_id, false).cancelled(&[], 0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{DecodingMethod, DecodingOptions, Hotword};
+
+    fn queue_with(count: usize) -> (QueueInner, Vec<JobId>) {
+        let mut inner = QueueInner::default();
+        let model = models::find_offline_model(models::DEFAULT_MODEL_ID).unwrap();
+        let paths = (0..count).map(|i| format!("/tmp/{}.wav", i)).collect();
+        let ids = inner.enqueue(paths, model, TranscriptionOptions::default());
+        (inner, ids)
+    }
+
+    fn statuses(inner: &QueueInner) -> Vec<(JobId, JobStatus)> {
+        inner.jobs.iter().map(|j| (j.id, j.status)).collect()
+    }
+
+    #[test]
+    fn start_ready_respects_concurrency_and_order() {
+        let (mut inner, ids) = queue_with(3);
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, vec![ids[0]]);
+        assert!(inner.start_ready().is_empty());
+
+        inner.concurrency = 3;
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, vec![ids[1], ids[2]]);
+    }
+
+    #[test]
+    fn jobs_for_another_model_wait_for_running_jobs() {
+        let (mut inner, ids) = queue_with(1);
+        inner.concurrency = 2;
+        let other = models::models()
+            .iter()
+            .find(|m| !m.is_streaming() && m.id != models::DEFAULT_MODEL_ID)
+            .unwrap();
+        let other_ids = inner.enqueue(
+            vec!["/tmp/other.wav".to_string()],
+            other,
+            TranscriptionOptions::default(),
+        );
+        let more = inner.enqueue(
+            vec!["/tmp/later.wav".to_string()],
+            models::find_offline_model(models::DEFAULT_MODEL_ID).unwrap(),
+            TranscriptionOptions::default(),
+        );
+
+        // 異なるモデルのジョブは追い越されず、後ろのジョブも待つ
+        assert_eq!(inner.start_ready().len(), 1);
+        assert!(!inner.finish(ids[0], JobStatus::Finished));
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, vec![other_ids[0]]);
+        assert!(!inner.finish(other_ids[0], JobStatus::Failed));
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, more);
+        assert!(inner.finish(more[0], JobStatus::Finished));
+    }
+
+    #[test]
+    fn jobs_with_other_decoding_or_hotwords_wait_for_running_jobs() {
+        let model = models::models()
+            .iter()
+            .find(|m| m.supports_hotwords())
+            .unwrap();
+        let mut inner = QueueInner {
+            concurrency: 3,
+            ..QueueInner::default()
+        };
+        let first = inner.enqueue(
+            vec!["/tmp/a.wav".to_string()],
+            model,
+            TranscriptionOptions::default(),
+        );
+        let beam = TranscriptionOptions {
+            decoding: Some(DecodingOptions {
+                method: DecodingMethod::ModifiedBeamSearch,
+                ..DecodingOptions::default()
+            }),
+            ..TranscriptionOptions::default()
+        };
+        let second = inner.enqueue(vec!["/tmp/b.wav".to_string()], model, beam);
+        let hotwords = TranscriptionOptions {
+            hotwords: Hotword::parse_list("東京").unwrap(),
+            ..TranscriptionOptions::default()
+        };
+        let third = inner.enqueue(vec!["/tmp/c.wav".to_string()], model, hotwords);
+
+        // 同時に実行すると認識器が読み込み直されるので、設定が変わるたびに前のジョブを待つ
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, first);
+        inner.finish(first[0], JobStatus::Finished);
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, second);
+        inner.finish(second[0], JobStatus::Finished);
+        let started: Vec<JobId> = inner.start_ready().iter().map(|l| l.id).collect();
+        assert_eq!(started, third);
+    }
+
+    #[test]
+    fn move_job_uses_positions_among_queued_jobs() {
+        let (mut inner, ids) = queue_with(4);
+        inner.start_ready();
+        // 待機中は ids[1..]。ids[3] を待機中の先頭へ
+        inner.move_job(ids[3], 0).unwrap();
+        let order: Vec<JobId> = inner.jobs.iter().map(|j| j.id).collect();
+        assert_eq!(order, vec![ids[0], ids[3], ids[1], ids[2]]);
+
+        // 範囲外の位置は末尾
+        inner.move_job(ids[3], 10).unwrap();
+        let order: Vec<JobId> = inner.jobs.iter().map(|j| j.id).collect();
+        assert_eq!(order, vec![ids[0], ids[1], ids[2], ids[3]]);
+
+        assert!(inner.move_job(ids[0], 0).is_err());
+        assert!(inner.move_job(999, 0).is_err());
+    }
+
+    #[test]
+    fn cancel_and_remove_follow_the_job_status() {
+        let (mut inner, ids) = queue_with(2);
+        let launches = inner.start_ready();
+
+        assert_eq!(inner.cancel(ids[0]).unwrap(), CancelAction::Requested);
+        assert!(launches[0].cancel_requested.load(Ordering::SeqCst));
+        assert!(inner.remove(ids[0]).is_err());
+
+        assert_eq!(inner.cancel(ids[1]).unwrap(), CancelAction::Dequeued);
+        assert!(inner.cancel(ids[1]).is_err());
+        assert!(inner.start_ready().is_empty());
+
+        assert!(inner.finish(ids[0], JobStatus::Cancelled));
+        assert_eq!(
+            statuses(&inner),
+            vec![
+                (ids[0], JobStatus::Cancelled),
+                (ids[1], JobStatus::Cancelled)
+            ]
+        );
+        inner.remove(ids[1]).unwrap();
+        assert_eq!(statuses(&inner), vec![(ids[0], JobStatus::Cancelled)]);
+    }
+
+    #[test]
+    fn finished_jobs_are_pruned_oldest_first() {
+        let (mut inner, ids) = queue_with(MAX_FINISHED_JOBS + 2);
+        inner.concurrency = ids.len();
+        inner.start_ready();
+        for &id in &ids {
+            inner.finish(id, JobStatus::Finished);
+        }
+        assert_eq!(inner.jobs.len(), MAX_FINISHED_JOBS);
+        assert_eq!(inner.jobs[0].id, ids[2]);
+
+        inner.prune_finished(0);
+        assert!(inner.jobs.is_empty());
+    }
+}
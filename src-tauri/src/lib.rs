@@ -1,14 +1,44 @@
-mod asr;
-mod audio_converter;
+pub mod asr;
+pub mod audio_converter;
+mod checksum;
+pub mod diarization;
 mod download;
+pub mod export;
+pub mod job_queue;
+pub mod live;
+mod media;
+pub mod models;
+mod offline_recognizer;
+mod online_recognizer;
+pub mod preprocess;
+pub mod recognizer;
+mod settings;
+pub mod sink;
 mod transcription;
+pub mod vad;
+mod waveform;
 
 use tauri;
 use tauri_plugin_log;
 
-use asr::start_asr_process;
+use checksum::verify_models;
 use download::download_model_file_stream;
-use transcription::save_transcription_file;
+use job_queue::{
+    cancel_asr_process, clear_finished_asr_jobs, enqueue_asr_jobs, list_asr_jobs, move_asr_job,
+    remove_asr_job, set_asr_concurrency, start_asr_process, JobQueue,
+};
+use live::{
+    list_audio_input_devices, push_live_audio, start_live_transcription, stop_live_transcription,
+    LiveSessions,
+};
+use media::{list_audio_tracks, probe_media};
+use models::list_asr_models;
+use settings::{
+    get_chunk_options, get_decoding_options, parse_hotwords, set_chunk_options,
+    set_decoding_options,
+};
+use transcription::{export_transcription_file, save_transcription_file};
+use waveform::get_waveform_peaks;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -33,10 +63,34 @@ pub fn run() {
                 .build()
         })
         .plugin(tauri_plugin_opener::init())
+        .manage(JobQueue::default())
+        .manage(LiveSessions::default())
         .invoke_handler(tauri::generate_handler![
             start_asr_process,
+            enqueue_asr_jobs,
+            list_asr_jobs,
+            move_asr_job,
+            remove_asr_job,
+            clear_finished_asr_jobs,
+            set_asr_concurrency,
+            cancel_asr_process,
+            list_asr_models,
             save_transcription_file,
-            download_model_file_stream
+            export_transcription_file,
+            download_model_file_stream,
+            verify_models,
+            list_audio_input_devices,
+            start_live_transcription,
+            push_live_audio,
+            stop_live_transcription,
+            get_decoding_options,
+            set_decoding_options,
+            parse_hotwords,
+            get_chunk_options,
+            set_chunk_options,
+            list_audio_tracks,
+            probe_media,
+            get_waveform_peaks
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,786 @@
+use crate::asr::{confidence_from_log_probs, Sentence};
+use crate::audio_converter::{AudioStream, SampleSource};
+use crate::export::{self, ExportFormat};
+use crate::models::{self, ModelInfo};
+use crate::online_recognizer::OnlineRecognizer;
+use crate::recognizer::StreamingRecognizer;
+use crate::sink::{LiveTranscriptionSink, TauriLiveEventSink};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, Manager, State};
+
+pub type SessionId = u64;
+
+// ファイル入力を実時間で再生するときに、認識器へ一度に渡す長さ（秒）
+const PLAYBACK_BLOCK_SECONDS: f32 = 0.1;
+// PCM 入力として受け付けるサンプリングレートの範囲（電話音声からハイレゾ録音まで）
+const MIN_PCM_SAMPLE_RATE: u32 = 8000;
+const MAX_PCM_SAMPLE_RATE: u32 = 192_000;
+// 入力を待っている間に停止要求を確認する間隔（デバイスが止まっても停止できるようにする）
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// 入力から届くブロック。入力デバイスのエラーは `Err` として届き、セッションのエラーになる
+type InputBlock = Result<Vec<f32>, String>;
+
+/// ライブ文字起こしの入力
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "type"
+)]
+pub enum LiveInput {
+    /// 入力デバイス（名前を省略すると既定のデバイス）
+    Microphone {
+        #[serde(default)]
+        device_name: Option<String>,
+    },
+    /// `push_live_audio` で渡されるモノラルの PCM
+    Pcm { sample_rate: u32 },
+    /// 音声ファイルを実時間で再生して入力の代わりにする（実機なしでの動作確認用）
+    File { path: String },
+}
+
+impl LiveInput {
+    /// PCM 入力のサンプリングレートが扱える範囲にあるかを確認する（0 では時刻を計算できない）
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            LiveInput::Pcm { sample_rate }
+                if !(MIN_PCM_SAMPLE_RATE..=MAX_PCM_SAMPLE_RATE).contains(&sample_rate) =>
+            {
+                Err(format!(
+                    "PCM sample rate must be between {} and {} Hz: {}",
+                    MIN_PCM_SAMPLE_RATE, MAX_PCM_SAMPLE_RATE, sample_rate
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// チャンネルで受け取ったブロックをそのまま返す。送信側がすべて閉じるか、
+// 入力を待っている間に停止を要求されると終わる
+struct ChannelSource {
+    receiver: Receiver<InputBlock>,
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl SampleSource for ChannelSource {
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>, String> {
+        loop {
+            match self.receiver.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(block) => return block.map(Some),
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.stop_requested.load(Ordering::SeqCst) {
+                        return Ok(None);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            }
+        }
+    }
+}
+
+// 元の音源を実時間と同じ速さで少しずつ返す
+struct RealtimeSource<S> {
+    inner: S,
+    sample_rate: u32,
+    pending: Vec<f32>,
+    delivered: usize,
+    started_at: Option<Instant>,
+}
+
+impl<S: SampleSource> RealtimeSource<S> {
+    fn new(inner: S, sample_rate: u32) -> Self {
+        RealtimeSource {
+            inner,
+            sample_rate,
+            pending: Vec::new(),
+            delivered: 0,
+            started_at: None,
+        }
+    }
+}
+
+impl<S: SampleSource> SampleSource for RealtimeSource<S> {
+    fn next_block(&mut self) -> Result<Option<Vec<f32>>, String> {
+        let block_len = (self.sample_rate as f32 * PLAYBACK_BLOCK_SECONDS) as usize;
+        while self.pending.len() < block_len {
+            match self.inner.next_block()? {
+                Some(block) => self.pending.extend_from_slice(&block),
+                None => break,
+            }
+        }
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+        let block: Vec<f32> = self
+            .pending
+            .drain(..block_len.min(self.pending.len()))
+            .collect();
+
+        // 返し終わったサンプル数に相当する時刻まで待つ
+        let started_at = *self.started_at.get_or_insert_with(Instant::now);
+        self.delivered += block.len();
+        let due = Duration::from_secs_f64(self.delivered as f64 / self.sample_rate as f64);
+        if let Some(wait) = due.checked_sub(started_at.elapsed()) {
+            thread::sleep(wait);
+        }
+        Ok(Some(block))
+    }
+}
+
+fn downmix<T>(data: &[T], channels: usize) -> Vec<f32>
+where
+    T: cpal::Sample,
+    f32: cpal::FromSample<T>,
+{
+    data.chunks(channels)
+        .map(|frame| frame.iter().map(|s| s.to_sample::<f32>()).sum::<f32>() / channels as f32)
+        .collect()
+}
+
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    sender: Sender<InputBlock>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    use cpal::traits::DeviceTrait;
+
+    let channels = config.channels as usize;
+    let error_sender = sender.clone();
+    device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            // 受信側が閉じた後のコールバックは捨てる
+            let _ = sender.send(Ok(downmix(data, channels)));
+        },
+        move |e| {
+            log::error!("Input stream error: {}", e);
+            let _ = error_sender.send(Err(format!("input stream error: {}", e)));
+        },
+        None,
+    )
+}
+
+// 入力デバイスからの録音を始め、ストリームと受信側、サンプリングレートを返す。
+// ストリームを破棄すると録音が止まる。
+fn open_microphone(
+    device_name: Option<&str>,
+    stop_requested: Arc<AtomicBool>,
+) -> Result<(cpal::Stream, ChannelSource, u32), String> {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let host = cpal::default_host();
+    let device = match device_name {
+        Some(name) => host
+            .input_devices()
+            .map_err(|e| format!("Failed to list input devices: {}", e))?
+            .find(|d| d.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| format!("input device not found: {}", name))?,
+        None => host
+            .default_input_device()
+            .ok_or("no input device available")?,
+    };
+    let supported = device
+        .default_input_config()
+        .map_err(|e| format!("Failed to get input config: {}", e))?;
+    let sample_rate = supported.sample_rate().0;
+    let config = supported.config();
+    log::info!(
+        "Recording from {} at {} Hz, {} channels",
+        device.name().unwrap_or_default(),
+        sample_rate,
+        config.channels
+    );
+
+    let (sender, receiver) = mpsc::channel();
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => build_input_stream::<f32>(&device, &config, sender),
+        cpal::SampleFormat::I16 => build_input_stream::<i16>(&device, &config, sender),
+        cpal::SampleFormat::U16 => build_input_stream::<u16>(&device, &config, sender),
+        other => return Err(format!("unsupported sample format: {}", other)),
+    }
+    .map_err(|e| format!("Failed to open input stream: {}", e))?;
+    stream
+        .play()
+        .map_err(|e| format!("Failed to start input stream: {}", e))?;
+    let source = ChannelSource {
+        receiver,
+        stop_requested,
+    };
+    Ok((stream, source, sample_rate))
+}
+
+// 入力の音声を 16 ビットのモノラル WAV に書き出す
+struct WavRecorder {
+    writer: hound::WavWriter<std::io::BufWriter<std::fs::File>>,
+}
+
+impl WavRecorder {
+    fn create(path: &Path, sample_rate: u32) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path, spec)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Ok(WavRecorder { writer })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer
+                .write_sample(value)
+                .map_err(|e| format!("Failed to write recording: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn finalize(self) -> Result<(), String> {
+        self.writer
+            .finalize()
+            .map_err(|e| format!("Failed to finalize recording: {}", e))
+    }
+}
+
+/// 入力が終わるか停止を要求されるまで認識を続け、確定した文を返す
+///
+/// 発話の終わりを検出するたびに仮説を文として確定させ、認識器をリセットする。
+/// 文の時刻はセッション開始からの経過時間（入力のサンプル数から計算）。
+fn run_session(
+    source: &mut dyn SampleSource,
+    sample_rate: u32,
+    recognizer: &mut dyn StreamingRecognizer,
+    mut recorder: Option<&mut WavRecorder>,
+    sink: &mut dyn LiveTranscriptionSink,
+    stop_requested: &AtomicBool,
+) -> Result<Vec<Sentence>, String> {
+    let mut sentences = Vec::new();
+    let mut received = 0;
+    let mut utterance_start = 0;
+    let mut last_partial = String::new();
+
+    let to_seconds = |samples: usize| samples as f32 / sample_rate as f32;
+    let mut finish_utterance =
+        |text: &str,
+         log_probs: &[f32],
+         start: usize,
+         end: usize,
+         sink: &mut dyn LiveTranscriptionSink| {
+            let text = text.trim();
+            if text.is_empty() {
+                return;
+            }
+            let log_probs: Vec<Option<f32>> = log_probs.iter().copied().map(Some).collect();
+            let sentence = Sentence {
+                start: to_seconds(start),
+                end: to_seconds(end),
+                text: text.to_string(),
+                speaker: None,
+                channel: None,
+                // ストリーミング認識は単語単位の時刻を返さないので、信頼度は発話全体で求める
+                words: Vec::new(),
+                confidence: confidence_from_log_probs(&log_probs),
+                needs_review: false,
+            };
+            sink.sentence(&sentence);
+            sentences.push(sentence);
+        };
+
+    while !stop_requested.load(Ordering::SeqCst) {
+        let Some(block) = source.next_block()? else {
+            break;
+        };
+        if let Some(recorder) = recorder.as_deref_mut() {
+            recorder.write(&block)?;
+        }
+        recognizer.accept_waveform(sample_rate, &block);
+        received += block.len();
+
+        let text = recognizer.decode();
+        if recognizer.is_endpoint() {
+            finish_utterance(
+                &text,
+                &recognizer.log_probs(),
+                utterance_start,
+                received,
+                sink,
+            );
+            recognizer.reset();
+            utterance_start = received;
+            last_partial.clear();
+        } else if text != last_partial {
+            sink.partial(&text);
+            last_partial = text;
+        }
+    }
+
+    // 停止時点で認識中だった発話も確定させる
+    recognizer.input_finished();
+    let text = recognizer.decode();
+    finish_utterance(
+        &text,
+        &recognizer.log_probs(),
+        utterance_start,
+        received,
+        sink,
+    );
+    Ok(sentences)
+}
+
+// 書き出した録音と同じ名前で、文字起こし結果を SSWT 形式で保存する
+fn save_transcript(recording_path: &Path, sentences: &[Sentence]) -> Result<(), String> {
+    let path = recording_path.with_extension(ExportFormat::Sswt.extension());
+    std::fs::write(&path, export::render(ExportFormat::Sswt, sentences))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn run_live(
+    app_handle: &AppHandle,
+    session_id: SessionId,
+    model: &'static ModelInfo,
+    input: LiveInput,
+    pcm_receiver: Option<Receiver<InputBlock>>,
+    recording_path: Option<PathBuf>,
+    stop_requested: &Arc<AtomicBool>,
+) {
+    let mut sink = TauriLiveEventSink::new(app_handle.clone(), session_id);
+
+    let model_dir_path = match app_handle.path().resolve(
+        format!("models/{}", model.directory),
+        BaseDirectory::AppLocalData,
+    ) {
+        Ok(path) => path,
+        Err(e) => {
+            sink.error(&format!("モデルディレクトリの解決に失敗しました: {}", e));
+            return;
+        }
+    };
+    let mut recognizer = match OnlineRecognizer::new(model, &model_dir_path) {
+        Ok(recognizer) => recognizer,
+        Err(e) => {
+            sink.error(&format!("認識器の初期化に失敗しました: {}", e));
+            return;
+        }
+    };
+
+    // マイクのストリームは録音中ずっと保持しておく必要がある
+    let mut microphone = None;
+    let (mut source, sample_rate): (Box<dyn SampleSource>, u32) = match input {
+        LiveInput::Microphone { device_name } => {
+            match open_microphone(device_name.as_deref(), stop_requested.clone()) {
+                Ok((stream, source, sample_rate)) => {
+                    microphone = Some(stream);
+                    (Box::new(source), sample_rate)
+                }
+                Err(e) => {
+                    sink.error(&format!("入力デバイスを開けませんでした: {}", e));
+                    return;
+                }
+            }
+        }
+        LiveInput::Pcm { sample_rate } => {
+            let receiver = pcm_receiver.expect("PCM input must have a receiver");
+            let source = ChannelSource {
+                receiver,
+                stop_requested: stop_requested.clone(),
+            };
+            (Box::new(source), sample_rate)
+        }
+        LiveInput::File { path } => match AudioStream::open(&path) {
+            Ok(stream) => (
+                Box::new(RealtimeSource::new(
+                    stream,
+                    crate::asr::REQUIRED_SAMPLE_RATE,
+                )),
+                crate::asr::REQUIRED_SAMPLE_RATE,
+            ),
+            Err(e) => {
+                sink.error(&format!("音声ファイルの読み込みに失敗しました: {}", e));
+                return;
+            }
+        },
+    };
+
+    let mut recorder = match recording_path
+        .as_deref()
+        .map(|path| WavRecorder::create(path, sample_rate))
+        .transpose()
+    {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            sink.error(&format!("録音ファイルを作成できませんでした: {}", e));
+            return;
+        }
+    };
+
+    sink.started(sample_rate);
+    let result = run_session(
+        source.as_mut(),
+        sample_rate,
+        &mut recognizer,
+        recorder.as_mut(),
+        &mut sink,
+        stop_requested,
+    );
+    drop(microphone);
+
+    let sentences = match result {
+        Ok(sentences) => sentences,
+        Err(e) => {
+            sink.error(&format!("ライブ文字起こしに失敗しました: {}", e));
+            return;
+        }
+    };
+    if let (Some(recorder), Some(path)) = (recorder, recording_path.as_deref()) {
+        if let Err(e) = recorder
+            .finalize()
+            .and_then(|_| save_transcript(path, &sentences))
+        {
+            sink.error(&format!("録音の保存に失敗しました: {}", e));
+            return;
+        }
+    }
+    log::info!(
+        "Live session {} stopped ({} sentences)",
+        session_id,
+        sentences.len()
+    );
+    sink.stopped(&sentences, recording_path.as_deref().and_then(Path::to_str));
+}
+
+struct Session {
+    stop_requested: Arc<AtomicBool>,
+    // PCM 入力の場合だけ持つ。破棄すると入力の終わりとして扱われる
+    pcm_sender: Option<Sender<InputBlock>>,
+}
+
+struct LiveInner {
+    sessions: HashMap<SessionId, Session>,
+    next_id: SessionId,
+}
+
+/// 実行中のライブ文字起こしセッション
+///
+/// セッションごとにスレッドとストリーミング認識器を持つ。
+pub struct LiveSessions {
+    inner: Mutex<LiveInner>,
+}
+
+impl Default for LiveSessions {
+    fn default() -> Self {
+        LiveSessions {
+            inner: Mutex::new(LiveInner {
+                sessions: HashMap::new(),
+                next_id: 1,
+            }),
+        }
+    }
+}
+
+/// 利用できる入力デバイスの名前の一覧
+#[tauri::command]
+pub fn list_audio_input_devices() -> Result<Vec<String>, String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    let devices = cpal::default_host()
+        .input_devices()
+        .map_err(|e| format!("Failed to list input devices: {}", e))?;
+    Ok(devices.filter_map(|d| d.name().ok()).collect())
+}
+
+/// ライブ文字起こしを開始する
+///
+/// `recording_path` を指定すると入力を WAV で録音し、停止時に同じ名前の `.sswt` に
+/// 文字起こし結果を保存する。
+#[tauri::command]
+pub fn start_live_transcription(
+    app_handle: AppHandle,
+    sessions: State<'_, LiveSessions>,
+    model_id: Option<String>,
+    input: LiveInput,
+    recording_path: Option<String>,
+) -> Result<SessionId, String> {
+    let model = models::find_streaming_model(
+        model_id
+            .as_deref()
+            .unwrap_or(models::DEFAULT_STREAMING_MODEL_ID),
+    )?;
+    input.validate()?;
+
+    let stop_requested = Arc::new(AtomicBool::new(false));
+    let (pcm_sender, pcm_receiver) = match input {
+        LiveInput::Pcm { .. } => {
+            let (sender, receiver) = mpsc::channel();
+            (Some(sender), Some(receiver))
+        }
+        _ => (None, None),
+    };
+
+    let session_id = {
+        let mut inner = sessions.inner.lock().unwrap();
+        let session_id = inner.next_id;
+        inner.next_id += 1;
+        inner.sessions.insert(
+            session_id,
+            Session {
+                stop_requested: stop_requested.clone(),
+                pcm_sender,
+            },
+        );
+        session_id
+    };
+    log::info!(
+        "Starting live session {} with {} from {:?}",
+        session_id,
+        model.id,
+        input
+    );
+
+    thread::spawn(move || {
+        run_live(
+            &app_handle,
+            session_id,
+            model,
+            input,
+            pcm_receiver,
+            recording_path.map(PathBuf::from),
+            &stop_requested,
+        );
+        let sessions = app_handle.state::<LiveSessions>();
+        sessions.inner.lock().unwrap().sessions.remove(&session_id);
+    });
+    Ok(session_id)
+}
+
+/// PCM 入力のセッションにモノラルの音声を渡す
+#[tauri::command]
+pub fn push_live_audio(
+    sessions: State<'_, LiveSessions>,
+    session_id: SessionId,
+    samples: Vec<f32>,
+) -> Result<(), String> {
+    let inner = sessions.inner.lock().unwrap();
+    let session = inner
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| format!("live session not found: {}", session_id))?;
+    let sender = session
+        .pcm_sender
+        .as_ref()
+        .ok_or_else(|| format!("live session does not accept PCM: {}", session_id))?;
+    sender
+        .send(Ok(samples))
+        .map_err(|_| format!("live session already stopped: {}", session_id))
+}
+
+/// ライブ文字起こしを停止する（確定した文は `live-stopped` で通知される）
+#[tauri::command]
+pub fn stop_live_transcription(
+    sessions: State<'_, LiveSessions>,
+    session_id: SessionId,
+) -> Result<(), String> {
+    log::info!("Stop of live session {} requested", session_id);
+    let mut inner = sessions.inner.lock().unwrap();
+    let session = inner
+        .sessions
+        .get_mut(&session_id)
+        .ok_or_else(|| format!("live session not found: {}", session_id))?;
+    session.stop_requested.store(true, Ordering::SeqCst);
+    // 入力待ちで止まっている PCM セッションを起こす
+    session.pcm_sender = None;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 10;
+
+    // ブロックごとに (仮説, 発話の終わりか) を返す偽の認識器
+    struct ScriptedStreamingRecognizer {
+        script: Vec<(&'static str, bool)>,
+        blocks: usize,
+        finished: bool,
+    }
+
+    impl StreamingRecognizer for ScriptedStreamingRecognizer {
+        fn accept_waveform(&mut self, _sample_rate: u32, _samples: &[f32]) {
+            self.blocks += 1;
+        }
+
+        fn decode(&mut self) -> String {
+            self.script
+                .get(self.blocks.wrapping_sub(1))
+                .map(|(text, _)| text.to_string())
+                .unwrap_or_default()
+        }
+
+        fn is_endpoint(&mut self) -> bool {
+            self.script
+                .get(self.blocks.wrapping_sub(1))
+                .is_some_and(|(_, endpoint)| *endpoint)
+        }
+
+        fn reset(&mut self) {}
+
+        fn input_finished(&mut self) {
+            self.finished = true;
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryLiveSink {
+        partials: Vec<String>,
+        sentences: Vec<Sentence>,
+    }
+
+    impl LiveTranscriptionSink for MemoryLiveSink {
+        fn started(&mut self, _sample_rate: u32) {}
+
+        fn partial(&mut self, text: &str) {
+            self.partials.push(text.to_string());
+        }
+
+        fn sentence(&mut self, sentence: &Sentence) {
+            self.sentences.push(sentence.clone());
+        }
+
+        fn stopped(&mut self, _sentences: &[Sentence], _recording_path: Option<&str>) {}
+
+        fn error(&mut self, _message: &str) {}
+    }
+
+    fn sentence(text: &str, start: f32, end: f32) -> Sentence {
+        Sentence {
+            start,
+            end,
+            text: text.to_string(),
+            speaker: None,
+            channel: None,
+            words: Vec::new(),
+            confidence: None,
+            needs_review: false,
+        }
+    }
+
+    #[test]
+    fn run_session_emits_partials_and_finalizes_on_endpoints() {
+        let samples = [0.0; 50];
+        let mut recognizer = ScriptedStreamingRecognizer {
+            script: vec![
+                (" HELLO", false),
+                (" HELLO WORLD", false),
+                (" HELLO WORLD", true),
+                (" AGAIN", false),
+                (" AGAIN", false),
+            ],
+            blocks: 0,
+            finished: false,
+        };
+        let mut sink = MemoryLiveSink::default();
+        let sentences = run_session(
+            &mut samples.chunks(10),
+            SAMPLE_RATE,
+            &mut recognizer,
+            None,
+            &mut sink,
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+
+        let expected = vec![
+            sentence("HELLO WORLD", 0.0, 3.0),
+            sentence("AGAIN", 3.0, 5.0),
+        ];
+        assert_eq!(sentences, expected);
+        assert_eq!(sink.sentences, expected);
+        assert_eq!(sink.partials, vec![" HELLO", " HELLO WORLD", " AGAIN"]);
+        assert!(recognizer.finished);
+    }
+
+    #[test]
+    fn run_session_stops_when_requested() {
+        let samples = [0.0; 50];
+        let mut recognizer = ScriptedStreamingRecognizer {
+            script: vec![(" HELLO", false)],
+            blocks: 0,
+            finished: false,
+        };
+        let mut sink = MemoryLiveSink::default();
+        let sentences = run_session(
+            &mut samples.chunks(10),
+            SAMPLE_RATE,
+            &mut recognizer,
+            None,
+            &mut sink,
+            &AtomicBool::new(true),
+        )
+        .unwrap();
+
+        assert!(sentences.is_empty());
+        assert_eq!(recognizer.blocks, 0);
+    }
+
+    #[test]
+    fn channel_source_ends_on_stop_and_reports_input_errors() {
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let mut source = ChannelSource {
+            receiver,
+            stop_requested: stop_requested.clone(),
+        };
+        sender.send(Ok(vec![0.5; 4])).unwrap();
+        assert_eq!(source.next_block(), Ok(Some(vec![0.5; 4])));
+
+        // デバイスのエラーはセッションのエラーになる
+        sender.send(Err("device lost".to_string())).unwrap();
+        assert_eq!(source.next_block(), Err("device lost".to_string()));
+
+        // 送信側（マイクのストリーム）が生きていても、入力が止まれば停止要求で終わる
+        stop_requested.store(true, Ordering::SeqCst);
+        assert_eq!(source.next_block(), Ok(None));
+        drop(sender);
+    }
+
+    #[test]
+    fn live_input_deserializes_from_tagged_json() {
+        let input: LiveInput =
+            serde_json::from_str(r#"{"type":"pcm","sampleRate":48000}"#).unwrap();
+        assert!(matches!(input, LiveInput::Pcm { sample_rate: 48000 }));
+        let input: LiveInput = serde_json::from_str(r#"{"type":"microphone"}"#).unwrap();
+        assert!(matches!(input, LiveInput::Microphone { device_name: None }));
+    }
+
+    #[test]
+    fn pcm_input_rejects_unusable_sample_rates() {
+        for sample_rate in [0, 100, MAX_PCM_SAMPLE_RATE + 1] {
+            assert!(LiveInput::Pcm { sample_rate }.validate().is_err());
+        }
+        for sample_rate in [MIN_PCM_SAMPLE_RATE, 16000, 48000, MAX_PCM_SAMPLE_RATE] {
+            assert!(LiveInput::Pcm { sample_rate }.validate().is_ok());
+        }
+        assert!(LiveInput::Microphone { device_name: None }
+            .validate()
+            .is_ok());
+    }
+}
@@ -0,0 +1,18 @@
+//! Commands that inspect media files before they are transcribed.
+use crate::audio_converter::{self, AudioTrackInfo, MediaInfo};
+
+/// Lists the decodable tracks of a file with their channel layout, so the UI can offer a
+/// track and channel selection (`AudioSelection`) before starting a job.
+#[tauri::command]
+pub fn list_audio_tracks(file_path: String) -> Result<Vec<AudioTrackInfo>, String> {
+    audio_converter::list_tracks(&file_path)
+        .map_err(|e| format!("failed to read tracks of {}: {}", file_path, e))
+}
+
+/// Reads the duration, stream parameters, tags and chapters of a file from its headers, so the
+/// UI can show them (and estimate the processing time) before the file is decoded.
+#[tauri::command]
+pub fn probe_media(file_path: String) -> Result<MediaInfo, String> {
+    audio_converter::probe_media(&file_path)
+        .map_err(|e| format!("failed to probe {}: {}", file_path, e))
+}
@@ -0,0 +1,341 @@
+use crate::asr::REQUIRED_SAMPLE_RATE;
+
+/// モデルを指定しなかったときに使うモデル（以前のバージョンで唯一使えたもの）
+pub const DEFAULT_MODEL_ID: &str = "parakeet-tdt-0.6b-v2";
+
+/// ライブ文字起こしでモデルを指定しなかったときに使うストリーミングモデル
+pub const DEFAULT_STREAMING_MODEL_ID: &str = "streaming-zipformer-en";
+
+/// モデルの種類と、種類ごとに必要なファイル
+#[derive(Debug, Clone, Copy)]
+pub enum ModelKind {
+    /// NeMo Parakeet や Zipformer などのトランスデューサーモデル
+    Transducer {
+        encoder: &'static str,
+        decoder: &'static str,
+        joiner: &'static str,
+        /// sherpa-onnx の `model_type`（空文字列なら自動判定）
+        model_type: &'static str,
+        /// ホットワードを分割する単位（sherpa-onnx の `modeling_unit`）。`None` は modified beam
+        /// search とホットワードに対応しないモデル（NeMo のモデルは greedy search しか使えない）
+        hotwords_modeling_unit: Option<&'static str>,
+    },
+    Whisper {
+        encoder: &'static str,
+        decoder: &'static str,
+    },
+    SenseVoice {
+        model: &'static str,
+    },
+    /// ストリーミング（オンライン）認識用のトランスデューサーモデル。ライブ文字起こし専用
+    OnlineTransducer {
+        encoder: &'static str,
+        decoder: &'static str,
+        joiner: &'static str,
+    },
+}
+
+/// 組み込みのモデル定義
+#[derive(Debug, Clone, Copy)]
+pub struct ModelInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// `models` ディレクトリ直下のディレクトリ名
+    pub directory: &'static str,
+    /// 各ファイルのダウンロード元（`{base_url}/{file}`）
+    pub base_url: &'static str,
+    pub kind: ModelKind,
+    pub tokens: &'static str,
+    pub sample_rate: u32,
+    pub feature_dim: i32,
+    /// 対応言語（ISO 639-1）
+    pub languages: &'static [&'static str],
+    /// ファイル名と SHA-256（16進小文字）の組。ダウンロードと検証はこれと照合し、
+    /// 記録のないファイルはサーバーが通知するハッシュで確認する（どちらもなければ未検証）
+    pub sha256: &'static [(&'static str, &'static str)],
+}
+
+impl ModelInfo {
+    /// モデルディレクトリに必要なファイルの一覧
+    pub fn files(&self) -> Vec<&'static str> {
+        let mut files = match self.kind {
+            ModelKind::Transducer {
+                encoder,
+                decoder,
+                joiner,
+                ..
+            } => vec![encoder, decoder, joiner],
+            ModelKind::Whisper { encoder, decoder } => vec![encoder, decoder],
+            ModelKind::SenseVoice { model } => vec![model],
+            ModelKind::OnlineTransducer {
+                encoder,
+                decoder,
+                joiner,
+            } => vec![encoder, decoder, joiner],
+        };
+        files.push(self.tokens);
+        files
+    }
+
+    /// モデルの定義に記録したファイルの SHA-256
+    pub fn sha256_of(&self, file: &str) -> Option<&'static str> {
+        self.sha256
+            .iter()
+            .find(|(name, _)| *name == file)
+            .map(|(_, sha256)| *sha256)
+    }
+
+    /// ライブ文字起こし用のストリーミングモデルかどうか
+    pub fn is_streaming(&self) -> bool {
+        matches!(self.kind, ModelKind::OnlineTransducer { .. })
+    }
+
+    /// デコード方法に modified beam search を選べるかどうか
+    pub fn supports_beam_search(&self) -> bool {
+        matches!(
+            self.kind,
+            ModelKind::Transducer {
+                hotwords_modeling_unit: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// トークンごとの時刻を返すかどうか。Whisper と SenseVoice は返さないことがあり、その場合は
+    /// チャンク内に等間隔で並べた推定値になる
+    pub fn has_token_timestamps(&self) -> bool {
+        matches!(
+            self.kind,
+            ModelKind::Transducer { .. } | ModelKind::OnlineTransducer { .. }
+        )
+    }
+
+    /// トークンの確率を返し、文や単語の信頼度を求められるかどうか（トランスデューサーのみ）。
+    /// 返さないモデルでは信頼度が `None` になり、要確認の印も付かない
+    pub fn reports_confidence(&self) -> bool {
+        matches!(
+            self.kind,
+            ModelKind::Transducer { .. } | ModelKind::OnlineTransducer { .. }
+        )
+    }
+
+    /// 一度に認識できる音声の最大長（秒）。Whisper は30秒の窓でしか認識できない
+    pub fn max_chunk_seconds(&self) -> Option<u32> {
+        match self.kind {
+            ModelKind::Whisper { .. } => Some(30),
+            _ => None,
+        }
+    }
+
+    /// ホットワードによる認識の誘導に対応しているかどうか（modified beam search が必要）
+    pub fn supports_hotwords(&self) -> bool {
+        self.supports_beam_search()
+    }
+}
+
+// `sha256` には配布元のファイルを `sha256sum` で確かめた値を記録する。記録のないファイルは
+// ダウンロード時にサーバーの ETag と照合し、ETag も SHA-256 でなければ未検証として報告する
+const MODELS: &[ModelInfo] = &[
+    ModelInfo {
+        id: DEFAULT_MODEL_ID,
+        name: "NeMo Parakeet TDT 0.6B v2 (English)",
+        directory: "sherpa-onnx-nemo-parakeet-tdt-0.6b-v2-int8",
+        base_url:
+            "https://huggingface.co/csukuangfj/sherpa-onnx-nemo-parakeet-tdt-0.6b-v2-int8/resolve/main",
+        kind: ModelKind::Transducer {
+            encoder: "encoder.int8.onnx",
+            decoder: "decoder.int8.onnx",
+            joiner: "joiner.int8.onnx",
+            model_type: "nemo_transducer",
+            hotwords_modeling_unit: None,
+        },
+        tokens: "tokens.txt",
+        sample_rate: REQUIRED_SAMPLE_RATE,
+        feature_dim: 80,
+        languages: &["en"],
+        sha256: &[],
+    },
+    ModelInfo {
+        id: "parakeet-tdt-0.6b-v3",
+        name: "NeMo Parakeet TDT 0.6B v3 (25 European languages)",
+        directory: "sherpa-onnx-nemo-parakeet-tdt-0.6b-v3-int8",
+        base_url:
+            "https://huggingface.co/csukuangfj/sherpa-onnx-nemo-parakeet-tdt-0.6b-v3-int8/resolve/main",
+        kind: ModelKind::Transducer {
+            encoder: "encoder.int8.onnx",
+            decoder: "decoder.int8.onnx",
+            joiner: "joiner.int8.onnx",
+            model_type: "nemo_transducer",
+            hotwords_modeling_unit: None,
+        },
+        tokens: "tokens.txt",
+        sample_rate: REQUIRED_SAMPLE_RATE,
+        feature_dim: 80,
+        languages: &[
+            "bg", "cs", "da", "de", "el", "en", "es", "et", "fi", "fr", "hr", "hu", "it", "lt",
+            "lv", "mt", "nl", "pl", "pt", "ro", "ru", "sk", "sl", "sv", "uk",
+        ],
+        sha256: &[],
+    },
+    ModelInfo {
+        id: "zipformer-ja-reazonspeech",
+        name: "Zipformer ReazonSpeech (Japanese)",
+        directory: "sherpa-onnx-zipformer-ja-reazonspeech-2024-08-01",
+        base_url:
+            "https://huggingface.co/csukuangfj/sherpa-onnx-zipformer-ja-reazonspeech-2024-08-01/resolve/main",
+        kind: ModelKind::Transducer {
+            encoder: "encoder-epoch-99-avg-1.int8.onnx",
+            decoder: "decoder-epoch-99-avg-1.onnx",
+            joiner: "joiner-epoch-99-avg-1.int8.onnx",
+            model_type: "transducer",
+            // トークンが1文字ずつなので、ホットワードも1文字ずつに分割する
+            hotwords_modeling_unit: Some("cjkchar"),
+        },
+        tokens: "tokens.txt",
+        sample_rate: REQUIRED_SAMPLE_RATE,
+        feature_dim: 80,
+        languages: &["ja"],
+        sha256: &[],
+    },
+    ModelInfo {
+        id: "sense-voice",
+        name: "SenseVoice (Chinese, English, Japanese, Korean, Cantonese)",
+        directory: "sherpa-onnx-sense-voice-zh-en-ja-ko-yue-2024-07-17",
+        base_url:
+            "https://huggingface.co/csukuangfj/sherpa-onnx-sense-voice-zh-en-ja-ko-yue-2024-07-17/resolve/main",
+        kind: ModelKind::SenseVoice {
+            model: "model.int8.onnx",
+        },
+        tokens: "tokens.txt",
+        sample_rate: REQUIRED_SAMPLE_RATE,
+        feature_dim: 80,
+        languages: &["zh", "en", "ja", "ko", "yue"],
+        sha256: &[],
+    },
+    ModelInfo {
+        id: "whisper-small",
+        name: "Whisper small (multilingual)",
+        directory: "sherpa-onnx-whisper-small",
+        base_url: "https://huggingface.co/csukuangfj/sherpa-onnx-whisper-small/resolve/main",
+        kind: ModelKind::Whisper {
+            encoder: "small-encoder.int8.onnx",
+            decoder: "small-decoder.int8.onnx",
+        },
+        tokens: "small-tokens.txt",
+        sample_rate: REQUIRED_SAMPLE_RATE,
+        feature_dim: 80,
+        // 約 100 言語に対応するが、ここでは主なものだけを挙げる
+        languages: &[
+            "en", "ja", "zh", "ko", "de", "fr", "es", "it", "pt", "ru", "nl", "pl", "tr", "ar",
+        ],
+        sha256: &[],
+    },
+    ModelInfo {
+        id: DEFAULT_STREAMING_MODEL_ID,
+        name: "Streaming Zipformer (English, live)",
+        directory: "sherpa-onnx-streaming-zipformer-en-2023-06-26",
+        base_url:
+            "https://huggingface.co/csukuangfj/sherpa-onnx-streaming-zipformer-en-2023-06-26/resolve/main",
+        kind: ModelKind::OnlineTransducer {
+            encoder: "encoder-epoch-99-avg-1-chunk-16-left-128.int8.onnx",
+            decoder: "decoder-epoch-99-avg-1-chunk-16-left-128.onnx",
+            joiner: "joiner-epoch-99-avg-1-chunk-16-left-128.int8.onnx",
+        },
+        tokens: "tokens.txt",
+        sample_rate: REQUIRED_SAMPLE_RATE,
+        feature_dim: 80,
+        languages: &["en"],
+        sha256: &[],
+    },
+];
+
+// 認識モデル以外にダウンロードするファイル（話者分離モデルと Silero VAD）の SHA-256。
+// キーはアプリのローカルデータディレクトリからの相対パス。記録の仕方は `MODELS` と同じ
+const AUXILIARY_SHA256: &[(&str, &str)] = &[];
+
+/// アプリのローカルデータディレクトリからの相対パス（`models/<ディレクトリ>/<ファイル>` など）に
+/// 対応するファイルについて、モデルの定義に記録した SHA-256
+pub fn recorded_sha256(file_path: &str) -> Option<&'static str> {
+    if let Some((_, sha256)) = AUXILIARY_SHA256.iter().find(|(path, _)| *path == file_path) {
+        return Some(sha256);
+    }
+    let (directory, file) = file_path.strip_prefix("models/")?.split_once('/')?;
+    MODELS
+        .iter()
+        .find(|m| m.directory == directory)?
+        .sha256_of(file)
+}
+
+/// 組み込みのモデル定義の一覧
+pub fn models() -> &'static [ModelInfo] {
+    MODELS
+}
+
+pub fn find_model(model_id: &str) -> Result<&'static ModelInfo, String> {
+    MODELS
+        .iter()
+        .find(|m| m.id == model_id)
+        .ok_or_else(|| format!("unknown model: {}", model_id))
+}
+
+/// ファイルの文字起こしに使うモデルを探す（ストリーミングモデルは使えない）
+pub fn find_offline_model(model_id: &str) -> Result<&'static ModelInfo, String> {
+    let model = find_model(model_id)?;
+    if model.is_streaming() {
+        return Err(format!(
+            "model {} can only be used for live transcription",
+            model_id
+        ));
+    }
+    Ok(model)
+}
+
+/// ライブ文字起こしに使うストリーミングモデルを探す
+pub fn find_streaming_model(model_id: &str) -> Result<&'static ModelInfo, String> {
+    let model = find_model(model_id)?;
+    if !model.is_streaming() {
+        return Err(format!("model {} does not support streaming", model_id));
+    }
+    Ok(model)
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPayload {
+    id: &'static str,
+    name: &'static str,
+    directory: &'static str,
+    base_url: &'static str,
+    files: Vec<&'static str>,
+    languages: &'static [&'static str],
+    is_default: bool,
+    /// ライブ文字起こし専用のストリーミングモデルかどうか
+    streaming: bool,
+    supports_hotwords: bool,
+    supports_beam_search: bool,
+    reports_confidence: bool,
+    /// 一度に認識できる音声の最大長（秒）。上限がなければ `None`
+    max_chunk_seconds: Option<u32>,
+}
+
+/// 利用できるモデルの一覧（フロントエンドのモデル選択とダウンロード用）
+#[tauri::command]
+pub fn list_asr_models() -> Vec<ModelPayload> {
+    MODELS
+        .iter()
+        .map(|m| ModelPayload {
+            id: m.id,
+            name: m.name,
+            directory: m.directory,
+            base_url: m.base_url,
+            files: m.files(),
+            languages: m.languages,
+            is_default: m.id == DEFAULT_MODEL_ID,
+            streaming: m.is_streaming(),
+            supports_hotwords: m.supports_hotwords(),
+            supports_beam_search: m.supports_beam_search(),
+            reports_confidence: m.reports_confidence(),
+            max_chunk_seconds: m.max_chunk_seconds(),
+        })
+        .collect()
+}
@@ -0,0 +1,223 @@
+use crate::models::{ModelInfo, ModelKind};
+use crate::recognizer::{
+    log_probs_from_json, DecodingMethod, DecodingOptions, RecognitionResult, Recognizer,
+};
+use sherpa_rs_sys as sys;
+use std::ffi::{CStr, CString};
+use std::path::Path;
+
+/// sherpa-onnx のオフライン（非ストリーミング）トランスデューサー認識器
+///
+/// sherpa-rs の `TransducerConfig` ではビーム幅（`max_active_paths`）を指定できないため、
+/// C API を直接呼び出す。ストリームはチャンクごとに作り直す。
+pub struct OfflineTransducerRecognizer {
+    recognizer: *const sys::SherpaOnnxOfflineRecognizer,
+}
+
+// 認識器は同時に1スレッドからしか使わない（SharedRecognizer のロックで保証する）
+unsafe impl Send for OfflineTransducerRecognizer {}
+
+impl OfflineTransducerRecognizer {
+    /// `hotwords` はホットワードファイルのパスと加点。modified beam search のときだけ使われる
+    pub fn new(
+        model: &ModelInfo,
+        model_dir_path: &Path,
+        decoding: &DecodingOptions,
+        hotwords: Option<(&Path, f32)>,
+    ) -> Result<Self, String> {
+        let ModelKind::Transducer {
+            encoder,
+            decoder,
+            joiner,
+            model_type,
+            hotwords_modeling_unit,
+        } = model.kind
+        else {
+            return Err(format!("model {} is not a transducer model", model.id));
+        };
+
+        let c_path = |file: &str| {
+            let path = model_dir_path.join(file);
+            if !path.exists() {
+                return Err(format!("model file not found: {}", path.display()));
+            }
+            CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())
+        };
+        let c_str = |value: &str| CString::new(value).map_err(|e| e.to_string());
+        let encoder = c_path(encoder)?;
+        let decoder = c_path(decoder)?;
+        let joiner = c_path(joiner)?;
+        let tokens = c_path(model.tokens)?;
+        let provider = c_str("cpu")?;
+        let model_type = c_str(model_type)?;
+        let modeling_unit = c_str(hotwords_modeling_unit.unwrap_or(""))?;
+        let decoding = DecodingConfig::new(decoding, hotwords);
+        let decoding_method = c_str(decoding.method)?;
+        let hotwords_file = c_str(&decoding.hotwords_file)?;
+
+        // 使わないフィールドはゼロ（NULL）のままにすると sherpa-onnx の既定値になる
+        let config = unsafe {
+            sys::SherpaOnnxOfflineRecognizerConfig {
+                feat_config: sys::SherpaOnnxFeatureConfig {
+                    sample_rate: model.sample_rate as i32,
+                    feature_dim: model.feature_dim,
+                },
+                model_config: sys::SherpaOnnxOfflineModelConfig {
+                    transducer: sys::SherpaOnnxOfflineTransducerModelConfig {
+                        encoder: encoder.as_ptr(),
+                        decoder: decoder.as_ptr(),
+                        joiner: joiner.as_ptr(),
+                    },
+                    tokens: tokens.as_ptr(),
+                    num_threads: crate::recognizer::num_threads(),
+                    debug: 1,
+                    provider: provider.as_ptr(),
+                    model_type: model_type.as_ptr(),
+                    modeling_unit: modeling_unit.as_ptr(),
+                    ..std::mem::zeroed()
+                },
+                decoding_method: decoding_method.as_ptr(),
+                max_active_paths: decoding.max_active_paths,
+                hotwords_file: hotwords_file.as_ptr(),
+                hotwords_score: decoding.hotwords_score,
+                blank_penalty: decoding.blank_penalty,
+                ..std::mem::zeroed()
+            }
+        };
+
+        let recognizer = unsafe { sys::SherpaOnnxCreateOfflineRecognizer(&config) };
+        if recognizer.is_null() {
+            return Err(format!(
+                "failed to create offline recognizer from: {}",
+                model_dir_path.display()
+            ));
+        }
+        Ok(OfflineTransducerRecognizer { recognizer })
+    }
+}
+
+// C API の設定構造体に入れるデコード関連の値。FFI を通さずに確かめられるよう分けておく
+#[derive(Debug, PartialEq)]
+struct DecodingConfig {
+    method: &'static str,
+    max_active_paths: i32,
+    blank_penalty: f32,
+    hotwords_file: String,
+    hotwords_score: f32,
+}
+
+impl DecodingConfig {
+    fn new(decoding: &DecodingOptions, hotwords: Option<(&Path, f32)>) -> Self {
+        // greedy search ではホットワードが使われないので、ファイルも渡さない
+        let hotwords = hotwords.filter(|_| decoding.method == DecodingMethod::ModifiedBeamSearch);
+        let (hotwords_file, hotwords_score) = match hotwords {
+            Some((path, score)) => (path.to_string_lossy().into_owned(), score),
+            None => (String::new(), 0.0),
+        };
+        DecodingConfig {
+            method: decoding.method.as_sherpa(),
+            max_active_paths: i32::try_from(decoding.max_active_paths).unwrap_or(i32::MAX),
+            blank_penalty: decoding.blank_penalty,
+            hotwords_file,
+            hotwords_score,
+        }
+    }
+}
+
+impl Recognizer for OfflineTransducerRecognizer {
+    fn transcribe(&mut self, sample_rate: u32, samples: &[f32]) -> RecognitionResult {
+        unsafe {
+            let stream = sys::SherpaOnnxCreateOfflineStream(self.recognizer);
+            if stream.is_null() {
+                log::error!("Failed to create offline stream");
+                return RecognitionResult::default();
+            }
+            sys::SherpaOnnxAcceptWaveformOffline(
+                stream,
+                sample_rate as i32,
+                samples.as_ptr(),
+                samples.len() as i32,
+            );
+            sys::SherpaOnnxDecodeOfflineStream(self.recognizer, stream);
+
+            let result = sys::SherpaOnnxGetOfflineStreamResult(stream);
+            let recognition = if result.is_null() {
+                RecognitionResult::default()
+            } else {
+                let count = (*result).count.max(0) as usize;
+                let tokens = if (*result).tokens_arr.is_null() {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts((*result).tokens_arr, count)
+                        .iter()
+                        .map(|&token| CStr::from_ptr(token).to_string_lossy().into_owned())
+                        .collect()
+                };
+                let timestamps = if (*result).timestamps.is_null() {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts((*result).timestamps, count).to_vec()
+                };
+                // トークンの確率は構造体のフィールドにはなく、JSON にだけ含まれる
+                let log_probs = if (*result).json.is_null() {
+                    Vec::new()
+                } else {
+                    let json = CStr::from_ptr((*result).json).to_string_lossy();
+                    log_probs_from_json(&json, "ys_log_probs", tokens.len())
+                };
+                sys::SherpaOnnxDestroyOfflineRecognizerResult(result);
+                RecognitionResult {
+                    tokens,
+                    timestamps,
+                    log_probs,
+                }
+            };
+            sys::SherpaOnnxDestroyOfflineStream(stream);
+            recognition
+        }
+    }
+}
+
+impl Drop for OfflineTransducerRecognizer {
+    fn drop(&mut self) {
+        unsafe { sys::SherpaOnnxDestroyOfflineRecognizer(self.recognizer) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoding_options_map_to_sherpa_config() {
+        let greedy = DecodingConfig::new(&DecodingOptions::default(), None);
+        assert_eq!(greedy.method, "greedy_search");
+        assert_eq!(greedy.max_active_paths, 4);
+        assert_eq!(greedy.hotwords_file, "");
+
+        let beam = DecodingOptions {
+            method: DecodingMethod::ModifiedBeamSearch,
+            max_active_paths: 8,
+            blank_penalty: 0.5,
+        };
+        let hotwords = Path::new("/tmp/hotwords.txt");
+        let config = DecodingConfig::new(&beam, Some((hotwords, 2.0)));
+        assert_eq!(
+            config,
+            DecodingConfig {
+                method: "modified_beam_search",
+                max_active_paths: 8,
+                blank_penalty: 0.5,
+                hotwords_file: "/tmp/hotwords.txt".to_string(),
+                hotwords_score: 2.0,
+            }
+        );
+
+        // greedy search ではホットワードを渡さない
+        let ignored = DecodingConfig::new(&DecodingOptions::default(), Some((hotwords, 2.0)));
+        assert_eq!(
+            (ignored.hotwords_file.as_str(), ignored.hotwords_score),
+            ("", 0.0)
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::models::{ModelInfo, ModelKind};
+use crate::recognizer::{log_probs_from_json, StreamingRecognizer};
+use sherpa_rs_sys as sys;
+use std::ffi::{CStr, CString};
+use std::path::Path;
+
+// 発話の終わりとみなす無音の長さ（秒）。まだ何も認識していない場合と、認識済みの場合
+const RULE1_MIN_TRAILING_SILENCE: f32 = 2.4;
+const RULE2_MIN_TRAILING_SILENCE: f32 = 1.2;
+// これより長い発話は無音がなくても区切る（秒）
+const RULE3_MIN_UTTERANCE_LENGTH: f32 = 20.0;
+
+/// sherpa-onnx のオンライン（ストリーミング）認識器
+///
+/// sherpa-rs はオンライン認識器を提供していないため、C API を直接呼び出す。
+/// ストリームは1本だけ持ち、発話ごとに `reset` で使い回す。
+pub struct OnlineRecognizer {
+    recognizer: *const sys::SherpaOnnxOnlineRecognizer,
+    stream: *const sys::SherpaOnnxOnlineStream,
+    // 直前の `decode` で得た仮説のトークンごとの対数確率
+    log_probs: Vec<f32>,
+}
+
+impl OnlineRecognizer {
+    pub fn new(model: &ModelInfo, model_dir_path: &Path) -> Result<Self, String> {
+        let ModelKind::OnlineTransducer {
+            encoder,
+            decoder,
+            joiner,
+        } = model.kind
+        else {
+            return Err(format!("model {} does not support streaming", model.id));
+        };
+
+        let c_path = |file: &str| {
+            let path = model_dir_path.join(file);
+            if !path.exists() {
+                return Err(format!("model file not found: {}", path.display()));
+            }
+            CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())
+        };
+        let encoder = c_path(encoder)?;
+        let decoder = c_path(decoder)?;
+        let joiner = c_path(joiner)?;
+        let tokens = c_path(model.tokens)?;
+        let provider = CString::new("cpu").unwrap();
+        let decoding_method = CString::new("greedy_search").unwrap();
+
+        // 使わないフィールドはゼロ（NULL）のままにすると sherpa-onnx の既定値になる
+        let config = unsafe {
+            sys::SherpaOnnxOnlineRecognizerConfig {
+                feat_config: sys::SherpaOnnxFeatureConfig {
+                    sample_rate: model.sample_rate as i32,
+                    feature_dim: model.feature_dim,
+                },
+                model_config: sys::SherpaOnnxOnlineModelConfig {
+                    transducer: sys::SherpaOnnxOnlineTransducerModelConfig {
+                        encoder: encoder.as_ptr(),
+                        decoder: decoder.as_ptr(),
+                        joiner: joiner.as_ptr(),
+                    },
+                    tokens: tokens.as_ptr(),
+                    num_threads: crate::recognizer::num_threads(),
+                    provider: provider.as_ptr(),
+                    debug: 1,
+                    ..std::mem::zeroed()
+                },
+                decoding_method: decoding_method.as_ptr(),
+                max_active_paths: 4,
+                enable_endpoint: 1,
+                rule1_min_trailing_silence: RULE1_MIN_TRAILING_SILENCE,
+                rule2_min_trailing_silence: RULE2_MIN_TRAILING_SILENCE,
+                rule3_min_utterance_length: RULE3_MIN_UTTERANCE_LENGTH,
+                ..std::mem::zeroed()
+            }
+        };
+
+        let recognizer = unsafe { sys::SherpaOnnxCreateOnlineRecognizer(&config) };
+        if recognizer.is_null() {
+            return Err(format!(
+                "failed to create online recognizer from: {}",
+                model_dir_path.display()
+            ));
+        }
+        let stream = unsafe { sys::SherpaOnnxCreateOnlineStream(recognizer) };
+        if stream.is_null() {
+            unsafe { sys::SherpaOnnxDestroyOnlineRecognizer(recognizer) };
+            return Err("failed to create online stream".to_string());
+        }
+        Ok(OnlineRecognizer {
+            recognizer,
+            stream,
+            log_probs: Vec::new(),
+        })
+    }
+}
+
+impl StreamingRecognizer for OnlineRecognizer {
+    fn accept_waveform(&mut self, sample_rate: u32, samples: &[f32]) {
+        // 入力のサンプリングレートがモデルと異なる場合は sherpa-onnx が内部でリサンプリングする
+        unsafe {
+            sys::SherpaOnnxOnlineStreamAcceptWaveform(
+                self.stream,
+                sample_rate as i32,
+                samples.as_ptr(),
+                samples.len() as i32,
+            )
+        };
+    }
+
+    fn decode(&mut self) -> String {
+        unsafe {
+            while sys::SherpaOnnxIsOnlineStreamReady(self.recognizer, self.stream) == 1 {
+                sys::SherpaOnnxDecodeOnlineStream(self.recognizer, self.stream);
+            }
+            self.log_probs.clear();
+            let result = sys::SherpaOnnxGetOnlineStreamResult(self.recognizer, self.stream);
+            if result.is_null() {
+                return String::new();
+            }
+            let text = if (*result).text.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr((*result).text)
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            if !(*result).json.is_null() {
+                let json = CStr::from_ptr((*result).json).to_string_lossy();
+                let count = (*result).count.max(0) as usize;
+                self.log_probs = log_probs_from_json(&json, "ys_probs", count);
+            }
+            sys::SherpaOnnxDestroyOnlineRecognizerResult(result);
+            text
+        }
+    }
+
+    fn log_probs(&self) -> Vec<f32> {
+        self.log_probs.clone()
+    }
+
+    fn is_endpoint(&mut self) -> bool {
+        unsafe { sys::SherpaOnnxOnlineStreamIsEndpoint(self.recognizer, self.stream) == 1 }
+    }
+
+    fn reset(&mut self) {
+        unsafe { sys::SherpaOnnxOnlineStreamReset(self.recognizer, self.stream) };
+    }
+
+    fn input_finished(&mut self) {
+        unsafe { sys::SherpaOnnxOnlineStreamInputFinished(self.stream) };
+    }
+}
+
+impl Drop for OnlineRecognizer {
+    fn drop(&mut self) {
+        unsafe {
+            sys::SherpaOnnxDestroyOnlineStream(self.stream);
+            sys::SherpaOnnxDestroyOnlineRecognizer(self.recognizer);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Optional level and filtering stage between resampling and recognition.
+//!
+//! DC removal and the high-pass filter run block by block inside `AudioStream`. Normalisation
+//! needs to see the whole signal first, so it is applied once the signal has been read.
+use std::f64::consts::PI;
+
+/// Which preprocessing steps to apply. Everything is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PreprocessOptions {
+    /// Remove a constant offset (e.g. from a cheap microphone preamp).
+    pub remove_dc: bool,
+    /// Cut-off of a second-order Butterworth high-pass filter, for rumble and handling noise.
+    pub high_pass_hz: Option<f32>,
+    /// Level to scale the signal to. Unlike the filters, this needs the whole signal in memory,
+    /// about 230 MB per hour of audio.
+    pub normalization: Option<Normalization>,
+}
+
+/// How the level of the signal is normalised.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum Normalization {
+    /// Scale so the highest peak reaches `target_dbfs` (0 or below).
+    #[serde(rename_all = "camelCase")]
+    Peak { target_dbfs: f32 },
+    /// Scale so the integrated loudness (ITU-R BS.1770 / EBU R128 gating) reaches `target_lufs`.
+    /// The gain is reduced if it would push peaks above full scale.
+    #[serde(rename_all = "camelCase")]
+    Loudness { target_lufs: f32 },
+}
+
+/// Default target for `--normalize loudness`, the EBU R128 broadcast level.
+pub const DEFAULT_TARGET_LUFS: f32 = -23.0;
+/// Default target for `--normalize peak`.
+pub const DEFAULT_TARGET_DBFS: f32 = -1.0;
+
+impl PreprocessOptions {
+    pub fn validate(&self, sample_rate: u32) -> Result<(), String> {
+        if let Some(hz) = self.high_pass_hz {
+            let nyquist = sample_rate as f32 / 2.0;
+            if !(hz > 0.0 && hz < nyquist) {
+                return Err(format!(
+                    "high-pass cut-off must be between 0 and {} Hz, got {}",
+                    nyquist, hz
+                ));
+            }
+        }
+        match self.normalization {
+            Some(Normalization::Peak { target_dbfs }) if !(..=0.0).contains(&target_dbfs) => Err(
+                format!("peak target must be 0 dBFS or below, got {}", target_dbfs),
+            ),
+            Some(Normalization::Loudness { target_lufs })
+                if !(-70.0..=0.0).contains(&target_lufs) =>
+            {
+                Err(format!(
+                    "loudness target must be between -70 and 0 LUFS, got {}",
+                    target_lufs
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The streaming filters to run on each block, or `None` if none are enabled.
+    pub fn filters(&self, sample_rate: u32) -> Option<Filters> {
+        if !self.remove_dc && self.high_pass_hz.is_none() {
+            return None;
+        }
+        Some(Filters {
+            dc_blocker: self.remove_dc.then(DcBlocker::default),
+            high_pass: self
+                .high_pass_hz
+                .map(|hz| Biquad::high_pass(hz as f64, sample_rate)),
+        })
+    }
+}
+
+/// DC removal and high-pass filtering, keeping their state between blocks.
+#[derive(Debug, Clone)]
+pub struct Filters {
+    dc_blocker: Option<DcBlocker>,
+    high_pass: Option<Biquad>,
+}
+
+impl Filters {
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let mut x = *sample as f64;
+            if let Some(dc_blocker) = &mut self.dc_blocker {
+                x = dc_blocker.process(x);
+            }
+            if let Some(high_pass) = &mut self.high_pass {
+                x = high_pass.process(x);
+            }
+            *sample = x as f32;
+        }
+    }
+}
+
+// One-pole DC blocker, y[n] = x[n] - x[n-1] + R * y[n-1]. R = 0.995 puts the cut-off around
+// 13 Hz at 16 kHz, well below speech.
+#[derive(Debug, Clone, Default)]
+struct DcBlocker {
+    prev_input: f64,
+    prev_output: f64,
+}
+
+impl DcBlocker {
+    const POLE: f64 = 0.995;
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = x - self.prev_input + Self::POLE * self.prev_output;
+        self.prev_input = x;
+        self.prev_output = y;
+        y
+    }
+}
+
+// Second-order IIR section in direct form I, coefficients normalised by a0.
+#[derive(Debug, Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    // Butterworth high-pass from the Audio EQ Cookbook.
+    fn high_pass(cutoff_hz: f64, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Biquad::new(
+            [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// The two K-weighting stages of BS.1770 (a high shelf modelling the head, then a high-pass),
+// derived for any sample rate as in libebur128.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Integrated loudness in LUFS of a mono signal, using 400 ms blocks with 75 % overlap, an
+/// absolute gate at -70 LUFS and a relative gate 10 LU below the level of the blocks that pass
+/// the absolute gate. `None` for signals that are silent or shorter than one block.
+pub fn integrated_loudness(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let [mut shelf, mut high_pass] = k_weighting(sample_rate);
+    // Sum the weighted energy per 100 ms step; each block spans four consecutive steps.
+    let step = sample_rate as usize / 10;
+    if step == 0 {
+        return None;
+    }
+    let step_energies: Vec<f64> = samples
+        .chunks_exact(step)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|&x| high_pass.process(shelf.process(x as f64)).powi(2))
+                .sum()
+        })
+        .collect();
+    if step_energies.len() < 4 {
+        return None;
+    }
+    let block_powers: Vec<f64> = step_energies
+        .windows(4)
+        .map(|window| window.iter().sum::<f64>() / (4 * step) as f64)
+        .collect();
+
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10();
+    let gated_mean = |threshold: f64| {
+        let gated: Vec<f64> = block_powers
+            .iter()
+            .copied()
+            .filter(|&power| loudness(power) > threshold)
+            .collect();
+        (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+    let absolute = gated_mean(-70.0)?;
+    let relative = gated_mean(loudness(absolute) - 10.0)?;
+    Some(loudness(relative) as f32)
+}
+
+/// Scales `samples` in place to the requested level. Silent signals are left unchanged.
+pub fn normalize(samples: &mut [f32], normalization: &Normalization, sample_rate: u32) {
+    let peak = samples.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+    if peak == 0.0 {
+        return;
+    }
+    let gain = match *normalization {
+        Normalization::Peak { target_dbfs } => db_to_gain(target_dbfs) / peak,
+        Normalization::Loudness { target_lufs } => {
+            let Some(loudness) = integrated_loudness(samples, sample_rate) else {
+                return;
+            };
+            // Clipping would hurt recognition more than a level slightly below the target.
+            (db_to_gain(target_lufs - loudness)).min(1.0 / peak)
+        }
+    };
+    log::info!("Normalising with a gain of {:.1} dB", 20.0 * gain.log10());
+    for sample in samples {
+        *sample *= gain;
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    fn sine(frequency: f32, amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(SAMPLE_RATE as f32 * seconds) as usize)
+            .map(|i| {
+                amplitude
+                    * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn dc_removal_cancels_a_constant_offset() {
+        let mut samples: Vec<f32> = sine(440.0, 0.2, 2.0).iter().map(|x| x + 0.3).collect();
+        let options = PreprocessOptions {
+            remove_dc: true,
+            ..PreprocessOptions::default()
+        };
+        let mut filters = options.filters(SAMPLE_RATE).unwrap();
+        // Blocks of different sizes must give the same result as one pass.
+        let (first, rest) = samples.split_at_mut(1234);
+        filters.process(first);
+        filters.process(rest);
+
+        let settled = &samples[SAMPLE_RATE as usize..];
+        let mean = settled.iter().sum::<f32>() / settled.len() as f32;
+        assert!(mean.abs() < 0.005, "mean {}", mean);
+        assert!((rms(settled) - 0.2 / 2f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn high_pass_attenuates_rumble_and_keeps_speech() {
+        let options = PreprocessOptions {
+            high_pass_hz: Some(100.0),
+            ..PreprocessOptions::default()
+        };
+        let filtered_rms = |frequency: f32| {
+            let mut samples = sine(frequency, 0.5, 1.0);
+            options.filters(SAMPLE_RATE).unwrap().process(&mut samples);
+            rms(&samples[SAMPLE_RATE as usize / 2..]) / rms(&sine(frequency, 0.5, 1.0))
+        };
+        // A second-order filter falls by about 12 dB per octave below the cut-off.
+        assert!(filtered_rms(25.0) < 0.1);
+        assert!((filtered_rms(100.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.05);
+        assert!((filtered_rms(1000.0) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn loudness_matches_the_bs1770_reference_tone() {
+        // A full-scale 997 Hz sine measures -3.01 LUFS on a single channel.
+        let loudness = integrated_loudness(&sine(997.0, 1.0, 5.0), SAMPLE_RATE).unwrap();
+        assert!((loudness + 3.01).abs() < 0.1, "loudness {}", loudness);
+
+        // Silence between the words is gated out of the measurement.
+        let mut speech_with_pauses = sine(997.0, 0.1, 3.0);
+        speech_with_pauses.extend(vec![0.0; SAMPLE_RATE as usize * 3]);
+        let loudness = integrated_loudness(&speech_with_pauses, SAMPLE_RATE).unwrap();
+        assert!((loudness + 23.01).abs() < 0.3, "loudness {}", loudness);
+
+        assert_eq!(integrated_loudness(&[0.0; 16000], SAMPLE_RATE), None);
+        assert_eq!(integrated_loudness(&[0.5; 100], SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn normalisation_reaches_the_target_without_clipping() {
+        let mut quiet = sine(997.0, 0.01, 3.0);
+        normalize(
+            &mut quiet,
+            &Normalization::Loudness { target_lufs: -23.0 },
+            SAMPLE_RATE,
+        );
+        let loudness = integrated_loudness(&quiet, SAMPLE_RATE).unwrap();
+        assert!((loudness + 23.0).abs() < 0.05, "loudness {}", loudness);
+
+        let mut peaks = sine(997.0, 0.1, 1.0);
+        normalize(
+            &mut peaks,
+            &Normalization::Peak { target_dbfs: -1.0 },
+            SAMPLE_RATE,
+        );
+        let peak = peaks.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!((peak - db_to_gain(-1.0)).abs() < 1e-4);
+
+        // A target louder than the signal can reach without clipping is capped at full scale.
+        let mut loud = sine(997.0, 0.5, 1.0);
+        normalize(
+            &mut loud,
+            &Normalization::Loudness { target_lufs: 0.0 },
+            SAMPLE_RATE,
+        );
+        let peak = loud.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!(peak <= 1.0 + 1e-6);
+
+        let mut silence = vec![0.0; 16000];
+        normalize(
+            &mut silence,
+            &Normalization::Peak { target_dbfs: 0.0 },
+            SAMPLE_RATE,
+        );
+        assert!(silence.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn options_are_validated() {
+        let valid = PreprocessOptions {
+            remove_dc: true,
+            high_pass_hz: Some(80.0),
+            normalization: Some(Normalization::Loudness { target_lufs: -23.0 }),
+        };
+        assert!(valid.validate(SAMPLE_RATE).is_ok());
+        for invalid in [
+            PreprocessOptions {
+                high_pass_hz: Some(8000.0),
+                ..valid
+            },
+            PreprocessOptions {
+                high_pass_hz: Some(f32::NAN),
+                ..valid
+            },
+            PreprocessOptions {
+                normalization: Some(Normalization::Peak { target_dbfs: 1.0 }),
+                ..valid
+            },
+            PreprocessOptions {
+                normalization: Some(Normalization::Loudness { target_lufs: -80.0 }),
+                ..valid
+            },
+        ] {
+            assert!(invalid.validate(SAMPLE_RATE).is_err(), "{:?}", invalid);
+        }
+        assert!(PreprocessOptions::default().filters(SAMPLE_RATE).is_none());
+    }
+}
@@ -0,0 +1,633 @@
+use crate::models::{ModelInfo, ModelKind};
+use crate::offline_recognizer::OfflineTransducerRecognizer;
+use sherpa_rs::sense_voice::{SenseVoiceConfig, SenseVoiceRecognizer};
+use sherpa_rs::whisper::{WhisperConfig, WhisperRecognizer};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// ホットワードごとに加点を指定しなかった場合の加点（sherpa-onnx の既定値と同じ）
+const DEFAULT_HOTWORDS_SCORE: f32 = 1.5;
+
+/// トランスデューサーモデルのデコード方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DecodingMethod {
+    /// 各時刻で最も確率の高いトークンだけを選ぶ（最も速い）
+    #[default]
+    GreedySearch,
+    /// 複数の仮説を保持して選ぶ（遅いが精度が上がる。ホットワードにはこちらが必要）
+    ModifiedBeamSearch,
+}
+
+impl DecodingMethod {
+    // sherpa-onnx の `decoding_method` に渡す名前
+    pub(crate) fn as_sherpa(self) -> &'static str {
+        match self {
+            DecodingMethod::GreedySearch => "greedy_search",
+            DecodingMethod::ModifiedBeamSearch => "modified_beam_search",
+        }
+    }
+}
+
+/// トランスデューサーモデルのデコード設定（他の種類のモデルでは無視する）
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DecodingOptions {
+    pub method: DecodingMethod,
+    /// modified beam search で保持する仮説の数（ビーム幅）
+    pub max_active_paths: u32,
+    /// 空白トークンのスコアから差し引く値。大きくすると単語の脱落が減るが、挿入が増える
+    pub blank_penalty: f32,
+}
+
+impl Default for DecodingOptions {
+    fn default() -> Self {
+        DecodingOptions {
+            method: DecodingMethod::GreedySearch,
+            max_active_paths: 4,
+            blank_penalty: 0.0,
+        }
+    }
+}
+
+impl DecodingOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_active_paths == 0 {
+            return Err("max_active_paths must be at least 1".to_string());
+        }
+        if !self.blank_penalty.is_finite() || self.blank_penalty < 0.0 {
+            return Err(format!(
+                "blank_penalty must be a non-negative number: {}",
+                self.blank_penalty
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 認識で優先する語句と、その加点（省略時は既定値）
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hotword {
+    pub phrase: String,
+    #[serde(default)]
+    pub boost: Option<f32>,
+}
+
+impl Hotword {
+    /// 1行に1語句、加点を付ける場合は `語句 :2.0` の形式で書かれた一覧を読む
+    pub fn parse_list(text: &str) -> Result<Vec<Hotword>, String> {
+        let mut hotwords = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (phrase, boost) = match line.rsplit_once(" :") {
+                Some((phrase, boost)) => {
+                    let boost = boost
+                        .trim()
+                        .parse::<f32>()
+                        .map_err(|e| format!("invalid hotword boost in {:?}: {}", line, e))?;
+                    if !boost.is_finite() {
+                        return Err(format!("invalid hotword boost in {:?}: not a number", line));
+                    }
+                    (phrase, Some(boost))
+                }
+                None => (line, None),
+            };
+            hotwords.push(Hotword {
+                phrase: phrase.trim().to_string(),
+                boost,
+            });
+        }
+        Ok(hotwords)
+    }
+}
+
+// sherpa-onnx のホットワードファイルの内容（1行に1語句、行末の `:加点` は語句ごとの加点）
+fn format_hotwords(hotwords: &[Hotword]) -> String {
+    let mut out = String::new();
+    for hotword in hotwords {
+        let phrase = hotword
+            .phrase
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if phrase.is_empty() {
+            continue;
+        }
+        out.push_str(&phrase);
+        if let Some(boost) = hotword.boost {
+            out.push_str(&format!(" :{}", boost));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// sherpa-onnx はホットワードをファイルからしか読めないので、アプリのデータディレクトリに
+// 書き出す。名前の推測できる共有の一時ディレクトリは他のユーザーに先回りされうるため使わず、
+// 毎回新しい名前で作成する。ファイルは認識器と一緒に破棄する
+struct HotwordsFile {
+    path: PathBuf,
+}
+
+impl HotwordsFile {
+    fn create(dir: &Path, hotwords: &[Hotword]) -> Result<Self, String> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create directory {}: {}", dir.display(), e))?;
+        let contents = format_hotwords(hotwords);
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.subsec_nanos());
+            let path = dir.join(format!(
+                "hotwords-{}-{}-{:08x}.txt",
+                std::process::id(),
+                NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                // 同じ名前のファイルが残っていた場合は別の名前で作り直す
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(format!(
+                        "failed to create hotwords file {}: {}",
+                        path.display(),
+                        e
+                    ))
+                }
+            };
+            // 書き込みに失敗した場合も、作ったファイルは drop で削除される
+            let hotwords_file = HotwordsFile { path };
+            file.write_all(contents.as_bytes()).map_err(|e| {
+                format!(
+                    "failed to write hotwords file {}: {}",
+                    hotwords_file.path.display(),
+                    e
+                )
+            })?;
+            return Ok(hotwords_file);
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for HotwordsFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!(
+                "Failed to remove hotwords file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// 認識結果のトークン列と、各トークンのチャンク先頭からの相対時刻（秒）
+#[derive(Debug, Clone, Default)]
+pub struct RecognitionResult {
+    pub tokens: Vec<String>,
+    pub timestamps: Vec<f32>,
+    /// 各トークンの対数確率（エンジンが返さない場合は空）
+    pub log_probs: Vec<f32>,
+}
+
+impl RecognitionResult {
+    /// トークンごとの対数確率。エンジンが返さなかった場合はすべて `None`
+    pub fn token_log_probs(&self) -> Vec<Option<f32>> {
+        if self.log_probs.len() == self.tokens.len() {
+            self.log_probs.iter().copied().map(Some).collect()
+        } else {
+            vec![None; self.tokens.len()]
+        }
+    }
+}
+
+/// sherpa-onnx の認識結果の JSON から、トークンごとの対数確率を読む
+///
+/// オフライン認識は `ys_log_probs`、オンライン認識は `ys_probs`（名前に反して対数確率）に入る。
+/// 値がない、またはトークン数と合わない場合は空を返す（信頼度は `None` になる）。
+pub(crate) fn log_probs_from_json(json: &str, key: &str, token_count: usize) -> Vec<f32> {
+    let log_probs: Vec<f32> = serde_json::from_str::<serde_json::Value>(json)
+        .ok()
+        .and_then(|value| {
+            value
+                .get(key)?
+                .as_array()?
+                .iter()
+                .map(|p| p.as_f64().map(|p| p as f32))
+                .collect()
+        })
+        .unwrap_or_default();
+    if log_probs.len() == token_count {
+        log_probs
+    } else {
+        Vec::new()
+    }
+}
+
+/// チャンク単位で音声を認識するエンジン
+///
+/// チャンク分割や文の組み立てはこのトレイトだけに依存するため、
+/// 実際のモデルを使わずに偽のエンジンで検証できる。
+pub trait Recognizer {
+    fn transcribe(&mut self, sample_rate: u32, samples: &[f32]) -> RecognitionResult;
+}
+
+/// 音声を少しずつ受け取りながら認識するエンジン（ライブ文字起こし用）
+///
+/// 1発話ごとに `reset` するまで、`decode` は発話の先頭からの仮説全体を返す。
+pub trait StreamingRecognizer {
+    fn accept_waveform(&mut self, sample_rate: u32, samples: &[f32]);
+    /// 受け取った音声をデコードできるだけデコードし、現在の仮説を返す
+    fn decode(&mut self) -> String;
+    /// 直前の `decode` が返した仮説のトークンごとの対数確率（エンジンが返さない場合は空）
+    fn log_probs(&self) -> Vec<f32> {
+        Vec::new()
+    }
+    /// 発話の終わり（一定時間の無音など）を検出したかどうか
+    fn is_endpoint(&mut self) -> bool;
+    /// 次の発話に備えて仮説を捨てる
+    fn reset(&mut self);
+    /// これ以上音声が来ないことを伝え、残りをデコードできるようにする
+    fn input_finished(&mut self);
+}
+
+impl Recognizer for SenseVoiceRecognizer {
+    fn transcribe(&mut self, sample_rate: u32, samples: &[f32]) -> RecognitionResult {
+        let result = SenseVoiceRecognizer::transcribe(self, sample_rate, samples);
+        with_estimated_timestamps(
+            result.tokens,
+            result.timestamps,
+            samples.len() as f32 / sample_rate as f32,
+        )
+    }
+}
+
+impl Recognizer for WhisperRecognizer {
+    fn transcribe(&mut self, sample_rate: u32, samples: &[f32]) -> RecognitionResult {
+        let result = WhisperRecognizer::transcribe(self, sample_rate, samples);
+        with_estimated_timestamps(
+            result.tokens,
+            result.timestamps,
+            samples.len() as f32 / sample_rate as f32,
+        )
+    }
+}
+
+// Whisper などトークンごとの時刻を返さないモデルでは、チャンク内に等間隔で並べた時刻で代用する。
+// 重なりの除去には使えないので、これらのモデルではチャンクを重ねない（`ChunkOptions::for_model`）
+fn with_estimated_timestamps(
+    tokens: Vec<String>,
+    timestamps: Vec<f32>,
+    duration: f32,
+) -> RecognitionResult {
+    if timestamps.len() == tokens.len() {
+        return RecognitionResult {
+            tokens,
+            timestamps,
+            log_probs: Vec::new(),
+        };
+    }
+    let step = duration / tokens.len().max(1) as f32;
+    let timestamps = (0..tokens.len()).map(|i| i as f32 * step).collect();
+    RecognitionResult {
+        tokens,
+        timestamps,
+        log_probs: Vec::new(),
+    }
+}
+
+/// 読み込んだ認識器をそのまま使えるかを決める設定（モデルが対応しない設定は取り除いたもの）
+///
+/// これが異なると認識器を読み込み直すため、ジョブキューは異なるジョブを同時に実行しない。
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineSettings {
+    model_id: &'static str,
+    decoding: DecodingOptions,
+    hotwords: Vec<Hotword>,
+}
+
+impl EngineSettings {
+    pub fn new(
+        model: &'static ModelInfo,
+        decoding: &DecodingOptions,
+        hotwords: &[Hotword],
+    ) -> Self {
+        let (decoding, hotwords) = effective_settings(model, decoding, hotwords);
+        EngineSettings {
+            model_id: model.id,
+            decoding,
+            hotwords,
+        }
+    }
+}
+
+struct LoadedRecognizer {
+    settings: EngineSettings,
+    engine: Box<dyn Recognizer + Send>,
+    // 認識器を使っている間はホットワードファイルを残しておく
+    _hotwords_file: Option<HotwordsFile>,
+}
+
+// モデルが対応しない設定を外す。ホットワードは modified beam search でしか使えないので、
+// 指定されていればデコード方法を切り替える
+fn effective_settings(
+    model: &ModelInfo,
+    decoding: &DecodingOptions,
+    hotwords: &[Hotword],
+) -> (DecodingOptions, Vec<Hotword>) {
+    let mut decoding = *decoding;
+    if !model.supports_beam_search() {
+        if decoding.method != DecodingMethod::GreedySearch {
+            log::warn!(
+                "Model {} only supports greedy search; ignoring {:?}",
+                model.id,
+                decoding.method
+            );
+            decoding.method = DecodingMethod::GreedySearch;
+        }
+        if !hotwords.is_empty() {
+            log::warn!(
+                "Model {} does not support hotwords; ignoring them",
+                model.id
+            );
+        }
+        return (decoding, Vec::new());
+    }
+    if !hotwords.is_empty() {
+        decoding.method = DecodingMethod::ModifiedBeamSearch;
+    }
+    (decoding, hotwords.to_vec())
+}
+
+/// 複数のジョブで共有する認識器
+///
+/// モデルは最初のジョブで読み込み、キューが空になった時点で解放する。
+/// 別のモデルや別のデコード設定・ホットワードを要求された場合は読み込み直す。
+#[derive(Default)]
+pub struct SharedRecognizer {
+    inner: Mutex<Option<LoadedRecognizer>>,
+}
+
+impl SharedRecognizer {
+    /// `decoding` と `hotwords` は対応するモデルでのみ使い、それ以外のモデルでは無視する
+    pub fn ensure_loaded(
+        &self,
+        model: &'static ModelInfo,
+        model_dir_path: &Path,
+        decoding: &DecodingOptions,
+        hotwords: &[Hotword],
+    ) -> Result<(), String> {
+        decoding.validate()?;
+        let settings = EngineSettings::new(model, decoding, hotwords);
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .as_ref()
+            .is_some_and(|loaded| loaded.settings == settings)
+        {
+            return Ok(());
+        }
+        log::info!(
+            "Loading recognizer {} ({:?}, {} hotwords) from: {}",
+            model.id,
+            settings.decoding,
+            settings.hotwords.len(),
+            model_dir_path.display()
+        );
+        let hotwords_file = if settings.hotwords.is_empty() {
+            None
+        } else {
+            // モデルディレクトリの親（アプリのデータディレクトリ内の models）に置く
+            let dir = model_dir_path.parent().unwrap_or(model_dir_path);
+            Some(HotwordsFile::create(dir, &settings.hotwords)?)
+        };
+        let engine = load_engine(
+            model,
+            model_dir_path,
+            &settings.decoding,
+            hotwords_file.as_ref().map(HotwordsFile::path),
+        )?;
+        *inner = Some(LoadedRecognizer {
+            settings,
+            engine,
+            _hotwords_file: hotwords_file,
+        });
+        Ok(())
+    }
+
+    /// `engine` を読み込み済みの認識器として使う（FFI を通さないテスト用）
+    #[cfg(test)]
+    pub(crate) fn with_engine(
+        model: &'static ModelInfo,
+        engine: Box<dyn Recognizer + Send>,
+    ) -> Self {
+        let settings = EngineSettings::new(model, &DecodingOptions::default(), &[]);
+        SharedRecognizer {
+            inner: Mutex::new(Some(LoadedRecognizer {
+                settings,
+                engine,
+                _hotwords_file: None,
+            })),
+        }
+    }
+
+    pub fn release(&self) {
+        if self.inner.lock().unwrap().take().is_some() {
+            log::info!("Recognizer released");
+        }
+    }
+}
+
+// チャンクごとにロックを取るので、並行するジョブは認識処理だけが順番待ちになる
+impl Recognizer for &SharedRecognizer {
+    fn transcribe(&mut self, sample_rate: u32, samples: &[f32]) -> RecognitionResult {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .as_mut()
+            .expect("recognizer must be loaded before transcribing")
+            .engine
+            .transcribe(sample_rate, samples)
+    }
+}
+
+fn load_engine(
+    model: &ModelInfo,
+    model_dir_path: &Path,
+    decoding: &DecodingOptions,
+    hotwords_file: Option<&Path>,
+) -> Result<Box<dyn Recognizer + Send>, String> {
+    let path = |file: &str| model_dir_path.join(file).to_string_lossy().to_string();
+    let num_threads = num_threads();
+
+    match model.kind {
+        ModelKind::Transducer { .. } => {
+            let recognizer = OfflineTransducerRecognizer::new(
+                model,
+                model_dir_path,
+                decoding,
+                hotwords_file.map(|path| (path, DEFAULT_HOTWORDS_SCORE)),
+            )?;
+            Ok(Box::new(recognizer))
+        }
+        ModelKind::Whisper { encoder, decoder } => {
+            let config = WhisperConfig {
+                decoder: path(decoder),
+                encoder: path(encoder),
+                tokens: path(model.tokens),
+                // 空文字列なら言語を自動判定する
+                language: String::new(),
+                num_threads: Some(num_threads),
+                debug: true,
+                ..Default::default()
+            };
+            let recognizer = WhisperRecognizer::new(config).map_err(|e| e.to_string())?;
+            Ok(Box::new(recognizer))
+        }
+        ModelKind::SenseVoice { model: model_file } => {
+            let config = SenseVoiceConfig {
+                model: path(model_file),
+                tokens: path(model.tokens),
+                language: "auto".to_string(),
+                // 文末の判定に句読点が必要なので逆正規化を有効にする
+                use_itn: true,
+                num_threads: Some(num_threads),
+                debug: true,
+                ..Default::default()
+            };
+            let recognizer = SenseVoiceRecognizer::new(config).map_err(|e| e.to_string())?;
+            Ok(Box::new(recognizer))
+        }
+        ModelKind::OnlineTransducer { .. } => Err(format!(
+            "model {} can only be used for live transcription",
+            model.id
+        )),
+    }
+}
+
+pub(crate) fn num_threads() -> i32 {
+    let cpus = num_cpus::get();
+    // NOTE: CPU数が多いからといってスレッド数を増やしすぎると逆にパフォーマンスが落ちる。
+    std::cmp::max(1, std::cmp::min(6, cpus / 2)) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_probs_are_read_from_the_result_json() {
+        let offline = r#"{"lang":"","text":" hi there","timestamps":[0.12,0.40],"tokens":[" hi"," there"],"ys_log_probs":[-0.05,-1.5]}"#;
+        assert_eq!(
+            log_probs_from_json(offline, "ys_log_probs", 2),
+            vec![-0.05, -1.5]
+        );
+        let online = r#"{"text":" hi","tokens":[" hi"],"timestamps":[0.2],"ys_probs":[-0.3],"lm_probs":[],"is_final":false}"#;
+        assert_eq!(log_probs_from_json(online, "ys_probs", 1), vec![-0.3]);
+
+        // 値がない・トークン数と合わない・JSON でない場合は信頼度なしとして扱う
+        assert!(log_probs_from_json(offline, "ys_probs", 2).is_empty());
+        assert!(log_probs_from_json(offline, "ys_log_probs", 3).is_empty());
+        assert!(log_probs_from_json("not json", "ys_log_probs", 0).is_empty());
+    }
+
+    #[test]
+    fn hotword_list_parses_boosts_and_round_trips_to_sherpa_format() {
+        let hotwords =
+            Hotword::parse_list("# product names\nKotonoha  ASR :2.5\n\n  sherpa onnx\n").unwrap();
+        assert_eq!(
+            hotwords,
+            vec![
+                Hotword {
+                    phrase: "Kotonoha  ASR".to_string(),
+                    boost: Some(2.5),
+                },
+                Hotword {
+                    phrase: "sherpa onnx".to_string(),
+                    boost: None,
+                },
+            ]
+        );
+        assert_eq!(
+            format_hotwords(&hotwords),
+            "Kotonoha ASR :2.5\nsherpa onnx\n"
+        );
+        assert!(Hotword::parse_list("word :high").is_err());
+        assert!(Hotword::parse_list("word :").is_err());
+        assert!(Hotword::parse_list("word :inf").is_err());
+    }
+
+    #[test]
+    fn hotwords_files_are_unique_and_removed_on_drop() {
+        let dir =
+            std::env::temp_dir().join(format!("kotonoha-asr-hotwords-{}", std::process::id()));
+        let hotwords = Hotword::parse_list("Kotonoha ASR :2.5").unwrap();
+        let first = HotwordsFile::create(&dir, &hotwords).unwrap();
+        let second = HotwordsFile::create(&dir, &hotwords).unwrap();
+        assert_ne!(first.path(), second.path());
+        assert_eq!(
+            std::fs::read_to_string(first.path()).unwrap(),
+            "Kotonoha ASR :2.5\n"
+        );
+
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+        assert!(second.path().exists());
+        drop(second);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn effective_settings_respect_model_support_and_hotwords() {
+        let beam = DecodingOptions {
+            method: DecodingMethod::ModifiedBeamSearch,
+            max_active_paths: 8,
+            blank_penalty: 1.0,
+        };
+        let hotwords = Hotword::parse_list("Kotonoha").unwrap();
+
+        // NeMo のモデルは greedy search だけなので、デコード方法とホットワードを外す
+        let nemo = crate::models::find_offline_model(crate::models::DEFAULT_MODEL_ID).unwrap();
+        let (decoding, kept) = effective_settings(nemo, &beam, &hotwords);
+        assert_eq!(decoding.method, DecodingMethod::GreedySearch);
+        assert_eq!(decoding.blank_penalty, 1.0);
+        assert!(kept.is_empty());
+
+        // ホットワードがあれば greedy search の設定でも modified beam search に切り替える
+        let zipformer = crate::models::models()
+            .iter()
+            .find(|m| m.supports_beam_search())
+            .unwrap();
+        let (decoding, kept) =
+            effective_settings(zipformer, &DecodingOptions::default(), &hotwords);
+        assert_eq!(decoding.method, DecodingMethod::ModifiedBeamSearch);
+        assert_eq!(kept, hotwords);
+
+        assert!(DecodingOptions {
+            max_active_paths: 0,
+            ..DecodingOptions::default()
+        }
+        .validate()
+        .is_err());
+        assert!(DecodingOptions {
+            blank_penalty: f32::NAN,
+            ..DecodingOptions::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::asr::ChunkOptions;
+use crate::models::{self, ModelInfo};
+use crate::recognizer::{DecodingOptions, Hotword};
+use std::collections::HashMap;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+// フロントエンドの設定（ホットワードなど）と同じストア
+const SETTINGS_STORE: &str = "settings.json";
+const DECODING_KEY: &str = "decoding";
+// モデル ID ごとのチャンク設定
+const CHUNKING_KEY: &str = "chunking";
+
+/// 保存されたデコード設定。未保存または読めない場合は既定値
+pub fn load_decoding_options(app_handle: &AppHandle) -> DecodingOptions {
+    let store = match app_handle.store(SETTINGS_STORE) {
+        Ok(store) => store,
+        Err(e) => {
+            log::warn!("Failed to open settings store: {}", e);
+            return DecodingOptions::default();
+        }
+    };
+    store
+        .get(DECODING_KEY)
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring invalid decoding settings: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn get_decoding_options(app_handle: AppHandle) -> DecodingOptions {
+    load_decoding_options(&app_handle)
+}
+
+/// デコード設定を検証して保存する。次に読み込む認識器から反映される
+#[tauri::command]
+pub fn set_decoding_options(app_handle: AppHandle, options: DecodingOptions) -> Result<(), String> {
+    options.validate()?;
+    let store = app_handle
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("failed to open settings store: {}", e))?;
+    let value = serde_json::to_value(options)
+        .map_err(|e| format!("failed to serialize decoding settings: {}", e))?;
+    store.set(DECODING_KEY, value);
+    store
+        .save()
+        .map_err(|e| format!("failed to save settings: {}", e))
+}
+
+/// ホットワードの一覧のテキストを読む。CLI のホットワードファイルと同じ文法で検証するため、
+/// フロントエンドでは解釈せずにこのコマンドを使う
+#[tauri::command]
+pub fn parse_hotwords(text: String) -> Result<Vec<Hotword>, String> {
+    Hotword::parse_list(&text)
+}
+
+// 保存されたモデルごとのチャンク設定（読めない場合は空）
+fn load_chunking_map(app_handle: &AppHandle) -> HashMap<String, ChunkOptions> {
+    let store = match app_handle.store(SETTINGS_STORE) {
+        Ok(store) => store,
+        Err(e) => {
+            log::warn!("Failed to open settings store: {}", e);
+            return HashMap::new();
+        }
+    };
+    store
+        .get(CHUNKING_KEY)
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| log::warn!("Ignoring invalid chunk settings: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// モデルに保存されたチャンク設定。未保存またはモデルに合わない場合は既定値
+pub fn load_chunk_options(app_handle: &AppHandle, model: &ModelInfo) -> ChunkOptions {
+    load_chunking_map(app_handle)
+        .remove(model.id)
+        .filter(|chunking| chunking.validate(model).is_ok())
+        .unwrap_or_default()
+}
+
+/// `model_id` を省略すると既定のモデルの設定を返す
+#[tauri::command]
+pub fn get_chunk_options(
+    app_handle: AppHandle,
+    model_id: Option<String>,
+) -> Result<ChunkOptions, String> {
+    let model =
+        models::find_offline_model(model_id.as_deref().unwrap_or(models::DEFAULT_MODEL_ID))?;
+    Ok(load_chunk_options(&app_handle, model))
+}
+
+/// チャンク設定をモデルに合うか検証して、そのモデルの既定値として保存する
+#[tauri::command]
+pub fn set_chunk_options(
+    app_handle: AppHandle,
+    model_id: Option<String>,
+    options: ChunkOptions,
+) -> Result<(), String> {
+    let model =
+        models::find_offline_model(model_id.as_deref().unwrap_or(models::DEFAULT_MODEL_ID))?;
+    options.validate(model)?;
+    let mut chunking = load_chunking_map(&app_handle);
+    chunking.insert(model.id.to_string(), options);
+
+    let store = app_handle
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("failed to open settings store: {}", e))?;
+    let value = serde_json::to_value(chunking)
+        .map_err(|e| format!("failed to serialize chunk settings: {}", e))?;
+    store.set(CHUNKING_KEY, value);
+    store
+        .save()
+        .map_err(|e| format!("failed to save settings: {}", e))
+}
@@ -0,0 +1,638 @@
+use crate::asr::{Sentence, Word};
+use crate::export::{self, ExportFormat, SubtitleOptions, TranscriptMetadata, WordTiming};
+use crate::job_queue::JobId;
+use crate::live::SessionId;
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter};
+
+/// 文字起こしパイプラインからのイベントの通知先
+///
+/// パイプラインは Tauri に依存せず、このトレイトを通じて進捗を報告する。
+pub trait TranscriptionSink {
+    fn started(&mut self, total_duration_ms: u64);
+    fn sentence(&mut self, sentence: &Sentence);
+    fn finished(&mut self, sentences: &[Sentence], processing_time_ms: u64);
+    fn cancelled(&mut self, sentences: &[Sentence], processing_time_ms: u64);
+    fn error(&mut self, message: &str);
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartedPayload {
+    job_id: JobId,
+    total_duration_ms: u64,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProgressPayload {
+    job_id: JobId,
+    text: String,
+    start_time_ms: u64,
+    end_time_ms: u64,
+    speaker: Option<u32>,
+    /// チャンネルごとに文字起こしした場合のみ含める
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<u32>,
+    confidence: Option<f32>,
+    needs_review: bool,
+    /// ジョブで単語ごとの時刻が要求された場合のみ含める
+    #[serde(skip_serializing_if = "Option::is_none")]
+    words: Option<Vec<WordTiming>>,
+}
+
+impl ProgressPayload {
+    fn new(job_id: JobId, sentence: &Sentence, word_timestamps: bool) -> Self {
+        ProgressPayload {
+            job_id,
+            text: sentence.text.clone(),
+            start_time_ms: (sentence.start * 1000.0) as u64,
+            end_time_ms: (sentence.end * 1000.0) as u64,
+            speaker: sentence.speaker,
+            channel: sentence.channel,
+            confidence: sentence.confidence,
+            needs_review: sentence.needs_review,
+            words: word_timestamps.then(|| sentence.words.iter().map(WordTiming::from).collect()),
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FinishedPayload {
+    job_id: JobId,
+    processing_time_ms: u64,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CancelledPayload {
+    job_id: JobId,
+    sentences: Vec<ProgressPayload>,
+    processing_time_ms: u64,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    job_id: JobId,
+    message: String,
+}
+
+/// ジョブのイベントを Tauri イベントとしてフロントエンドへ送る
+pub struct TauriEventSink {
+    app_handle: AppHandle,
+    job_id: JobId,
+    word_timestamps: bool,
+}
+
+impl TauriEventSink {
+    /// `word_timestamps` が真なら、文のイベントに単語ごとの時刻を含める
+    pub fn new(app_handle: AppHandle, job_id: JobId, word_timestamps: bool) -> Self {
+        TauriEventSink {
+            app_handle,
+            job_id,
+            word_timestamps,
+        }
+    }
+
+    fn progress(&self, sentence: &Sentence) -> ProgressPayload {
+        ProgressPayload::new(self.job_id, sentence, self.word_timestamps)
+    }
+
+    // ウィンドウが閉じられた後などに送信が失敗しても処理は止めない
+    fn emit<S: serde::Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Err(e) = self.app_handle.emit(event, payload) {
+            log::warn!("Failed to emit {} for job {}: {}", event, self.job_id, e);
+        }
+    }
+}
+
+impl TranscriptionSink for TauriEventSink {
+    fn started(&mut self, total_duration_ms: u64) {
+        self.emit(
+            "asr-started",
+            StartedPayload {
+                job_id: self.job_id,
+                total_duration_ms,
+            },
+        );
+    }
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        self.emit("asr-progress", self.progress(sentence));
+    }
+
+    fn finished(&mut self, _sentences: &[Sentence], processing_time_ms: u64) {
+        self.emit(
+            "asr-finished",
+            FinishedPayload {
+                job_id: self.job_id,
+                processing_time_ms,
+            },
+        );
+    }
+
+    fn cancelled(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        self.emit(
+            "asr-cancelled",
+            CancelledPayload {
+                job_id: self.job_id,
+                sentences: sentences.iter().map(|s| self.progress(s)).collect(),
+                processing_time_ms,
+            },
+        );
+    }
+
+    fn error(&mut self, message: &str) {
+        log::error!("{}", message);
+        self.emit(
+            "asr-error",
+            ErrorPayload {
+                job_id: self.job_id,
+                message: message.to_string(),
+            },
+        );
+    }
+}
+
+/// ライブ文字起こしのイベントの通知先
+pub trait LiveTranscriptionSink {
+    /// 認識器と入力の準備ができ、音声の受け付けを始めた
+    fn started(&mut self, sample_rate: u32);
+    /// 認識中の発話の仮説（発話が確定するまで何度も更新される）
+    fn partial(&mut self, text: &str);
+    /// 発話の終わりを検出して確定した文
+    fn sentence(&mut self, sentence: &Sentence);
+    fn stopped(&mut self, sentences: &[Sentence], recording_path: Option<&str>);
+    fn error(&mut self, message: &str);
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LiveStartedPayload {
+    session_id: SessionId,
+    sample_rate: u32,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LivePartialPayload {
+    session_id: SessionId,
+    text: String,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LiveSentencePayload {
+    session_id: SessionId,
+    text: String,
+    start_time_ms: u64,
+    end_time_ms: u64,
+}
+
+impl LiveSentencePayload {
+    fn new(session_id: SessionId, sentence: &Sentence) -> Self {
+        LiveSentencePayload {
+            session_id,
+            text: sentence.text.clone(),
+            start_time_ms: (sentence.start * 1000.0) as u64,
+            end_time_ms: (sentence.end * 1000.0) as u64,
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LiveStoppedPayload {
+    session_id: SessionId,
+    sentences: Vec<LiveSentencePayload>,
+    recording_path: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LiveErrorPayload {
+    session_id: SessionId,
+    message: String,
+}
+
+/// ライブ文字起こしのイベントを Tauri イベントとしてフロントエンドへ送る
+pub struct TauriLiveEventSink {
+    app_handle: AppHandle,
+    session_id: SessionId,
+}
+
+impl TauriLiveEventSink {
+    pub fn new(app_handle: AppHandle, session_id: SessionId) -> Self {
+        TauriLiveEventSink {
+            app_handle,
+            session_id,
+        }
+    }
+
+    fn emit<S: serde::Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Err(e) = self.app_handle.emit(event, payload) {
+            log::warn!(
+                "Failed to emit {} for live session {}: {}",
+                event,
+                self.session_id,
+                e
+            );
+        }
+    }
+}
+
+impl LiveTranscriptionSink for TauriLiveEventSink {
+    fn started(&mut self, sample_rate: u32) {
+        self.emit(
+            "live-started",
+            LiveStartedPayload {
+                session_id: self.session_id,
+                sample_rate,
+            },
+        );
+    }
+
+    fn partial(&mut self, text: &str) {
+        self.emit(
+            "live-partial",
+            LivePartialPayload {
+                session_id: self.session_id,
+                text: text.to_string(),
+            },
+        );
+    }
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        self.emit(
+            "live-sentence",
+            LiveSentencePayload::new(self.session_id, sentence),
+        );
+    }
+
+    fn stopped(&mut self, sentences: &[Sentence], recording_path: Option<&str>) {
+        self.emit(
+            "live-stopped",
+            LiveStoppedPayload {
+                session_id: self.session_id,
+                sentences: sentences
+                    .iter()
+                    .map(|s| LiveSentencePayload::new(self.session_id, s))
+                    .collect(),
+                recording_path: recording_path.map(str::to_string),
+            },
+        );
+    }
+
+    fn error(&mut self, message: &str) {
+        log::error!("{}", message);
+        self.emit(
+            "live-error",
+            LiveErrorPayload {
+                session_id: self.session_id,
+                message: message.to_string(),
+            },
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptionEvent {
+    Started { total_duration_ms: u64 },
+    Sentence(Sentence),
+    Finished { processing_time_ms: u64 },
+    Cancelled { sentences: Vec<Sentence> },
+    Error(String),
+}
+
+/// 受け取ったイベントをすべてメモリに保持する（テスト用）
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub events: Vec<TranscriptionEvent>,
+}
+
+impl MemorySink {
+    /// `sentence` イベントとして通知された文を順に返す
+    pub fn sentences(&self) -> Vec<Sentence> {
+        self.events
+            .iter()
+            .filter_map(|e| match e {
+                TranscriptionEvent::Sentence(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl TranscriptionSink for MemorySink {
+    fn started(&mut self, total_duration_ms: u64) {
+        self.events
+            .push(TranscriptionEvent::Started { total_duration_ms });
+    }
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        self.events
+            .push(TranscriptionEvent::Sentence(sentence.clone()));
+    }
+
+    fn finished(&mut self, _sentences: &[Sentence], processing_time_ms: u64) {
+        self.events
+            .push(TranscriptionEvent::Finished { processing_time_ms });
+    }
+
+    fn cancelled(&mut self, sentences: &[Sentence], _processing_time_ms: u64) {
+        self.events.push(TranscriptionEvent::Cancelled {
+            sentences: sentences.to_vec(),
+        });
+    }
+
+    fn error(&mut self, message: &str) {
+        self.events
+            .push(TranscriptionEvent::Error(message.to_string()));
+    }
+}
+
+/// 完了時に文字起こし結果を指定の形式でファイルへ書き出す（バッチ処理用）
+///
+/// 出力先が `None` の場合は標準出力へ書き出す。
+pub struct FileSink {
+    path: Option<PathBuf>,
+    format: ExportFormat,
+    subtitle_options: SubtitleOptions,
+    metadata: TranscriptMetadata,
+    error: Option<String>,
+}
+
+impl FileSink {
+    /// `metadata` には入力ファイルとモデル ID を渡す。音声の長さと処理時間はパイプラインから
+    /// 通知された値で埋める。
+    pub fn new(
+        path: Option<PathBuf>,
+        format: ExportFormat,
+        subtitle_options: SubtitleOptions,
+        metadata: TranscriptMetadata,
+    ) -> Self {
+        FileSink {
+            path,
+            format,
+            subtitle_options,
+            metadata,
+            error: None,
+        }
+    }
+
+    /// パイプラインまたは書き出しで発生したエラー
+    pub fn failure(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn write(&self, sentences: &[Sentence]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let content = export::render_with_options(
+            self.format,
+            sentences,
+            &self.metadata,
+            &self.subtitle_options,
+        );
+        match &self.path {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(path, content)
+            }
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(content.as_bytes())?;
+                stdout.flush()
+            }
+        }
+    }
+}
+
+impl TranscriptionSink for FileSink {
+    fn started(&mut self, total_duration_ms: u64) {
+        self.metadata.duration_ms = Some(total_duration_ms);
+    }
+
+    fn sentence(&mut self, _sentence: &Sentence) {}
+
+    fn finished(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        self.metadata.processing_time_ms = Some(processing_time_ms);
+        if let Err(e) = self.write(sentences) {
+            self.error = Some(format!("failed to write transcript: {}", e));
+        }
+    }
+
+    fn cancelled(&mut self, _sentences: &[Sentence], _processing_time_ms: u64) {}
+
+    fn error(&mut self, message: &str) {
+        self.error = Some(message.to_string());
+    }
+}
+
+/// 要確認のしきい値が信頼度の範囲（0〜1）にあるかを確認する
+///
+/// 範囲外では、すべての文に印が付くか、どの文にも付かなくなる。
+pub fn validate_review_threshold(threshold: f32) -> Result<(), String> {
+    if (0.0..=1.0).contains(&threshold) {
+        Ok(())
+    } else {
+        Err(format!(
+            "review threshold must be between 0 and 1: {}",
+            threshold
+        ))
+    }
+}
+
+/// 信頼度がしきい値を下回る文に要確認の印を付けてから下流のシンクへ渡す
+pub struct ReviewMarkingSink<'a> {
+    inner: &'a mut dyn TranscriptionSink,
+    threshold: f32,
+}
+
+impl<'a> ReviewMarkingSink<'a> {
+    pub fn new(inner: &'a mut dyn TranscriptionSink, threshold: f32) -> Self {
+        ReviewMarkingSink { inner, threshold }
+    }
+
+    // 信頼度が分からない文は印を付けない
+    fn mark(&self, sentence: &Sentence) -> Sentence {
+        Sentence {
+            needs_review: sentence.confidence.is_some_and(|c| c < self.threshold),
+            ..sentence.clone()
+        }
+    }
+
+    fn mark_all(&self, sentences: &[Sentence]) -> Vec<Sentence> {
+        sentences.iter().map(|s| self.mark(s)).collect()
+    }
+}
+
+impl TranscriptionSink for ReviewMarkingSink<'_> {
+    fn started(&mut self, total_duration_ms: u64) {
+        self.inner.started(total_duration_ms);
+    }
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        let marked = self.mark(sentence);
+        self.inner.sentence(&marked);
+    }
+
+    fn finished(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        let marked = self.mark_all(sentences);
+        self.inner.finished(&marked, processing_time_ms);
+    }
+
+    fn cancelled(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        let marked = self.mark_all(sentences);
+        self.inner.cancelled(&marked, processing_time_ms);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.inner.error(message);
+    }
+}
+
+/// 文と単語の時刻をずらしてから下流のシンクへ渡す（ファイルの途中から文字起こしした場合に使う）
+pub struct TimeOffsetSink<'a> {
+    inner: &'a mut dyn TranscriptionSink,
+    offset: f32,
+}
+
+impl<'a> TimeOffsetSink<'a> {
+    /// `offset` は秒
+    pub fn new(inner: &'a mut dyn TranscriptionSink, offset: f32) -> Self {
+        TimeOffsetSink { inner, offset }
+    }
+
+    fn shift(&self, sentence: &Sentence) -> Sentence {
+        Sentence {
+            start: sentence.start + self.offset,
+            end: sentence.end + self.offset,
+            words: sentence
+                .words
+                .iter()
+                .map(|word| Word {
+                    start_time: word.start_time + self.offset,
+                    end_time: word.end_time + self.offset,
+                    ..word.clone()
+                })
+                .collect(),
+            ..sentence.clone()
+        }
+    }
+
+    fn shift_all(&self, sentences: &[Sentence]) -> Vec<Sentence> {
+        sentences.iter().map(|s| self.shift(s)).collect()
+    }
+}
+
+impl TranscriptionSink for TimeOffsetSink<'_> {
+    fn started(&mut self, total_duration_ms: u64) {
+        self.inner.started(total_duration_ms);
+    }
+
+    fn sentence(&mut self, sentence: &Sentence) {
+        let shifted = self.shift(sentence);
+        self.inner.sentence(&shifted);
+    }
+
+    fn finished(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        let shifted = self.shift_all(sentences);
+        self.inner.finished(&shifted, processing_time_ms);
+    }
+
+    fn cancelled(&mut self, sentences: &[Sentence], processing_time_ms: u64) {
+        let shifted = self.shift_all(sentences);
+        self.inner.cancelled(&shifted, processing_time_ms);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.inner.error(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello() -> Sentence {
+        Sentence {
+            start: 1.0,
+            end: 2.0,
+            text: "Hello.".to_string(),
+            speaker: None,
+            channel: None,
+            words: vec![Word {
+                start_time: 1.0,
+                end_time: 2.0,
+                text: "Hello.".to_string(),
+                confidence: None,
+            }],
+            confidence: None,
+            needs_review: false,
+        }
+    }
+
+    #[test]
+    fn progress_payload_includes_words_only_when_requested() {
+        let without = serde_json::to_value(ProgressPayload::new(7, &hello(), false)).unwrap();
+        assert_eq!(without.get("words"), None);
+
+        let with = serde_json::to_value(ProgressPayload::new(7, &hello(), true)).unwrap();
+        assert_eq!(
+            with["words"],
+            serde_json::json!([{
+                "text": "Hello.",
+                "startTimeMs": 1000,
+                "endTimeMs": 2000,
+                "confidence": null
+            }])
+        );
+    }
+
+    #[test]
+    fn review_marking_flags_sentences_below_threshold() {
+        let confident = Sentence {
+            confidence: Some(0.9),
+            ..hello()
+        };
+        let doubtful = Sentence {
+            confidence: Some(0.4),
+            ..hello()
+        };
+        let unknown = hello();
+
+        let mut memory = MemorySink::default();
+        let mut sink = ReviewMarkingSink::new(&mut memory, 0.6);
+        for sentence in [&confident, &doubtful, &unknown] {
+            sink.sentence(sentence);
+        }
+
+        let marks: Vec<bool> = memory.sentences().iter().map(|s| s.needs_review).collect();
+        assert_eq!(marks, vec![false, true, false]);
+
+        assert!(validate_review_threshold(0.0).is_ok());
+        assert!(validate_review_threshold(1.0).is_ok());
+        for threshold in [-1.0, 5.0, f32::NAN, f32::INFINITY] {
+            assert!(validate_review_threshold(threshold).is_err());
+        }
+    }
+
+    #[test]
+    fn time_offset_shifts_sentences_and_words() {
+        let mut memory = MemorySink::default();
+        let mut sink = TimeOffsetSink::new(&mut memory, 60.0);
+        sink.sentence(&hello());
+
+        let shifted = &memory.sentences()[0];
+        assert_eq!((shifted.start, shifted.end), (61.0, 62.0));
+        assert_eq!(
+            (shifted.words[0].start_time, shifted.words[0].end_time),
+            (61.0, 62.0)
+        );
+    }
+}
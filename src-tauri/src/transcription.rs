@@ -1,3 +1,6 @@
+use crate::asr::{Sentence, Word};
+use crate::export::{self, ExportFormat, SubtitleOptions, TranscriptMetadata, WordTiming};
+use crate::models;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::Path;
@@ -5,7 +8,70 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 #[tauri::command]
 pub fn save_transcription_file(filepath: String, content: String) -> Result<(), String> {
-    let path = Path::new(&filepath);
+    write_file_atomically(&filepath, &content)
+}
+
+/// A sentence as the frontend holds it, i.e. the payload of an `asr-progress` event.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SentenceInput {
+    text: String,
+    start_time_ms: u64,
+    end_time_ms: u64,
+    #[serde(default)]
+    speaker: Option<u32>,
+    #[serde(default)]
+    channel: Option<u32>,
+    #[serde(default)]
+    words: Vec<WordTiming>,
+    #[serde(default)]
+    confidence: Option<f32>,
+    #[serde(default)]
+    needs_review: bool,
+}
+
+impl From<SentenceInput> for Sentence {
+    fn from(input: SentenceInput) -> Self {
+        Sentence {
+            start: input.start_time_ms as f32 / 1000.0,
+            end: input.end_time_ms as f32 / 1000.0,
+            text: input.text,
+            speaker: input.speaker,
+            channel: input.channel,
+            words: input.words.into_iter().map(Word::from).collect(),
+            confidence: input.confidence,
+            needs_review: input.needs_review,
+        }
+    }
+}
+
+/// Formats the sentences on the Rust side and saves them, so the frontend does not have to
+/// know every output format.
+#[tauri::command]
+pub fn export_transcription_file(
+    filepath: String,
+    format: ExportFormat,
+    sentences: Vec<SentenceInput>,
+    metadata: Option<TranscriptMetadata>,
+    options: Option<SubtitleOptions>,
+) -> Result<(), String> {
+    let sentences: Vec<Sentence> = sentences.into_iter().map(Sentence::from).collect();
+    let mut metadata = metadata.unwrap_or_default();
+    // The frontend only knows the model ID, so look up whether that model reports confidence.
+    if metadata.confidence_available.is_none() {
+        metadata.confidence_available = metadata
+            .model_id
+            .as_deref()
+            .and_then(|id| models::find_model(id).ok())
+            .map(|model| model.reports_confidence());
+    }
+    let content =
+        export::render_with_options(format, &sentences, &metadata, &options.unwrap_or_default());
+    write_file_atomically(&filepath, &content)
+}
+
+fn write_file_atomically(filepath: &str, content: &str) -> Result<(), String> {
+    let path = Path::new(filepath);
 
     // Expect absolute path from Tauri dialog save
     if !path.is_absolute() {
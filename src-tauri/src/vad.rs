@@ -0,0 +1,263 @@
+use sherpa_rs::silero_vad::{SileroVad, SileroVadConfig};
+use std::path::Path;
+
+/// `models` ディレクトリに置かれていれば Silero VAD を使う（アプリは VAD 分割の開始時にダウンロードする）
+pub const SILERO_VAD_MODEL_FILE: &str = "silero_vad.onnx";
+
+const SILERO_WINDOW_SIZE: usize = 512;
+const SPEECH_THRESHOLD: f32 = 0.5;
+const MIN_SILENCE_SECONDS: f32 = 0.5;
+const MIN_SPEECH_SECONDS: f32 = 0.25;
+// 発話区間の前後に付ける余白（語頭・語尾の切れを防ぐ）
+const PADDING_SECONDS: f32 = 0.2;
+
+const ENERGY_FRAME_SECONDS: f32 = 0.03;
+// 無音レベル（フレームのエネルギーの下位 10%）よりこれだけ大きければ発話とみなす。
+// 無音のない録音でも全体が発話になるよう、最大レベルからも同じだけ下げた値を上限にする。
+const ENERGY_MARGIN_DB: f32 = 10.0;
+const ENERGY_FLOOR_DB: f32 = -60.0;
+
+/// 発話区間（サンプル位置、`end` は含まない）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeechSegment {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 発話区間を検出する
+///
+/// Silero VAD のモデルがあればそれを使い、なければエネルギーベースの簡易検出にフォールバックする。
+/// 認識器に一度に渡す長さを抑えるため、各区間は `max_segment_seconds` 以下に分割される。
+pub fn detect_speech(
+    samples: &[f32],
+    sample_rate: u32,
+    max_segment_seconds: usize,
+    silero_model_path: Option<&Path>,
+) -> Vec<SpeechSegment> {
+    let raw = match silero_model_path.filter(|p| p.exists()) {
+        Some(model_path) => {
+            log::info!("Using Silero VAD: {}", model_path.display());
+            match detect_with_silero(samples, sample_rate, max_segment_seconds, model_path) {
+                Ok(segments) => segments,
+                Err(e) => {
+                    log::warn!("Silero VAD failed, falling back to energy VAD: {}", e);
+                    detect_with_energy(samples, sample_rate)
+                }
+            }
+        }
+        None => {
+            // 黙ってフォールバックすると精度の差に気付けないので、探した場所も残す
+            match silero_model_path {
+                Some(model_path) => log::info!(
+                    "Silero VAD model not found at {}, using energy VAD",
+                    model_path.display()
+                ),
+                None => log::info!("Using energy VAD"),
+            }
+            detect_with_energy(samples, sample_rate)
+        }
+    };
+
+    let padded = pad_and_merge(&raw, samples.len(), sample_rate);
+    split_long_segments(
+        samples,
+        &padded,
+        sample_rate,
+        max_segment_seconds * sample_rate as usize,
+    )
+}
+
+fn detect_with_silero(
+    samples: &[f32],
+    sample_rate: u32,
+    max_segment_seconds: usize,
+    model_path: &Path,
+) -> Result<Vec<SpeechSegment>, String> {
+    let config = SileroVadConfig {
+        model: model_path.to_string_lossy().to_string(),
+        threshold: SPEECH_THRESHOLD,
+        min_silence_duration: MIN_SILENCE_SECONDS,
+        min_speech_duration: MIN_SPEECH_SECONDS,
+        max_speech_duration: max_segment_seconds as f32,
+        sample_rate,
+        window_size: SILERO_WINDOW_SIZE as i32,
+        ..Default::default()
+    };
+    let buffer_seconds = max_segment_seconds as f32 * 2.0;
+    let mut vad = SileroVad::new(config, buffer_seconds).map_err(|e| e.to_string())?;
+
+    let mut segments = Vec::new();
+    let mut drain = |vad: &mut SileroVad| {
+        while !vad.is_empty() {
+            let segment = vad.front();
+            let start = segment.start.max(0) as usize;
+            segments.push(SpeechSegment {
+                start,
+                end: (start + segment.samples.len()).min(samples.len()),
+            });
+            vad.pop();
+        }
+    };
+
+    for window in samples.chunks(SILERO_WINDOW_SIZE) {
+        vad.accept_waveform(window.to_vec());
+        drain(&mut vad);
+    }
+    vad.flush();
+    drain(&mut vad);
+
+    Ok(segments)
+}
+
+// フレームごとの RMS を無音レベルと比べて発話区間を求める
+fn detect_with_energy(samples: &[f32], sample_rate: u32) -> Vec<SpeechSegment> {
+    let frame_len = ((sample_rate as f32 * ENERGY_FRAME_SECONDS) as usize).max(1);
+    let levels: Vec<f32> = samples.chunks(frame_len).map(rms_db).collect();
+    if levels.is_empty() {
+        return Vec::new();
+    }
+
+    let mut sorted = levels.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = sorted[sorted.len() / 10];
+    let peak = sorted[sorted.len() - 1];
+    let threshold = (noise_floor + ENERGY_MARGIN_DB)
+        .min(peak - ENERGY_MARGIN_DB)
+        .max(ENERGY_FLOOR_DB);
+
+    let min_silence_frames = (MIN_SILENCE_SECONDS / ENERGY_FRAME_SECONDS) as usize;
+    let min_speech_frames = (MIN_SPEECH_SECONDS / ENERGY_FRAME_SECONDS) as usize;
+
+    // 発話フレームの連続を区間にまとめる（短い無音はつなげる）
+    let mut frame_segments: Vec<(usize, usize)> = Vec::new();
+    for (i, level) in levels.iter().enumerate() {
+        if *level <= threshold {
+            continue;
+        }
+        match frame_segments.last_mut() {
+            Some((_, end)) if i - *end <= min_silence_frames => *end = i + 1,
+            _ => frame_segments.push((i, i + 1)),
+        }
+    }
+
+    frame_segments
+        .into_iter()
+        .filter(|(start, end)| end - start >= min_speech_frames)
+        .map(|(start, end)| SpeechSegment {
+            start: start * frame_len,
+            end: (end * frame_len).min(samples.len()),
+        })
+        .collect()
+}
+
+fn rms_db(frame: &[f32]) -> f32 {
+    let mean_square = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+    10.0 * mean_square.max(1e-12).log10()
+}
+
+fn pad_and_merge(
+    segments: &[SpeechSegment],
+    total_samples: usize,
+    sample_rate: u32,
+) -> Vec<SpeechSegment> {
+    let padding = (PADDING_SECONDS * sample_rate as f32) as usize;
+    let mut merged: Vec<SpeechSegment> = Vec::new();
+    for segment in segments {
+        let start = segment.start.saturating_sub(padding);
+        let end = (segment.end + padding).min(total_samples);
+        match merged.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => merged.push(SpeechSegment { start, end }),
+        }
+    }
+    merged
+}
+
+// 長すぎる区間は、後半のうち最も静かなフレームで分割する
+fn split_long_segments(
+    samples: &[f32],
+    segments: &[SpeechSegment],
+    sample_rate: u32,
+    max_len: usize,
+) -> Vec<SpeechSegment> {
+    let frame_len = ((sample_rate as f32 * ENERGY_FRAME_SECONDS) as usize).max(1);
+    let mut result = Vec::new();
+
+    for segment in segments {
+        let mut start = segment.start;
+        while segment.end - start > max_len {
+            let search_from = start + max_len / 2;
+            let search_to = start + max_len - frame_len;
+            let split = (search_from..search_to)
+                .step_by(frame_len)
+                .min_by(|&a, &b| {
+                    rms_db(&samples[a..a + frame_len])
+                        .total_cmp(&rms_db(&samples[b..b + frame_len]))
+                })
+                .map(|pos| pos + frame_len / 2)
+                .unwrap_or(start + max_len);
+            result.push(SpeechSegment { start, end: split });
+            start = split;
+        }
+        result.push(SpeechSegment {
+            start,
+            end: segment.end,
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn tone(seconds: f32) -> Vec<f32> {
+        let n = (seconds * SAMPLE_RATE as f32) as usize;
+        (0..n)
+            .map(|i| 0.5 * (i as f32 * 440.0 * std::f32::consts::TAU / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    fn silence(seconds: f32) -> Vec<f32> {
+        vec![0.0; (seconds * SAMPLE_RATE as f32) as usize]
+    }
+
+    fn seconds(samples: usize) -> f32 {
+        samples as f32 / SAMPLE_RATE as f32
+    }
+
+    #[test]
+    fn energy_vad_skips_long_silence_between_utterances() {
+        let samples = [
+            silence(3.0),
+            tone(2.0),
+            silence(5.0),
+            tone(1.0),
+            silence(2.0),
+        ]
+        .concat();
+        let segments = detect_speech(&samples, SAMPLE_RATE, 30, None);
+
+        assert_eq!(segments.len(), 2);
+        assert!((seconds(segments[0].start) - 2.8).abs() < 0.05);
+        assert!((seconds(segments[0].end) - 5.2).abs() < 0.05);
+        assert!((seconds(segments[1].start) - 9.8).abs() < 0.05);
+        assert!((seconds(segments[1].end) - 11.2).abs() < 0.05);
+    }
+
+    #[test]
+    fn energy_vad_splits_segments_longer_than_max() {
+        let samples = [silence(1.0), tone(25.0), silence(1.0)].concat();
+        let segments = detect_speech(&samples, SAMPLE_RATE, 10, None);
+
+        assert!(segments.len() >= 3);
+        for segment in &segments {
+            assert!(segment.end - segment.start <= 10 * SAMPLE_RATE as usize);
+        }
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Min/max peaks of a media file for drawing its waveform next to the transcript.
+use crate::audio_converter::{AudioStream, SampleSource};
+use log::{info, warn};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Peaks are computed from the same 16 kHz mono signal the recognizer sees.
+const SAMPLE_RATE: u32 = 16000;
+const CACHE_MAGIC: &[u8; 4] = b"KAPK";
+const CACHE_VERSION: u32 = 1;
+
+/// Lowest and highest sample of each `1 / peaks_per_second` seconds of audio, scaled to
+/// `i16` (full scale is ±32767) to keep the payload and the cache small.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaveformPeaks {
+    pub peaks_per_second: u32,
+    pub duration_ms: u64,
+    pub min: Vec<i16>,
+    pub max: Vec<i16>,
+}
+
+/// Computes the peaks of `file_path` at `peaks_per_second` (1 to 16000). When `transcript_path`
+/// is given, the peaks are cached beside it and reused until the media file changes.
+#[tauri::command]
+pub async fn get_waveform_peaks(
+    file_path: String,
+    peaks_per_second: u32,
+    transcript_path: Option<String>,
+) -> Result<WaveformPeaks, String> {
+    // Decoding a long file takes a while, so keep it off the async runtime's workers.
+    tauri::async_runtime::spawn_blocking(move || {
+        let cache_path = transcript_path.map(|path| cache_path(Path::new(&path), peaks_per_second));
+        load_or_compute(
+            Path::new(&file_path),
+            peaks_per_second,
+            cache_path.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| format!("waveform task failed: {}", e))?
+}
+
+/// The cache file for a transcript, e.g. `talk.json.peaks-100` for `talk.json` at 100 peaks
+/// per second.
+pub fn cache_path(transcript_path: &Path, peaks_per_second: u32) -> PathBuf {
+    let mut name = transcript_path
+        .file_name()
+        .unwrap_or_default()
+        .to_os_string();
+    name.push(format!(".peaks-{}", peaks_per_second));
+    transcript_path.with_file_name(name)
+}
+
+/// Reads the peaks from `cache_path` if it was made from the current version of the media
+/// file, and otherwise decodes the file and refreshes the cache.
+pub fn load_or_compute(
+    media_path: &Path,
+    peaks_per_second: u32,
+    cache_path: Option<&Path>,
+) -> Result<WaveformPeaks, String> {
+    validate_resolution(peaks_per_second)?;
+    let source = SourceStamp::of(media_path)?;
+    if let Some(cache_path) = cache_path {
+        if let Some(peaks) = read_cache(cache_path, &source, peaks_per_second) {
+            info!("Using cached waveform peaks: {}", cache_path.display());
+            return Ok(peaks);
+        }
+    }
+
+    let mut stream = AudioStream::open(&media_path.to_string_lossy())
+        .map_err(|e| format!("failed to open {}: {}", media_path.display(), e))?;
+    let peaks = compute_peaks(&mut stream, peaks_per_second)?;
+
+    if let Some(cache_path) = cache_path {
+        // The peaks are still usable when the cache cannot be written, e.g. on a read-only share.
+        if let Err(e) = write_cache(cache_path, &source, &peaks) {
+            warn!(
+                "Failed to write waveform cache {}: {}",
+                cache_path.display(),
+                e
+            );
+        }
+    }
+    Ok(peaks)
+}
+
+/// Reduces a 16 kHz signal to one min/max pair per `1 / peaks_per_second` seconds. The last
+/// peak may cover less time than the others.
+pub fn compute_peaks(
+    source: &mut impl SampleSource,
+    peaks_per_second: u32,
+) -> Result<WaveformPeaks, String> {
+    validate_resolution(peaks_per_second)?;
+    let mut min = Vec::new();
+    let mut max = Vec::new();
+    let mut position: u64 = 0;
+    while let Some(block) = source.next_block()? {
+        for sample in block {
+            // Integer arithmetic keeps the bins aligned even when the rate does not divide 16000.
+            let bin = (position * peaks_per_second as u64 / SAMPLE_RATE as u64) as usize;
+            let value = quantize(sample);
+            if bin == min.len() {
+                min.push(value);
+                max.push(value);
+            } else {
+                min[bin] = min[bin].min(value);
+                max[bin] = max[bin].max(value);
+            }
+            position += 1;
+        }
+    }
+    Ok(WaveformPeaks {
+        peaks_per_second,
+        duration_ms: position * 1000 / SAMPLE_RATE as u64,
+        min,
+        max,
+    })
+}
+
+fn validate_resolution(peaks_per_second: u32) -> Result<(), String> {
+    if peaks_per_second == 0 || peaks_per_second > SAMPLE_RATE {
+        return Err(format!(
+            "peaks per second must be between 1 and {}, got {}",
+            SAMPLE_RATE, peaks_per_second
+        ));
+    }
+    Ok(())
+}
+
+fn quantize(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+// Identifies the version of the media file a cache was made from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SourceStamp {
+    len: u64,
+    modified_ms: u64,
+}
+
+impl SourceStamp {
+    fn of(path: &Path) -> Result<Self, String> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_millis() as u64);
+        Ok(SourceStamp {
+            len: metadata.len(),
+            modified_ms,
+        })
+    }
+}
+
+// Cache layout (little endian): magic, version, source length, source mtime (ms),
+// peaks per second, duration (ms), peak count, then a min/max `i16` pair per peak.
+fn write_cache(path: &Path, source: &SourceStamp, peaks: &WaveformPeaks) -> std::io::Result<()> {
+    let mut bytes = Vec::with_capacity(40 + peaks.min.len() * 4);
+    bytes.extend_from_slice(CACHE_MAGIC);
+    bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&source.len.to_le_bytes());
+    bytes.extend_from_slice(&source.modified_ms.to_le_bytes());
+    bytes.extend_from_slice(&peaks.peaks_per_second.to_le_bytes());
+    bytes.extend_from_slice(&peaks.duration_ms.to_le_bytes());
+    bytes.extend_from_slice(&(peaks.min.len() as u32).to_le_bytes());
+    for (min, max) in peaks.min.iter().zip(&peaks.max) {
+        bytes.extend_from_slice(&min.to_le_bytes());
+        bytes.extend_from_slice(&max.to_le_bytes());
+    }
+
+    // Write to a temporary file first so an interrupted write never leaves a truncated cache.
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)
+}
+
+// Returns `None` when the cache is missing, unreadable or made from another file version.
+fn read_cache(path: &Path, source: &SourceStamp, peaks_per_second: u32) -> Option<WaveformPeaks> {
+    let bytes = std::fs::read(path).ok()?;
+    let mut reader = bytes.as_slice();
+    let mut take = |n: usize| -> Option<&[u8]> {
+        if reader.len() < n {
+            return None;
+        }
+        let (head, rest) = reader.split_at(n);
+        reader = rest;
+        Some(head)
+    };
+    if take(4)? != CACHE_MAGIC {
+        return None;
+    }
+    let read_u32 = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+    let read_u64 = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap());
+    if read_u32(take(4)?) != CACHE_VERSION {
+        return None;
+    }
+    let cached_source = SourceStamp {
+        len: read_u64(take(8)?),
+        modified_ms: read_u64(take(8)?),
+    };
+    if cached_source != *source || read_u32(take(4)?) != peaks_per_second {
+        return None;
+    }
+    let duration_ms = read_u64(take(8)?);
+    let count = read_u32(take(4)?) as usize;
+    let mut min = Vec::with_capacity(count);
+    let mut max = Vec::with_capacity(count);
+    for _ in 0..count {
+        let pair = take(4)?;
+        min.push(i16::from_le_bytes([pair[0], pair[1]]));
+        max.push(i16::from_le_bytes([pair[2], pair[3]]));
+    }
+    Some(WaveformPeaks {
+        peaks_per_second,
+        duration_ms,
+        min,
+        max,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peaks_hold_the_extremes_of_each_bin() {
+        // 1 second of a ramp from -1 to 1, then half a second of silence.
+        let mut samples: Vec<f32> = (0..SAMPLE_RATE)
+            .map(|i| i as f32 / (SAMPLE_RATE - 1) as f32 * 2.0 - 1.0)
+            .collect();
+        samples.extend(vec![0.0; SAMPLE_RATE as usize / 2]);
+
+        let peaks = compute_peaks(&mut samples.chunks(3000), 4).unwrap();
+        assert_eq!(peaks.duration_ms, 1500);
+        assert_eq!(peaks.min.len(), 6);
+        assert_eq!(peaks.max.len(), 6);
+        assert_eq!(peaks.min[0], -i16::MAX);
+        assert_eq!(peaks.max[3], i16::MAX);
+        assert!(peaks.max[0] < 0 && peaks.min[3] > 0);
+        assert_eq!((peaks.min[5], peaks.max[5]), (0, 0));
+
+        assert!(compute_peaks(&mut samples.chunks(3000), 0).is_err());
+        assert!(compute_peaks(&mut samples.chunks(3000), SAMPLE_RATE + 1).is_err());
+    }
+
+    #[test]
+    fn cache_is_reused_until_the_media_file_changes() {
+        let dir = std::env::temp_dir().join(format!("kotonoha-asr-peaks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let media = dir.join("talk.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&media, spec).unwrap();
+        for _ in 0..SAMPLE_RATE {
+            writer.write_sample(0.5f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let cache = cache_path(&dir.join("talk.json"), 10);
+        assert_eq!(cache, dir.join("talk.json.peaks-10"));
+        let peaks = load_or_compute(&media, 10, Some(&cache)).unwrap();
+        assert_eq!(peaks.min.len(), 10);
+        assert_eq!(peaks.max[0], quantize(0.5));
+
+        let source = SourceStamp::of(&media).unwrap();
+        assert_eq!(read_cache(&cache, &source, 10), Some(peaks));
+        assert_eq!(read_cache(&cache, &source, 20), None);
+        let changed = SourceStamp {
+            len: source.len + 1,
+            ..source
+        };
+        assert_eq!(read_cache(&cache, &changed, 10), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
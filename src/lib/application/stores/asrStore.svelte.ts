@@ -1,12 +1,20 @@
-import type { AsrProgressPayload } from '$lib/domain/entities/asr';
+import type { AsrJobId, AsrProgressPayload } from '$lib/domain/entities/asr';
+import type { MediaInfo } from '$lib/domain/entities/media';
 
 type Status = 'initial' | 'processing' | 'done' | 'error';
 
 const store = $state({
   status: 'initial' as Status,
+  jobId: null as AsrJobId | null,
+  /** ジョブに使われたモデルの ID（ジョブ開始前は `null`） */
+  modelId: null as string | null,
   progress: 0,
   fileName: '',
+  /** `probe_media` で読み取ったファイルの情報（読み取れなかった場合は `null`） */
+  media: null as MediaInfo | null,
   totalDurationMs: 0,
+  /** 範囲を指定して文字起こしした場合の範囲の先頭（文の時刻はファイル先頭からのため、進捗の計算に使う） */
+  rangeStartMs: 0,
   transcriptionSegments: [] as readonly AsrProgressPayload[],
   errorMessage: null as string | null,
   processingTimeMs: 0,
@@ -16,12 +24,21 @@ export const asrStore = {
   get status() {
     return store.status;
   },
+  get jobId() {
+    return store.jobId;
+  },
+  get modelId() {
+    return store.modelId;
+  },
   get progress() {
     return store.progress;
   },
   get fileName() {
     return store.fileName;
   },
+  get media() {
+    return store.media;
+  },
   get totalDurationMs() {
     return store.totalDurationMs;
   },
@@ -35,16 +52,36 @@ export const asrStore = {
     return store.processingTimeMs;
   },
 
-  start(fileName: string) {
+  start(fileName: string, rangeStartMs = 0) {
     store.status = 'processing';
+    store.jobId = null;
+    store.modelId = null;
     store.fileName = fileName;
+    store.media = null;
     store.progress = 0;
     store.totalDurationMs = 0;
+    store.rangeStartMs = rangeStartMs;
     store.transcriptionSegments = [];
     store.errorMessage = null;
     store.processingTimeMs = 0;
   },
 
+  setJob(jobId: AsrJobId, modelId: string) {
+    store.jobId = jobId;
+    store.modelId = modelId;
+  },
+
+  /**
+   * `asr-started` より先に分かるファイルの情報と長さを設定する。
+   * 長さは `asr-started` の値が届いていればそちらを優先する
+   */
+  setMedia(media: MediaInfo, durationMs: number | null) {
+    store.media = media;
+    if (store.totalDurationMs === 0 && durationMs !== null) {
+      store.totalDurationMs = durationMs;
+    }
+  },
+
   setStarted(totalDurationMs: number) {
     store.totalDurationMs = totalDurationMs;
   },
@@ -52,7 +89,8 @@ export const asrStore = {
   addProgress(payload: AsrProgressPayload) {
     store.transcriptionSegments = [...store.transcriptionSegments, payload];
     if (store.totalDurationMs > 0) {
-      const progress = Math.min(100, Math.round((payload.endTimeMs / store.totalDurationMs) * 100));
+      const elapsedMs = payload.endTimeMs - store.rangeStartMs;
+      const progress = Math.min(100, Math.round((elapsedMs / store.totalDurationMs) * 100));
       store.progress = progress;
     }
   },
@@ -63,6 +101,12 @@ export const asrStore = {
     store.processingTimeMs = processingTimeMs;
   },
 
+  setCancelled(sentences: readonly AsrProgressPayload[], processingTimeMs: number) {
+    store.status = 'done';
+    store.transcriptionSegments = sentences;
+    store.processingTimeMs = processingTimeMs;
+  },
+
   setError(message: string) {
     store.status = 'error';
     store.errorMessage = message;
@@ -70,9 +114,13 @@ export const asrStore = {
 
   reset() {
     store.status = 'initial';
+    store.jobId = null;
+    store.modelId = null;
     store.fileName = '';
+    store.media = null;
     store.progress = 0;
     store.totalDurationMs = 0;
+    store.rangeStartMs = 0;
     store.transcriptionSegments = [];
     store.errorMessage = null;
     store.processingTimeMs = 0;
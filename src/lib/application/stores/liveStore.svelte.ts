@@ -0,0 +1,75 @@
+import type { LiveSentencePayload, LiveSessionId } from '$lib/domain/entities/live';
+
+type Status = 'idle' | 'starting' | 'listening' | 'stopping' | 'done' | 'error';
+
+const store = $state({
+  status: 'idle' as Status,
+  sessionId: null as LiveSessionId | null,
+  partialText: '',
+  sentences: [] as readonly LiveSentencePayload[],
+  recordingPath: null as string | null,
+  errorMessage: null as string | null,
+});
+
+export const liveStore = {
+  get status() {
+    return store.status;
+  },
+  get sessionId() {
+    return store.sessionId;
+  },
+  get partialText() {
+    return store.partialText;
+  },
+  get sentences() {
+    return store.sentences;
+  },
+  get recordingPath() {
+    return store.recordingPath;
+  },
+  get errorMessage() {
+    return store.errorMessage;
+  },
+
+  start() {
+    store.status = 'starting';
+    store.sessionId = null;
+    store.partialText = '';
+    store.sentences = [];
+    store.recordingPath = null;
+    store.errorMessage = null;
+  },
+
+  setSessionId(sessionId: LiveSessionId) {
+    store.sessionId = sessionId;
+  },
+
+  setListening() {
+    store.status = 'listening';
+  },
+
+  setPartial(text: string) {
+    store.partialText = text;
+  },
+
+  addSentence(payload: LiveSentencePayload) {
+    store.sentences = [...store.sentences, payload];
+    store.partialText = '';
+  },
+
+  setStopping() {
+    store.status = 'stopping';
+  },
+
+  setStopped(sentences: readonly LiveSentencePayload[], recordingPath: string | null) {
+    store.status = 'done';
+    store.sentences = sentences;
+    store.partialText = '';
+    store.recordingPath = recordingPath;
+  },
+
+  setError(message: string) {
+    store.status = 'error';
+    store.errorMessage = message;
+  },
+};
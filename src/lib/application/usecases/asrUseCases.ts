@@ -1,11 +1,72 @@
 import { asrStore } from '$lib/application/stores/asrStore.svelte';
+import {
+  ensureDiarizationModelsAreReady,
+  ensureModelIsReady,
+  ensureVadModelIsReady,
+} from '$lib/application/usecases/modelSetup';
+import type {
+  AsrCancelledPayload,
+  AsrErrorPayload,
+  AsrFinishedPayload,
+  AsrJobId,
+  AsrProgressPayload,
+  AsrStartedPayload,
+  AsrTranscriptionOptions,
+} from '$lib/domain/entities/asr';
 import { asrRepository } from '$lib/infrastructure/repositories/asrRepository';
+import { mediaRepository } from '$lib/infrastructure/repositories/mediaRepository';
 import type { UnlistenFn } from '@tauri-apps/api/event';
 
-async function startProcessing(filePath: string): Promise<void> {
+async function startProcessing(
+  filePath: string,
+  modelId?: string,
+  options?: AsrTranscriptionOptions
+): Promise<void> {
   // NOTE: `asrStore.start` でファイル名だけを渡したいが、
   //       Tauri の FileDropEvent からフルパスしか取得できないため、一旦フルパスを渡している
-  asrStore.start(filePath);
+  asrStore.start(filePath, options?.range?.startMs);
+
+  // デコードには時間がかかるため、ヘッダーから分かる長さやタグを先に表示する
+  mediaRepository
+    .probeMedia(filePath)
+    .then((media) => {
+      const startMs = options?.range?.startMs ?? 0;
+      const endMs = Math.min(options?.range?.endMs ?? Infinity, media.durationMs ?? Infinity);
+      asrStore.setMedia(media, Number.isFinite(endMs) ? Math.max(0, endMs - startMs) : null);
+    })
+    .catch((error) => {
+      // 読み取れなくても文字起こしはできるので、`asr-started` で長さが分かるのを待つ
+      console.error('Failed to probe media:', error);
+    });
+
+  if (modelId) {
+    try {
+      await ensureModelIsReady(() => {}, modelId);
+    } catch (error) {
+      console.error('Failed to download model:', error);
+      asrStore.setError('モデルのダウンロードに失敗しました。');
+      return;
+    }
+  }
+
+  if (options?.diarization) {
+    try {
+      await ensureDiarizationModelsAreReady(() => {});
+    } catch (error) {
+      console.error('Failed to download diarization models:', error);
+      asrStore.setError('話者分離モデルのダウンロードに失敗しました。');
+      return;
+    }
+  }
+
+  if (options?.segmentation === 'vad') {
+    try {
+      await ensureVadModelIsReady(() => {});
+    } catch (error) {
+      // エネルギーベースの検出で続けられるので中断はしない（Rust 側がどちらを使ったかログに残す）
+      console.error('Failed to download Silero VAD model:', error);
+    }
+  }
 
   let unlistenFns: UnlistenFn[] = [];
 
@@ -14,28 +75,71 @@ async function startProcessing(filePath: string): Promise<void> {
     unlistenFns = [];
   };
 
-  const unlistenStarted = await asrRepository.onAsrStarted((payload) => {
-    asrStore.setStarted(payload.totalDurationMs);
-  });
+  // イベントはすべてのジョブのものが届くので、このジョブのものだけを反映する。
+  // ジョブは開始直後からイベントを送るため、ID が分かるまでに届いたものは溜めておく
+  let jobId: AsrJobId | null = null;
+  let pending: { jobId: AsrJobId; apply: () => void }[] = [];
+  const forThisJob =
+    <P extends { readonly jobId: AsrJobId }>(apply: (payload: P) => void) =>
+    (payload: P) => {
+      if (jobId === null) {
+        pending.push({ jobId: payload.jobId, apply: () => apply(payload) });
+      } else if (payload.jobId === jobId) {
+        apply(payload);
+      }
+    };
 
-  const unlistenProgress = await asrRepository.onAsrProgress((payload) => {
-    asrStore.addProgress(payload);
-  });
+  const unlistenStarted = await asrRepository.onAsrStarted(
+    forThisJob((payload: AsrStartedPayload) => {
+      asrStore.setStarted(payload.totalDurationMs);
+    })
+  );
 
-  const unlistenFinished = await asrRepository.onAsrFinished((payload) => {
-    asrStore.setFinished(payload.processingTimeMs);
-    cleanup();
-  });
+  const unlistenProgress = await asrRepository.onAsrProgress(
+    forThisJob((payload: AsrProgressPayload) => {
+      asrStore.addProgress(payload);
+    })
+  );
 
-  const unlistenError = await asrRepository.onAsrError((errorMessage) => {
-    asrStore.setError(errorMessage);
-    cleanup();
-  });
+  const unlistenFinished = await asrRepository.onAsrFinished(
+    forThisJob((payload: AsrFinishedPayload) => {
+      asrStore.setFinished(payload.processingTimeMs);
+      cleanup();
+    })
+  );
 
-  unlistenFns = [unlistenStarted, unlistenProgress, unlistenFinished, unlistenError];
+  const unlistenCancelled = await asrRepository.onAsrCancelled(
+    forThisJob((payload: AsrCancelledPayload) => {
+      asrStore.setCancelled(payload.sentences, payload.processingTimeMs);
+      cleanup();
+    })
+  );
+
+  const unlistenError = await asrRepository.onAsrError(
+    forThisJob((payload: AsrErrorPayload) => {
+      asrStore.setError(payload.message);
+      cleanup();
+    })
+  );
+
+  unlistenFns = [
+    unlistenStarted,
+    unlistenProgress,
+    unlistenFinished,
+    unlistenCancelled,
+    unlistenError,
+  ];
 
   try {
-    await asrRepository.startAsrProcess(filePath);
+    const startedJobId = await asrRepository.startAsrProcess(filePath, modelId, options);
+    // モデル ID を省略した場合に使われた既定のモデルを、保存時のメタデータのために記録する
+    const job = (await asrRepository.listAsrJobs()).find((j) => j.id === startedJobId);
+    asrStore.setJob(startedJobId, job?.modelId ?? modelId ?? '');
+
+    jobId = startedJobId;
+    const received = pending;
+    pending = [];
+    received.filter((event) => event.jobId === jobId).forEach((event) => event.apply());
   } catch (error) {
     console.error('Failed to start ASR process:', error);
     asrStore.setError('ASRプロセスの開始に失敗しました。');
@@ -43,6 +147,18 @@ async function startProcessing(filePath: string): Promise<void> {
   }
 }
 
+async function cancelProcessing(): Promise<void> {
+  const jobId = asrStore.jobId;
+  if (jobId === null) return;
+  try {
+    await asrRepository.cancelAsrProcess(jobId);
+  } catch (error) {
+    console.error('Failed to cancel ASR process:', error);
+    asrStore.setError('ASRプロセスのキャンセルに失敗しました。');
+  }
+}
+
 export const asrUseCases = {
   startProcessing,
+  cancelProcessing,
 };
@@ -0,0 +1,12 @@
+import type { ChunkOptions } from '$lib/domain/entities/asr';
+import { settingsRepository } from '$lib/infrastructure/repositories/settingsRepository';
+
+/** モデルに保存済みのチャンク設定を読み込むユースケース（未保存なら既定値） */
+export async function loadChunkOptions(modelId?: string): Promise<ChunkOptions> {
+  return await settingsRepository.getChunkOptions(modelId);
+}
+
+/** チャンク設定をモデルの既定値として保存するユースケース（不正な値はバックエンドがエラーにする） */
+export async function saveChunkOptions(options: ChunkOptions, modelId?: string): Promise<void> {
+  await settingsRepository.setChunkOptions(options, modelId);
+}
@@ -0,0 +1,12 @@
+import type { DecodingOptions } from '$lib/domain/entities/asr';
+import { settingsRepository } from '$lib/infrastructure/repositories/settingsRepository';
+
+/** 保存済みのデコード設定を読み込むユースケース（未保存なら既定値） */
+export async function loadDecodingOptions(): Promise<DecodingOptions> {
+  return await settingsRepository.getDecodingOptions();
+}
+
+/** デコード設定を保存するユースケース（不正な値はバックエンドがエラーにする） */
+export async function saveDecodingOptions(options: DecodingOptions): Promise<void> {
+  await settingsRepository.setDecodingOptions(options);
+}
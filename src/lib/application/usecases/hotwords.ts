@@ -0,0 +1,28 @@
+import type { Hotword } from '$lib/domain/entities/asr';
+import { settingsRepository } from '$lib/infrastructure/repositories/settingsRepository';
+
+/**
+ * 1行に1語句、加点を付ける場合は `語句 :2.0` の形式で書かれたテキストを読む
+ *
+ * 空行と `#` で始まる行は無視する。加点が数値でない行があればエラーにする。
+ */
+export async function parseHotwords(text: string): Promise<Hotword[]> {
+  return await settingsRepository.parseHotwords(text);
+}
+
+/** `parseHotwords` で読める形式のテキストにする */
+export function formatHotwords(hotwords: readonly Hotword[]): string {
+  return hotwords
+    .map(({ phrase, boost }) => (boost === undefined ? phrase : `${phrase} :${boost}`))
+    .join('\n');
+}
+
+/** 保存済みのホットワードを読み込むユースケース */
+export async function loadHotwords(): Promise<Hotword[]> {
+  return await settingsRepository.getHotwords();
+}
+
+/** ホットワードを保存するユースケース */
+export async function saveHotwords(hotwords: readonly Hotword[]): Promise<void> {
+  await settingsRepository.setHotwords(hotwords);
+}
@@ -0,0 +1,88 @@
+import { liveStore } from '$lib/application/stores/liveStore.svelte';
+import { ensureModelIsReady } from '$lib/application/usecases/modelSetup';
+import type { LiveInput } from '$lib/domain/entities/live';
+import { asrRepository } from '$lib/infrastructure/repositories/asrRepository';
+import { liveRepository } from '$lib/infrastructure/repositories/liveRepository';
+import type { UnlistenFn } from '@tauri-apps/api/event';
+
+/**
+ * ライブ文字起こしを開始する。
+ * @param input 入力（マイク、PCM、実時間再生するファイル）
+ * @param modelId ストリーミングモデルの ID（省略時は最初のストリーミングモデル）
+ * @param recordingPath 指定すると入力を WAV で録音し、同じ名前の `.sswt` に結果を保存する
+ */
+async function startLive(input: LiveInput, modelId?: string, recordingPath?: string): Promise<void> {
+  liveStore.start();
+
+  let streamingModelId: string;
+  try {
+    const models = await asrRepository.listAsrModels();
+    const model = models.find((m) => (modelId ? m.id === modelId : m.streaming));
+    if (!model) {
+      throw new Error(`Unknown streaming model: ${modelId}`);
+    }
+    streamingModelId = model.id;
+    await ensureModelIsReady(() => {}, streamingModelId);
+  } catch (error) {
+    console.error('Failed to download streaming model:', error);
+    liveStore.setError('モデルのダウンロードに失敗しました。');
+    return;
+  }
+
+  let unlistenFns: UnlistenFn[] = [];
+
+  const cleanup = () => {
+    unlistenFns.forEach((unlisten) => unlisten());
+    unlistenFns = [];
+  };
+
+  unlistenFns = [
+    await liveRepository.onLiveStarted(() => {
+      liveStore.setListening();
+    }),
+    await liveRepository.onLivePartial((payload) => {
+      liveStore.setPartial(payload.text);
+    }),
+    await liveRepository.onLiveSentence((payload) => {
+      liveStore.addSentence(payload);
+    }),
+    await liveRepository.onLiveStopped((payload) => {
+      liveStore.setStopped(payload.sentences, payload.recordingPath);
+      cleanup();
+    }),
+    await liveRepository.onLiveError((payload) => {
+      liveStore.setError(payload.message);
+      cleanup();
+    }),
+  ];
+
+  try {
+    const sessionId = await liveRepository.startLiveTranscription(
+      input,
+      streamingModelId,
+      recordingPath
+    );
+    liveStore.setSessionId(sessionId);
+  } catch (error) {
+    console.error('Failed to start live transcription:', error);
+    liveStore.setError('ライブ文字起こしの開始に失敗しました。');
+    cleanup();
+  }
+}
+
+async function stopLive(): Promise<void> {
+  const sessionId = liveStore.sessionId;
+  if (sessionId === null) return;
+  liveStore.setStopping();
+  try {
+    await liveRepository.stopLiveTranscription(sessionId);
+  } catch (error) {
+    console.error('Failed to stop live transcription:', error);
+    liveStore.setError('ライブ文字起こしの停止に失敗しました。');
+  }
+}
+
+export const liveUseCases = {
+  startLive,
+  stopLive,
+};
@@ -1,50 +1,65 @@
+import { asrRepository } from '$lib/infrastructure/repositories/asrRepository';
 import { fileRepository } from '$lib/infrastructure/repositories/fileRepository';
 import { modelDownloadRepository } from '$lib/infrastructure/repositories/modelDownloadRepository';
-import { error, trace } from '@tauri-apps/plugin-log';
-
-const FILES_TO_DOWNLOAD = [
-  { name: 'decoder.int8.onnx', isBinary: true },
-  { name: 'encoder.int8.onnx', isBinary: true },
-  { name: 'joiner.int8.onnx', isBinary: true },
-  { name: 'tokens.txt', isBinary: false },
-];
+import { error, trace, warn } from '@tauri-apps/plugin-log';
 
 /**
- * アプリケーションが必要とするモデルファイルがローカルに存在するかを確認し、
+ * 指定したモデル（省略時は既定のモデル）のファイルがローカルに存在するかを確認し、
  * 存在しない場合はHugging Faceからダウンロードする。
  * @param onProgress 進捗を報告するコールバック (0-100)
+ * @param modelId モデルレジストリのモデル ID
  */
-export async function ensureModelIsReady(onProgress: (progress: number) => void): Promise<void> {
+export async function ensureModelIsReady(
+  onProgress: (progress: number) => void,
+  modelId?: string
+): Promise<void> {
   try {
-    const checkResults = await Promise.all(
-      FILES_TO_DOWNLOAD.map((file) => fileRepository.modelFileExists(file.name))
-    );
-    const allFilesExist = checkResults.every((r) => r);
+    const models = await asrRepository.listAsrModels();
+    const model = models.find((m) => (modelId ? m.id === modelId : m.isDefault));
+    if (!model) {
+      throw new Error(`Unknown model: ${modelId}`);
+    }
+    const modelDir = `models/${model.directory}`;
 
-    if (allFilesExist) {
-      trace('All model files already exist.');
+    // 存在確認だけでなくハッシュも照合し、壊れていたファイル（削除済み）はダウンロードし直す
+    const reports = await modelDownloadRepository.verifyModelFiles(model.id);
+    for (const report of reports) {
+      if (report.status === 'corrupted') {
+        warn(
+          `Corrupted model file ${report.filePath}: expected ${report.expected}, got ${report.actual}`
+        );
+      } else if (report.status === 'unverified') {
+        warnUnverified(report.filePath);
+      }
+    }
+    const needsDownload = (file: string) =>
+      reports.some(
+        (r) =>
+          r.filePath === `${modelDir}/${file}` && (r.status === 'missing' || r.status === 'corrupted')
+      );
+    if (!model.files.some(needsDownload)) {
+      trace(`All files of ${model.id} already exist.`);
       onProgress(100);
       return;
     }
-    trace('Some model files are missing. Starting download...');
+    trace(`Some files of ${model.id} are missing or corrupted. Starting download...`);
 
     let completedFiles = 0;
-    const totalFiles = FILES_TO_DOWNLOAD.length;
-
-    for (const file of FILES_TO_DOWNLOAD) {
-      if (file.isBinary) {
-        // 大きなバイナリファイルはストリーミングダウンロード
-        await modelDownloadRepository.downloadFileStream(file.name, (fileProgress) => {
-          const totalProgress = Math.round(
-            ((completedFiles + fileProgress / 100) / totalFiles) * 100
-          );
-          onProgress(totalProgress);
-        });
-      } else {
-        // 小さなテキストファイルは従来通り
-        const content = await modelDownloadRepository.downloadFile(file.name);
-        const textContent = new TextDecoder().decode(content);
-        await fileRepository.saveModelTextFile(file.name, textContent);
+    const totalFiles = model.files.length;
+
+    for (const file of model.files) {
+      if (needsDownload(file)) {
+        const status = await modelDownloadRepository.downloadUrlStream(
+          `${model.baseUrl}/${file}`,
+          `${modelDir}/${file}`,
+          (fileProgress) => {
+            const totalProgress = Math.round(
+              ((completedFiles + fileProgress / 100) / totalFiles) * 100
+            );
+            onProgress(totalProgress);
+          }
+        );
+        if (status === 'unverified') warnUnverified(`${modelDir}/${file}`);
       }
 
       completedFiles++;
@@ -57,3 +72,89 @@ export async function ensureModelIsReady(onProgress: (progress: number) => void)
     throw e; // エラーを呼び出し元に伝える
   }
 }
+
+const DIARIZATION_MODEL_DIR = 'models/diarization';
+
+// Rust 側の `diarization::SEGMENTATION_MODEL_FILE` / `EMBEDDING_MODEL_FILE` と同じ名前で保存する
+const DIARIZATION_FILES_TO_DOWNLOAD = [
+  {
+    name: 'pyannote-segmentation-3-0.onnx',
+    url: 'https://huggingface.co/csukuangfj/sherpa-onnx-pyannote-segmentation-3-0/resolve/main/model.onnx',
+  },
+  {
+    name: 'nemo_en_titanet_small.onnx',
+    url: 'https://huggingface.co/csukuangfj/speaker-embedding-models/resolve/main/nemo_en_titanet_small.onnx',
+  },
+];
+
+/**
+ * 話者分離に必要なモデルファイルがローカルに存在するかを確認し、
+ * 存在しない場合はHugging Faceからダウンロードする。
+ * @param onProgress 進捗を報告するコールバック (0-100)
+ */
+export async function ensureDiarizationModelsAreReady(
+  onProgress: (progress: number) => void
+): Promise<void> {
+  try {
+    await downloadMissingFiles(DIARIZATION_MODEL_DIR, DIARIZATION_FILES_TO_DOWNLOAD, onProgress);
+    trace('Diarization models are ready.');
+  } catch (e) {
+    error(`Failed to setup diarization models: ${e}`);
+    throw e;
+  }
+}
+
+// Rust 側の `vad::SILERO_VAD_MODEL_FILE` と同じ名前で、モデルのディレクトリと並べて保存する
+const VAD_FILES_TO_DOWNLOAD = [
+  {
+    name: 'silero_vad.onnx',
+    url: 'https://github.com/k2-fsa/sherpa-onnx/releases/download/asr-models/silero_vad.onnx',
+  },
+];
+
+/**
+ * VAD 分割に使う Silero VAD のモデルがローカルに存在するかを確認し、
+ * 存在しない場合はダウンロードする。
+ * @param onProgress 進捗を報告するコールバック (0-100)
+ */
+export async function ensureVadModelIsReady(
+  onProgress: (progress: number) => void
+): Promise<void> {
+  try {
+    await downloadMissingFiles('models', VAD_FILES_TO_DOWNLOAD, onProgress);
+    trace('Silero VAD model is ready.');
+  } catch (e) {
+    error(`Failed to setup Silero VAD model: ${e}`);
+    throw e;
+  }
+}
+
+async function downloadMissingFiles(
+  dir: string,
+  files: { name: string; url: string }[],
+  onProgress: (progress: number) => void
+): Promise<void> {
+  const totalFiles = files.length;
+  let completedFiles = 0;
+
+  for (const file of files) {
+    const filePath = `${dir}/${file.name}`;
+    if (!(await fileRepository.appDataFileExists(filePath))) {
+      const status = await modelDownloadRepository.downloadUrlStream(
+        file.url,
+        filePath,
+        (fileProgress) => {
+          onProgress(Math.round(((completedFiles + fileProgress / 100) / totalFiles) * 100));
+        }
+      );
+      if (status === 'unverified') warnUnverified(filePath);
+    }
+    completedFiles++;
+    onProgress(Math.round((completedFiles / totalFiles) * 100));
+  }
+}
+
+// 配布元の SHA-256 が分からないファイルは、正しくダウンロードできたか確認できない
+function warnUnverified(filePath: string): void {
+  warn(`No expected SHA-256 for ${filePath}; the file could not be verified`);
+}
@@ -1,3 +1,9 @@
+import type {
+  AsrProgressPayload,
+  SubtitleOptions,
+  TranscriptExportFormat,
+  TranscriptMetadata,
+} from '$lib/domain/entities/asr';
 import { fileRepository } from '$lib/infrastructure/repositories/fileRepository';
 
 /**
@@ -6,3 +12,37 @@ import { fileRepository } from '$lib/infrastructure/repositories/fileRepository'
 export async function saveTranscriptionFile(filePath: string, content: string): Promise<void> {
   await fileRepository.saveTranscriptionFile(filePath, content);
 }
+
+const EXPORT_FORMAT_BY_EXTENSION: Record<string, TranscriptExportFormat> = {
+  sswt: 'sswt',
+  txt: 'text',
+  srt: 'srt',
+  vtt: 'webVtt',
+  json: 'json',
+};
+
+/** 保存先の拡張子から保存形式を決める（不明な拡張子は SSWT） */
+export function exportFormatForPath(filePath: string): TranscriptExportFormat {
+  const extension = filePath.split('.').pop()?.toLowerCase() ?? '';
+  return EXPORT_FORMAT_BY_EXTENSION[extension] ?? 'sswt';
+}
+
+/**
+ * 文字起こし結果を保存先の拡張子に応じた形式（SSWT / テキスト / SRT / WebVTT / JSON）で保存するユースケース
+ *
+ * `metadata` は JSON 形式でのみ使われる。
+ */
+export async function exportTranscriptionFile(
+  filePath: string,
+  sentences: readonly AsrProgressPayload[],
+  metadata?: TranscriptMetadata,
+  options?: SubtitleOptions
+): Promise<void> {
+  await fileRepository.exportTranscriptionFile(
+    filePath,
+    exportFormatForPath(filePath),
+    sentences,
+    metadata,
+    options
+  );
+}
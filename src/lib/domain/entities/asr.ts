@@ -1,13 +1,195 @@
+import type { AudioSelection } from '$lib/domain/entities/media';
+
+export type AsrJobId = number;
+
+export type AsrJobStatus = 'queued' | 'running' | 'finished' | 'failed' | 'cancelled';
+
+/** Rust 側のモデルレジストリに登録されたモデル */
+export type AsrModel = {
+  readonly id: string;
+  readonly name: string;
+  /** `models` ディレクトリ直下のディレクトリ名 */
+  readonly directory: string;
+  readonly baseUrl: string;
+  readonly files: readonly string[];
+  /** 対応言語（ISO 639-1） */
+  readonly languages: readonly string[];
+  readonly isDefault: boolean;
+  /** ライブ文字起こし専用のストリーミングモデルかどうか */
+  readonly streaming: boolean;
+  /** ホットワードによる認識の誘導に対応しているかどうか */
+  readonly supportsHotwords: boolean;
+  /** デコード方法に modified beam search を選べるかどうか */
+  readonly supportsBeamSearch: boolean;
+  /** トークンの確率を返し、信頼度（要確認の判定）を求められるかどうか */
+  readonly reportsConfidence: boolean;
+  /** 一度に認識できる音声の最大長（秒）。上限がなければ `null` */
+  readonly maxChunkSeconds: number | null;
+};
+
+/** トランスデューサーモデルのデコード方法 */
+export type DecodingMethod = 'greedySearch' | 'modifiedBeamSearch';
+
+/** トランスデューサーモデルのデコード設定（`get_decoding_options` / `set_decoding_options`） */
+export type DecodingOptions = {
+  readonly method: DecodingMethod;
+  /** modified beam search で保持する仮説の数（1以上） */
+  readonly maxActivePaths: number;
+  /** 空白トークンのスコアから差し引く値（0以上）。大きくすると単語の脱落が減る */
+  readonly blankPenalty: number;
+};
+
+/** 固定長チャンクの長さと、隣のチャンクと重ねる長さ（秒） */
+export type ChunkOptions = {
+  /** 5秒以上 */
+  readonly chunkSeconds: number;
+  /** `chunkSeconds` より短くする */
+  readonly overlapSeconds: number;
+};
+
+/** ファイルのうち文字起こしする範囲（ミリ秒）。`endMs` を省略するとファイルの終わりまで */
+export type TimeRange = {
+  readonly startMs?: number;
+  readonly endMs?: number;
+};
+
+/** 音量の正規化方法 */
+export type Normalization =
+  /** 最大ピークを `targetDbfs`（0 以下）に合わせる */
+  | { readonly mode: 'peak'; readonly targetDbfs: number }
+  /** 統合ラウドネス（EBU R128）を `targetLufs` に合わせる。クリップする場合は音量を抑える */
+  | { readonly mode: 'loudness'; readonly targetLufs: number };
+
+/** リサンプリング後、認識の前に行う前処理（すべて省略時は無効） */
+export type PreprocessOptions = {
+  /** 直流成分（DC オフセット）を取り除く */
+  readonly removeDc?: boolean;
+  /** ハイパスフィルターのカットオフ周波数（Hz、8000 未満）。低い雑音やハンドリングノイズを抑える */
+  readonly highPassHz?: number;
+  readonly normalization?: Normalization;
+};
+
+/** 認識で優先する語句と、その加点（省略時は既定値） */
+export type Hotword = {
+  readonly phrase: string;
+  readonly boost?: number;
+};
+
+/** `verify_models` による1ファイルの検証結果（`corrupted` のファイルは削除済み） */
+export type ModelFileReport = {
+  /** アプリのローカルデータディレクトリからの相対パス */
+  readonly filePath: string;
+} & (
+  | { readonly status: 'ok' | 'missing' | 'unverified' }
+  | { readonly status: 'corrupted'; readonly expected: string; readonly actual: string }
+);
+
+export type AsrSegmentation = 'fixedWindow' | 'vad';
+
+export type AsrDiarizationOptions = {
+  /** 想定される話者数（省略時は自動推定） */
+  readonly numSpeakers?: number;
+};
+
+export type AsrTranscriptionOptions = {
+  readonly segmentation?: AsrSegmentation;
+  /** 指定すると各文に話者 ID を付ける */
+  readonly diarization?: AsrDiarizationOptions;
+  /** 有効にすると `asr-progress` に単語ごとの時刻（`words`）を含める */
+  readonly wordTimestamps?: boolean;
+  /** 信頼度がこの値（0〜1）を下回る文を要確認（`needsReview`）とする */
+  readonly reviewThreshold?: number;
+  /** 認識で優先する語句（対応していないモデルでは無視される） */
+  readonly hotwords?: readonly Hotword[];
+  /** 省略すると保存されたデコード設定を使う */
+  readonly decoding?: DecodingOptions;
+  /** 省略するとモデルごとに保存されたチャンク設定を使う */
+  readonly chunking?: ChunkOptions;
+  /** 指定するとその範囲だけを文字起こしする。文の時刻はファイル先頭からのまま */
+  readonly range?: TimeRange;
+  /** 文字起こしするトラックとチャンネル（`list_audio_tracks` で一覧を取得できる） */
+  readonly audio?: AudioSelection;
+  /**
+   * 有効にすると各チャンネルを別々に文字起こしし、文にチャンネルを付けて時刻順にまとめる。
+   * 左右に話者が分かれた通話録音向け。`diarization` や `audio.channelMix` とは同時に使えない
+   */
+  readonly splitChannels?: boolean;
+  /** 小さな音量の録音やクリップした電話音声などで認識精度を上げるための前処理 */
+  readonly preprocessing?: PreprocessOptions;
+};
+
+/** 文字起こし結果の保存形式（`webVtt` の拡張子は `.vtt`） */
+export type TranscriptExportFormat = 'sswt' | 'text' | 'srt' | 'webVtt' | 'json';
+
+/** JSON 形式で保存するときに書き込む文字起こしの実行情報（`doc/transcript_json_schema.md`） */
+export type TranscriptMetadata = {
+  readonly sourceFile?: string;
+  readonly durationMs?: number;
+  readonly modelId?: string;
+  readonly processingTimeMs?: number;
+  /** モデルがトークンの確率を返すかどうか（省略すると `modelId` から補う） */
+  readonly confidenceAvailable?: boolean;
+};
+
+/** 字幕（SRT / WebVTT）のレイアウト */
+export type SubtitleOptions = {
+  /** 1行の最大文字数 */
+  readonly maxLineLength?: number;
+  /** 1キューの最大行数（超える文は複数のキューに分ける） */
+  readonly maxLines?: number;
+};
+
+export type AsrJobInfo = {
+  readonly id: AsrJobId;
+  readonly filePath: string;
+  readonly modelId: string;
+  readonly status: AsrJobStatus;
+};
+
 export type AsrStartedPayload = {
+  readonly jobId: AsrJobId;
+  /** 文字起こしする音声の長さ（範囲を指定した場合は範囲の長さ） */
   readonly totalDurationMs: number;
 };
 
+/** 単語とその時刻 */
+export type AsrWord = {
+  readonly text: string;
+  readonly startTimeMs: number;
+  readonly endTimeMs: number;
+  /** 認識の信頼度（0〜1）。モデルが確率を返さない場合は `null` */
+  readonly confidence: number | null;
+};
+
 export type AsrProgressPayload = {
+  readonly jobId: AsrJobId;
   readonly text: string;
   readonly startTimeMs: number;
   readonly endTimeMs: number;
+  /** 話者分離を行った場合の話者 ID（0 始まり）。`splitChannels` ではチャンネルと同じ */
+  readonly speaker: number | null;
+  /** `splitChannels` を有効にしたジョブでのみ含まれる、元のチャンネル（0 始まり） */
+  readonly channel?: number;
+  /** 文の信頼度（0〜1、単語の信頼度の平均）。モデルが確率を返さない場合は `null` */
+  readonly confidence: number | null;
+  /** 信頼度が `reviewThreshold` を下回り、確認が必要な文かどうか */
+  readonly needsReview: boolean;
+  /** `wordTimestamps` を有効にしたジョブでのみ含まれる */
+  readonly words?: readonly AsrWord[];
 };
 
 export type AsrFinishedPayload = {
+  readonly jobId: AsrJobId;
   readonly processingTimeMs: number;
 };
+
+export type AsrCancelledPayload = {
+  readonly jobId: AsrJobId;
+  readonly sentences: readonly AsrProgressPayload[];
+  readonly processingTimeMs: number;
+};
+
+export type AsrErrorPayload = {
+  readonly jobId: AsrJobId;
+  readonly message: string;
+};
@@ -0,0 +1,41 @@
+export type LiveSessionId = number;
+
+/** ライブ文字起こしの入力 */
+export type LiveInput =
+  /** 入力デバイス（`deviceName` を省略すると既定のデバイス） */
+  | { readonly type: 'microphone'; readonly deviceName?: string }
+  /** `pushLiveAudio` で渡すモノラルの PCM */
+  | { readonly type: 'pcm'; readonly sampleRate: number }
+  /** 音声ファイルを実時間で再生して入力の代わりにする（実機なしでの動作確認用） */
+  | { readonly type: 'file'; readonly path: string };
+
+export type LiveStartedPayload = {
+  readonly sessionId: LiveSessionId;
+  readonly sampleRate: number;
+};
+
+/** 認識中の発話の仮説（確定するまで何度も更新される） */
+export type LivePartialPayload = {
+  readonly sessionId: LiveSessionId;
+  readonly text: string;
+};
+
+export type LiveSentencePayload = {
+  readonly sessionId: LiveSessionId;
+  readonly text: string;
+  /** セッション開始からの時刻 */
+  readonly startTimeMs: number;
+  readonly endTimeMs: number;
+};
+
+export type LiveStoppedPayload = {
+  readonly sessionId: LiveSessionId;
+  readonly sentences: readonly LiveSentencePayload[];
+  /** 録音した場合の WAV のパス（同じ名前の `.sswt` に文字起こし結果がある） */
+  readonly recordingPath: string | null;
+};
+
+export type LiveErrorPayload = {
+  readonly sessionId: LiveSessionId;
+  readonly message: string;
+};
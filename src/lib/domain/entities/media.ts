@@ -0,0 +1,63 @@
+/** `list_audio_tracks` が返す、デコードできるトラック */
+export type AudioTrackInfo = {
+  readonly id: number;
+  /** コーデックの短い名前（例: `aac`, `pcm_s16le`） */
+  readonly codec: string;
+  readonly language: string | null;
+  readonly sampleRate: number | null;
+  readonly channels: number | null;
+  /** 各チャンネルのスピーカー位置（例: `FRONT_LEFT`）。コンテナが持たない場合は空 */
+  readonly channelLabels: readonly string[];
+  readonly durationMs: number | null;
+  /** トラックを指定しなかった場合に使われるトラックかどうか */
+  readonly isDefault: boolean;
+};
+
+/** チャンネルをモノラルにまとめる方法 */
+export type ChannelMix =
+  | { readonly mode: 'average' }
+  /** 1つのチャンネル（0 始まり）だけを使う */
+  | { readonly mode: 'single'; readonly channel: number }
+  /** チャンネルごとの係数で重み付けして足し合わせる（正規化はしない） */
+  | { readonly mode: 'weights'; readonly weights: readonly number[] };
+
+/** 文字起こしするトラックとチャンネルの選び方 */
+export type AudioSelection = {
+  /** 省略すると最初の音声トラック */
+  readonly trackId?: number;
+  /** 省略するとすべてのチャンネルの平均 */
+  readonly channelMix?: ChannelMix;
+};
+
+/** コンテナに埋め込まれたチャプター */
+export type MediaChapter = {
+  readonly startMs: number;
+  /** 次のチャプターの先頭（最後のチャプターはファイルの終わり） */
+  readonly endMs: number | null;
+  readonly title: string | null;
+};
+
+/** `probe_media` がデコードせずに読み取ったファイルの情報。ストリームの情報は既定のトラックのもの */
+export type MediaInfo = {
+  /** コンテナ形式（例: `wav`, `mp4`）。判別できない場合は `null` */
+  readonly container: string | null;
+  readonly codec: string | null;
+  readonly sampleRate: number | null;
+  readonly channels: number | null;
+  readonly durationMs: number | null;
+  readonly title: string | null;
+  readonly artist: string | null;
+  readonly recordingDate: string | null;
+  readonly chapters: readonly MediaChapter[];
+};
+
+/**
+ * `get_waveform_peaks` が返す波形の最小値・最大値。
+ * `1 / peaksPerSecond` 秒ごとに1組で、値は ±32767 をフルスケールとする整数
+ */
+export type WaveformPeaks = {
+  readonly peaksPerSecond: number;
+  readonly durationMs: number;
+  readonly min: readonly number[];
+  readonly max: readonly number[];
+};
@@ -1,14 +1,62 @@
 import type {
+  AsrCancelledPayload,
+  AsrErrorPayload,
   AsrFinishedPayload,
+  AsrJobId,
+  AsrJobInfo,
+  AsrModel,
   AsrProgressPayload,
   AsrStartedPayload,
+  AsrTranscriptionOptions,
 } from '$lib/domain/entities/asr';
 import { invoke } from '@tauri-apps/api/core';
 import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 // --- Repository ---
-async function startAsrProcess(filePath: string): Promise<void> {
-  await invoke('start_asr_process', { filePath });
+async function listAsrModels(): Promise<AsrModel[]> {
+  return await invoke<AsrModel[]>('list_asr_models');
+}
+
+/** `modelId` を省略するとレジストリの既定のモデルを使う */
+async function startAsrProcess(
+  filePath: string,
+  modelId?: string,
+  options?: AsrTranscriptionOptions
+): Promise<AsrJobId> {
+  return await invoke<AsrJobId>('start_asr_process', { filePath, modelId, options });
+}
+
+async function enqueueAsrJobs(
+  filePaths: readonly string[],
+  modelId?: string,
+  options?: AsrTranscriptionOptions
+): Promise<AsrJobId[]> {
+  return await invoke<AsrJobId[]>('enqueue_asr_jobs', { filePaths, modelId, options });
+}
+
+async function listAsrJobs(): Promise<AsrJobInfo[]> {
+  return await invoke<AsrJobInfo[]>('list_asr_jobs');
+}
+
+async function moveAsrJob(jobId: AsrJobId, newIndex: number): Promise<void> {
+  await invoke('move_asr_job', { jobId, newIndex });
+}
+
+async function removeAsrJob(jobId: AsrJobId): Promise<void> {
+  await invoke('remove_asr_job', { jobId });
+}
+
+/** 終了済み（完了・失敗・キャンセル）のジョブを一覧から取り除く */
+async function clearFinishedAsrJobs(): Promise<void> {
+  await invoke('clear_finished_asr_jobs');
+}
+
+async function setAsrConcurrency(concurrency: number): Promise<void> {
+  await invoke('set_asr_concurrency', { concurrency });
+}
+
+async function cancelAsrProcess(jobId: AsrJobId): Promise<void> {
+  await invoke('cancel_asr_process', { jobId });
 }
 
 async function onAsrStarted(callback: (payload: AsrStartedPayload) => void): Promise<UnlistenFn> {
@@ -23,14 +71,29 @@ async function onAsrFinished(callback: (payload: AsrFinishedPayload) => void): P
   return await listen<AsrFinishedPayload>('asr-finished', (event) => callback(event.payload));
 }
 
-async function onAsrError(callback: (payload: string) => void): Promise<UnlistenFn> {
-  return await listen<string>('asr-error', (event) => callback(event.payload));
+async function onAsrCancelled(
+  callback: (payload: AsrCancelledPayload) => void
+): Promise<UnlistenFn> {
+  return await listen<AsrCancelledPayload>('asr-cancelled', (event) => callback(event.payload));
+}
+
+async function onAsrError(callback: (payload: AsrErrorPayload) => void): Promise<UnlistenFn> {
+  return await listen<AsrErrorPayload>('asr-error', (event) => callback(event.payload));
 }
 
 export const asrRepository = {
+  listAsrModels,
   startAsrProcess,
+  enqueueAsrJobs,
+  listAsrJobs,
+  moveAsrJob,
+  removeAsrJob,
+  clearFinishedAsrJobs,
+  setAsrConcurrency,
+  cancelAsrProcess,
   onAsrStarted,
   onAsrProgress,
   onAsrFinished,
+  onAsrCancelled,
   onAsrError,
 };
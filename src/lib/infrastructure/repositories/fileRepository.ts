@@ -1,37 +1,39 @@
+import type {
+  AsrProgressPayload,
+  SubtitleOptions,
+  TranscriptExportFormat,
+  TranscriptMetadata,
+} from '$lib/domain/entities/asr';
 import { invoke } from '@tauri-apps/api/core';
-import { BaseDirectory, exists, mkdir, writeFile, writeTextFile } from '@tauri-apps/plugin-fs';
+import { BaseDirectory, exists } from '@tauri-apps/plugin-fs';
 import { trace } from '@tauri-apps/plugin-log';
 
-const MODEL_DIR_NAME = 'models/sherpa-onnx-nemo-parakeet-tdt-0.6b-v2-int8';
-
-async function ensureModelDirExists(): Promise<void> {
-  if (!(await exists(MODEL_DIR_NAME, { baseDir: BaseDirectory.AppLocalData }))) {
-    trace(`Directory does not exist, creating: ${MODEL_DIR_NAME}`);
-    await mkdir(MODEL_DIR_NAME, { recursive: true, baseDir: BaseDirectory.AppLocalData });
-  }
-}
-
 export const fileRepository = {
-  async modelFileExists(fileName: string): Promise<boolean> {
-    return await exists(`${MODEL_DIR_NAME}/${fileName}`, { baseDir: BaseDirectory.AppLocalData });
-  },
-
-  async saveModelFile(fileName: string, content: Uint8Array): Promise<void> {
-    await ensureModelDirExists();
-    const filePath = `${MODEL_DIR_NAME}/${fileName}`;
-    await writeFile(filePath, content, { baseDir: BaseDirectory.AppLocalData });
-    trace(`Saved binary file: ${filePath}`);
-  },
-
-  async saveModelTextFile(fileName: string, content: string): Promise<void> {
-    await ensureModelDirExists();
-    const filePath = `${MODEL_DIR_NAME}/${fileName}`;
-    await writeTextFile(filePath, content, { baseDir: BaseDirectory.AppLocalData });
-    trace(`Saved text file: ${filePath}`);
+  /** アプリのローカルデータディレクトリからの相対パスでファイルの存在を確認する */
+  async appDataFileExists(filePath: string): Promise<boolean> {
+    return await exists(filePath, { baseDir: BaseDirectory.AppLocalData });
   },
 
   async saveTranscriptionFile(filePath: string, content: string): Promise<void> {
     await invoke('save_transcription_file', { filepath: filePath, content });
     trace(`Saved transcription file: ${filePath}`);
   },
+
+  /** 文の一覧を Rust 側で指定の形式に整形して保存する */
+  async exportTranscriptionFile(
+    filePath: string,
+    format: TranscriptExportFormat,
+    sentences: readonly AsrProgressPayload[],
+    metadata?: TranscriptMetadata,
+    options?: SubtitleOptions
+  ): Promise<void> {
+    await invoke('export_transcription_file', {
+      filepath: filePath,
+      format,
+      sentences,
+      metadata,
+      options,
+    });
+    trace(`Exported transcription file as ${format}: ${filePath}`);
+  },
 };
@@ -0,0 +1,74 @@
+import type {
+  LiveErrorPayload,
+  LiveInput,
+  LivePartialPayload,
+  LiveSentencePayload,
+  LiveSessionId,
+  LiveStartedPayload,
+  LiveStoppedPayload,
+} from '$lib/domain/entities/live';
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+// --- Repository ---
+async function listAudioInputDevices(): Promise<string[]> {
+  return await invoke<string[]>('list_audio_input_devices');
+}
+
+/**
+ * `modelId` を省略すると既定のストリーミングモデルを使う。
+ * `recordingPath` を指定すると入力を WAV で録音する。
+ */
+async function startLiveTranscription(
+  input: LiveInput,
+  modelId?: string,
+  recordingPath?: string
+): Promise<LiveSessionId> {
+  return await invoke<LiveSessionId>('start_live_transcription', {
+    input,
+    modelId,
+    recordingPath,
+  });
+}
+
+async function pushLiveAudio(sessionId: LiveSessionId, samples: Float32Array): Promise<void> {
+  await invoke('push_live_audio', { sessionId, samples: Array.from(samples) });
+}
+
+async function stopLiveTranscription(sessionId: LiveSessionId): Promise<void> {
+  await invoke('stop_live_transcription', { sessionId });
+}
+
+async function onLiveStarted(callback: (payload: LiveStartedPayload) => void): Promise<UnlistenFn> {
+  return await listen<LiveStartedPayload>('live-started', (event) => callback(event.payload));
+}
+
+async function onLivePartial(callback: (payload: LivePartialPayload) => void): Promise<UnlistenFn> {
+  return await listen<LivePartialPayload>('live-partial', (event) => callback(event.payload));
+}
+
+async function onLiveSentence(
+  callback: (payload: LiveSentencePayload) => void
+): Promise<UnlistenFn> {
+  return await listen<LiveSentencePayload>('live-sentence', (event) => callback(event.payload));
+}
+
+async function onLiveStopped(callback: (payload: LiveStoppedPayload) => void): Promise<UnlistenFn> {
+  return await listen<LiveStoppedPayload>('live-stopped', (event) => callback(event.payload));
+}
+
+async function onLiveError(callback: (payload: LiveErrorPayload) => void): Promise<UnlistenFn> {
+  return await listen<LiveErrorPayload>('live-error', (event) => callback(event.payload));
+}
+
+export const liveRepository = {
+  listAudioInputDevices,
+  startLiveTranscription,
+  pushLiveAudio,
+  stopLiveTranscription,
+  onLiveStarted,
+  onLivePartial,
+  onLiveSentence,
+  onLiveStopped,
+  onLiveError,
+};
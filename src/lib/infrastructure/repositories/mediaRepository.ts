@@ -0,0 +1,30 @@
+import type { AudioTrackInfo, MediaInfo, WaveformPeaks } from '$lib/domain/entities/media';
+import { invoke } from '@tauri-apps/api/core';
+
+// --- Repository ---
+async function listAudioTracks(filePath: string): Promise<AudioTrackInfo[]> {
+  return await invoke<AudioTrackInfo[]>('list_audio_tracks', { filePath });
+}
+
+async function probeMedia(filePath: string): Promise<MediaInfo> {
+  return await invoke<MediaInfo>('probe_media', { filePath });
+}
+
+/** `transcriptPath` を指定すると、その横に結果をキャッシュして次回から再利用する */
+async function getWaveformPeaks(
+  filePath: string,
+  peaksPerSecond: number,
+  transcriptPath?: string
+): Promise<WaveformPeaks> {
+  return await invoke<WaveformPeaks>('get_waveform_peaks', {
+    filePath,
+    peaksPerSecond,
+    transcriptPath,
+  });
+}
+
+export const mediaRepository = {
+  listAudioTracks,
+  probeMedia,
+  getWaveformPeaks,
+};
@@ -1,34 +1,26 @@
+import type { ModelFileReport } from '$lib/domain/entities/asr';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
-import { fetch } from '@tauri-apps/plugin-http';
 import { trace } from '@tauri-apps/plugin-log';
 
-const MODEL_BASE_URL =
-  'https://huggingface.co/csukuangfj/sherpa-onnx-nemo-parakeet-tdt-0.6b-v2-int8/resolve/main';
-
 export const modelDownloadRepository = {
-  async downloadFile(fileName: string): Promise<Uint8Array> {
-    const url = `${MODEL_BASE_URL}/${fileName}`;
-    trace(`Downloading ${url}`);
-
-    const response = await fetch(url, {
-      method: 'GET',
-    });
-
-    if (!response.ok) {
-      throw new Error(`Failed to download ${fileName}: ${response.status}`);
-    }
-
-    const content = await response.arrayBuffer();
-    return new Uint8Array(content);
+  /**
+   * モデルのファイルを記録済みの SHA-256 と照合する。壊れたファイルは Rust 側で削除される。
+   */
+  async verifyModelFiles(modelId: string): Promise<ModelFileReport[]> {
+    return await invoke<ModelFileReport[]>('verify_models', { modelId });
   },
 
-  async downloadFileStream(
-    fileName: string,
+  /**
+   * 任意の URL をアプリのローカルデータディレクトリ配下の `filePath` へストリーミングダウンロードする。
+   * 配布元の SHA-256 と照合できなかった場合は `unverified` を返す。
+   */
+  async downloadUrlStream(
+    url: string,
+    filePath: string,
     onProgress?: (progress: number) => void
-  ): Promise<void> {
-    const url = `${MODEL_BASE_URL}/${fileName}`;
-    const filePath = `models/sherpa-onnx-nemo-parakeet-tdt-0.6b-v2-int8/${fileName}`;
+  ): Promise<'ok' | 'unverified'> {
+    const fileName = filePath.split('/').pop() ?? filePath;
 
     trace(`Streaming download ${url} to ${filePath}`);
 
@@ -44,10 +36,11 @@ export const modelDownloadRepository = {
     }
 
     try {
-      await invoke('download_model_file_stream', {
+      const result = await invoke<{ status: 'ok' | 'unverified' }>('download_model_file_stream', {
         url,
         filePath,
       });
+      return result.status;
     } finally {
       if (unlisten) {
         unlisten();
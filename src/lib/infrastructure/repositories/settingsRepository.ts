@@ -0,0 +1,51 @@
+import type { ChunkOptions, DecodingOptions, Hotword } from '$lib/domain/entities/asr';
+import { invoke } from '@tauri-apps/api/core';
+import { LazyStore } from '@tauri-apps/plugin-store';
+
+// アプリの設定はアプリのデータディレクトリの `settings.json` に保存する
+const store = new LazyStore('settings.json');
+
+const HOTWORDS_KEY = 'hotwords';
+
+// --- Repository ---
+async function getHotwords(): Promise<Hotword[]> {
+  return (await store.get<Hotword[]>(HOTWORDS_KEY)) ?? [];
+}
+
+async function setHotwords(hotwords: readonly Hotword[]): Promise<void> {
+  await store.set(HOTWORDS_KEY, hotwords);
+  await store.save();
+}
+
+// ホットワードの文法は CLI のホットワードファイルと共有するため、解釈はバックエンドに任せる
+async function parseHotwords(text: string): Promise<Hotword[]> {
+  return await invoke<Hotword[]>('parse_hotwords', { text });
+}
+
+// デコード設定はバックエンドが文字起こしのたびに読むので、検証も含めてバックエンドに任せる
+async function getDecodingOptions(): Promise<DecodingOptions> {
+  return await invoke<DecodingOptions>('get_decoding_options');
+}
+
+async function setDecodingOptions(options: DecodingOptions): Promise<void> {
+  await invoke('set_decoding_options', { options });
+}
+
+/** `modelId` を省略すると既定のモデルの設定を読み書きする */
+async function getChunkOptions(modelId?: string): Promise<ChunkOptions> {
+  return await invoke<ChunkOptions>('get_chunk_options', { modelId });
+}
+
+async function setChunkOptions(options: ChunkOptions, modelId?: string): Promise<void> {
+  await invoke('set_chunk_options', { modelId, options });
+}
+
+export const settingsRepository = {
+  getHotwords,
+  setHotwords,
+  parseHotwords,
+  getDecodingOptions,
+  setDecodingOptions,
+  getChunkOptions,
+  setChunkOptions,
+};
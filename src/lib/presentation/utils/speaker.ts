@@ -0,0 +1,8 @@
+/**
+ * 話者 ID を 1 始まりのラベルにフォーマットします（Rust 側の `format_speaker` と同じ形式）。
+ * @param speaker - 0 始まりの話者 ID
+ * @returns フォーマットされた文字列
+ */
+export function formatSpeaker(speaker: number): string {
+  return `Speaker ${speaker + 1}`;
+}